@@ -1,7 +1,7 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 extern crate raytracer;
-use raytracer::vec3::*;
+use raytracer::{bvh::*, hittable::*, ray::Ray, sampler::*, scenes::*, vec3::*};
 
 // For Vec3::random_on_unit_sphere()
 
@@ -43,12 +43,46 @@ fn random_in_by_rejection() -> Vec3 {
     }
 }
 
+// For BVH traversal, closest hits of a batch of camera rays
+
+fn camera_rays(scene: &Scene, count: usize) -> Vec<Ray> {
+    (0..count)
+        .map(|_| scene.camera.get_ray(random(), random(), &mut Independent))
+        .collect()
+}
+
+fn trace(world: &dyn Hittable, rays: &[Ray]) -> usize {
+    rays.iter()
+        .filter(|ray| world.hit(ray, 0.001, F::INFINITY).is_some())
+        .count()
+}
+
+fn bvh_benchmark(c: &mut Criterion, name: &str, scene: Scene) {
+    let world = &scene.world;
+    let rays = camera_rays(&scene, 1024);
+
+    let midpoint = BVH::new(world, 0, world.len(), 0.0, 1.0);
+    let sah = BVH::sah(world, SahOptions::default(), 0.0, 1.0);
+    let linear = LinearBVH::new(world, SahOptions::default(), 0.0, 1.0);
+
+    let mut group = c.benchmark_group(name);
+    group.bench_function("midpoint", |b| {
+        b.iter(|| trace(&midpoint, black_box(&rays)))
+    });
+    group.bench_function("sah", |b| b.iter(|| trace(&sah, black_box(&rays))));
+    group.bench_function("linear", |b| b.iter(|| trace(&linear, black_box(&rays))));
+    group.finish();
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("on_gaussian", |b| b.iter(|| random_on_by_gaussian()));
-    c.bench_function("on_rejection", |b| b.iter(|| random_on_by_rejection()));
+    c.bench_function("on_gaussian", |b| b.iter(random_on_by_gaussian));
+    c.bench_function("on_rejection", |b| b.iter(random_on_by_rejection));
 
-    c.bench_function("in_gaussian", |b| b.iter(|| random_in_by_gaussian()));
-    c.bench_function("in_rejection", |b| b.iter(|| random_in_by_rejection()));
+    c.bench_function("in_gaussian", |b| b.iter(random_in_by_gaussian));
+    c.bench_function("in_rejection", |b| b.iter(random_in_by_rejection));
+
+    bvh_benchmark(c, "random_scene", _random_scene(1.0));
+    bvh_benchmark(c, "final_scene", _final_scene(1.0));
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -57,3 +91,7 @@ criterion_main!(benches);
 // Type - On, In
 // Gaussian ~ 68 ns, 63 ns
 // Rejection ~ 104 ns, 46 ns - curse of dimensionality
+
+// BVH, 1024 camera rays - midpoint, sah, linear
+// random_scene ~ 3.09 ms, 1.23 ms, 0.58 ms
+// final_scene ~ 0.83 ms, 0.77 ms, 0.57 ms - inner BVHs are linear in all three
@@ -0,0 +1,82 @@
+// Compares the midpoint and SAH BVH builders on the object sets of the built-in scenes
+// cargo run --release --example bvh_stats
+
+use std::{sync::Arc, time::Instant};
+
+use raytracer::{aarect::*, bvh::*, hittable::*, material::*, sphere::*, vec3::*};
+
+fn ground_boxes(material: &Arc<M>) -> HittableList {
+    let mut boxes = HittableList::new();
+
+    for i in 0..20 {
+        for k in 0..20 {
+            let min = Point3::new(-1000.0 + i as F * 100.0, 0.0, -1000.0 + k as F * 100.0);
+            let max = min + Vec3::new(100.0, random_range(1.0, 101.0), 100.0);
+            boxes.add(Arc::new(AABox::new(min, max, Arc::clone(material))));
+        }
+    }
+
+    boxes
+}
+
+fn sphere_cloud(material: &Arc<M>) -> HittableList {
+    let mut spheres = HittableList::new();
+
+    for _ in 0..1000 {
+        spheres.add(Arc::new(Sphere::new(
+            Point3::random_vector(0.0, 165.0),
+            10.0,
+            Arc::clone(material),
+        )));
+    }
+
+    spheres
+}
+
+// Small spheres on a grid with one huge ground sphere, as in the random scene
+fn sphere_field(material: &Arc<M>) -> HittableList {
+    let mut spheres = HittableList::new();
+    spheres.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::clone(material),
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let centre = Point3::new(a as F + 0.9 * random(), 0.2, b as F + 0.9 * random());
+            spheres.add(Arc::new(Sphere::new(centre, 0.2, Arc::clone(material))));
+        }
+    }
+
+    spheres
+}
+
+fn main() {
+    let material: Arc<M> = Arc::new(Lambertian::rgb(0.5, 0.5, 0.5));
+    let sets = with_seed(0, || {
+        [
+            ("ground boxes", ground_boxes(&material)),
+            ("sphere cloud", sphere_cloud(&material)),
+            ("sphere field", sphere_field(&material)),
+        ]
+    });
+
+    for (name, objects) in sets.iter() {
+        let start = Instant::now();
+        let midpoint = BVH::new(objects, 0, objects.len(), 0.0, 1.0);
+        let midpoint_time = start.elapsed();
+
+        let start = Instant::now();
+        let sah = BVH::sah(objects, SahOptions::default(), 0.0, 1.0);
+        let sah_time = start.elapsed();
+
+        println!("== {} ({} objects)\n", name, objects.len());
+        println!(
+            "midpoint, built in {:?}\n{}\n",
+            midpoint_time,
+            midpoint.stats()
+        );
+        println!("sah, built in {:?}\n{}\n", sah_time, sah.stats());
+    }
+}
@@ -21,6 +21,23 @@ impl AABB {
         self.max
     }
 
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> F {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    // Grown by `distance` on every side
+    pub fn expand(&self, distance: F) -> Self {
+        Self {
+            min: self.min - distance,
+            max: self.max + distance,
+        }
+    }
+
     pub fn surrounding_box(box0: Self, box1: Self) -> Self {
         let min_point = Point3::new(
             box0.min().x().min(box1.min().x()),
@@ -51,6 +68,33 @@ impl AABB {
         }
     }
 
+    // Slab test with the reciprocal direction and its signs precomputed per ray
+    pub fn hit_inverse(
+        &self,
+        origin: Point3,
+        inv_direction: Vec3,
+        negative: [bool; 3],
+        mut t_min: F,
+        mut t_max: F,
+    ) -> bool {
+        for (i, &is_negative) in negative.iter().enumerate() {
+            let (near, far) = if is_negative {
+                (self.max.ix(i), self.min.ix(i))
+            } else {
+                (self.min.ix(i), self.max.ix(i))
+            };
+
+            t_min = t_min.max((near - origin.ix(i)) * inv_direction.ix(i));
+            t_max = t_max.min((far - origin.ix(i)) * inv_direction.ix(i));
+
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+
     pub fn hit(&self, ray: &Ray, mut t_min: F, mut t_max: F) -> bool {
         for i in 0..3 {
             let inv = 1.0 / ray.direction().ix(i);
@@ -74,6 +118,6 @@ impl AABB {
             }
         }
 
-        return true;
+        true
     }
 }
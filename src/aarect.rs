@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{aabb::*, hittable::*, ray::*, texture::*, vec3::*};
+use crate::{aabb::*, hittable::*, ray::*, sampler::*, texture::*, vec3::*};
 
 pub enum Plane {
     XY,
@@ -50,13 +50,13 @@ impl Hittable for AARect {
         let t = (self.k - ray.origin().ix(k_axis)) / ray.direction().ix(k_axis);
 
         if t < t_min || t > t_max {
-            return None;
+            None
         } else {
             let a = ray.origin().ix(a_axis) + t * ray.direction().ix(a_axis);
             let b = ray.origin().ix(b_axis) + t * ray.direction().ix(b_axis);
 
             if a < self.a0 || a > self.a1 || b < self.b0 || b > self.b1 {
-                return None;
+                None
             } else {
                 let p = ray.at(t);
 
@@ -71,7 +71,7 @@ impl Hittable for AARect {
                     HitRecord::new(p, outward_normal, t, tp, true, Arc::clone(&self.material));
                 hit_record.set_face_normal(ray, outward_normal);
 
-                return Some(hit_record);
+                Some(hit_record)
             }
         }
     }
@@ -87,6 +87,37 @@ impl Hittable for AARect {
 
         Some(AABB::new(min, max))
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        if let Some(hit_record) = self.hit(&Ray::new(origin, direction, 0.0), 0.001, F::INFINITY) {
+            let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+            let distance_squared = hit_record.t() * hit_record.t() * direction.length_squared();
+            let cosine = dot(&direction, &hit_record.n()).abs() / direction.length();
+
+            distance_squared / (cosine * area)
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.next_2d();
+        let mut point = Point3::zero();
+        point.set_all(
+            self.plane.axes(),
+            (
+                self.a0 + u * (self.a1 - self.a0),
+                self.b0 + v * (self.b1 - self.b0),
+                self.k,
+            ),
+        );
+
+        point - origin
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        visit(&self.material)
+    }
 }
 
 pub struct AABox {
@@ -166,4 +197,16 @@ impl Hittable for AABox {
     fn bounding_box(&self, _time0: F, _time1: F) -> Option<AABB> {
         Some(AABB::new(self.min, self.max))
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        self.sides.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.sides.random(origin, sampler)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.sides.materials(visit)
+    }
 }
@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{hittable::*, ray::*, scenes::Scene, vec3::*};
+
+// Arbitrary output variables, images rendered alongside the beauty pass.
+// Geometry comes from the first surface along the camera ray, seeing through
+// participating media, and is zero where the ray hits nothing. Misses are
+// averaged in like any other sample, pulling silhouettes towards zero, so depth
+// and position need the ObjectId layer, nonzero wherever a sample hit, to mask
+// the pixels the scene never covers.
+// Emission, direct and indirect split the beauty image by the number of
+// bounces before the light was found, so they add up to it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Aov {
+    Albedo,
+    // Outward facing, in world space
+    Normal,
+    // Distance from the camera
+    Depth,
+    Position,
+    // One more than the index of the object in the scene's top level list, so
+    // a group or BVH counts as a single object
+    ObjectId,
+    // One more than the index of the material in order of first appearance
+    MaterialId,
+    // Light sources and background seen directly
+    Emission,
+    // Light reflected once
+    Direct,
+    // Light reflected more than once
+    Indirect,
+    // One layer per light, of the light that each path found on it
+    Lights,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 10] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Emission,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Lights,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Emission => "emission",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Lights => "lights",
+        }
+    }
+
+    // Whether the value is an identifier rather than something to average
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+    // Whether the value is light, and tone mapped like the beauty image
+    pub fn is_light(&self) -> bool {
+        matches!(
+            self,
+            Aov::Emission | Aov::Direct | Aov::Indirect | Aov::Lights
+        )
+    }
+
+    fn bit(&self) -> u16 {
+        1 << *self as u16
+    }
+}
+
+impl Display for Aov {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Aov::ALL
+            .iter()
+            .find(|aov| aov.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = Aov::ALL.iter().map(|aov| aov.name()).collect();
+                format!(
+                    "unknown AOV '{}', expected one of {} or all",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// The AOVs a render produces
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AovSet(u16);
+
+impl AovSet {
+    pub fn all() -> Self {
+        Aov::ALL
+            .iter()
+            .fold(Self::default(), |set, aov| set.with(*aov))
+    }
+
+    pub fn with(self, aov: Aov) -> Self {
+        Self(self.0 | aov.bit())
+    }
+
+    pub fn union(self, other: AovSet) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn contains(&self, aov: Aov) -> bool {
+        self.0 & aov.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Aov> + '_ {
+        Aov::ALL
+            .iter()
+            .copied()
+            .filter(move |aov| self.contains(*aov))
+    }
+
+    // The images making up the AOVs, `Lights` giving one per light
+    pub fn layers(&self, light_count: usize) -> Vec<Layer> {
+        self.iter()
+            .flat_map(|aov| {
+                let count = if aov == Aov::Lights { light_count } else { 1 };
+                (0..count).map(move |light| Layer { aov, light })
+            })
+            .collect()
+    }
+}
+
+impl Display for AovSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = self.iter().map(|aov| aov.name()).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+// Comma separated AOV names, or all
+impl FromStr for AovSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(AovSet::default(), |set, name| match name {
+                "all" => Ok(AovSet::all()),
+                _ => Ok(set.with(name.parse()?)),
+            })
+    }
+}
+
+// A colour for telling IDs apart, black for 0
+pub fn id_colour(id: u32) -> Colour {
+    if id == 0 {
+        return Colour::zero();
+    }
+
+    // Integer hash by Chris Wellons
+    let mut h = id;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7FEB_352D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846C_A68B);
+    h ^= h >> 16;
+
+    let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xFF) as F / 255.0;
+    Colour::new(channel(0), channel(8), channel(16))
+}
+
+// One image of an AOV
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Layer {
+    pub aov: Aov,
+    // Index into the scene's lights for `Aov::Lights`, otherwise 0
+    pub light: usize,
+}
+
+impl Layer {
+    pub fn name(&self) -> String {
+        match self.aov {
+            Aov::Lights => format!("light_{}", self.light),
+            aov => aov.name().to_string(),
+        }
+    }
+}
+
+// The beauty estimate of one sample split by where its light came from
+pub struct LightSplit<'a> {
+    // Index into the scene's lights of each top level object, by object ID - 1
+    light_of_object: &'a [Option<usize>],
+    pub emission: Colour,
+    pub direct: Colour,
+    pub indirect: Colour,
+    pub lights: Vec<Colour>,
+}
+
+impl<'a> LightSplit<'a> {
+    pub fn new(light_of_object: &'a [Option<usize>], light_count: usize) -> Self {
+        Self {
+            light_of_object,
+            emission: Colour::zero(),
+            direct: Colour::zero(),
+            indirect: Colour::zero(),
+            lights: vec![Colour::zero(); light_count],
+        }
+    }
+
+    // Light found after `bounces` bounces on the object `object_id`, 0 for the background
+    pub fn add(&mut self, bounces: u32, object_id: u32, radiance: Colour) {
+        match bounces {
+            0 => self.emission = self.emission + radiance,
+            1 => self.direct = self.direct + radiance,
+            _ => self.indirect = self.indirect + radiance,
+        }
+
+        let light = object_id
+            .checked_sub(1)
+            .and_then(|index| self.light_of_object.get(index as usize).copied().flatten());
+        if let Some(light) = light {
+            self.lights[light] = self.lights[light] + radiance;
+        }
+    }
+}
+
+// Everything about a scene needed to fill in the AOVs of a render
+pub struct AovContext {
+    layers: Vec<Layer>,
+    light_of_object: Vec<Option<usize>>,
+    light_count: usize,
+    // Keyed by the address of the material
+    material_ids: HashMap<usize, u32>,
+}
+
+// Media scatter at random depths, which would leave their noise in the AOVs
+fn first_surface(ray: &Ray, world: &dyn Hittable) -> Option<HitRecord> {
+    const MAX_MEDIUM_HITS: usize = 16;
+
+    let mut t_min = 0.001;
+    for _ in 0..MAX_MEDIUM_HITS {
+        let hit_record = world.hit(ray, t_min, F::INFINITY)?;
+        if !hit_record.material().is_volume() {
+            return Some(hit_record);
+        }
+        t_min = hit_record.t();
+    }
+
+    None
+}
+
+fn material_key(material: &Arc<M>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
+
+impl AovContext {
+    pub fn new(scene: &Scene, layers: Vec<Layer>) -> Self {
+        let light_of_object = (0..scene.world.len())
+            .map(|object| {
+                let object = scene.world.ix(object);
+                (0..scene.lights.len()).find(|&light| Arc::ptr_eq(&scene.lights.ix(light), &object))
+            })
+            .collect();
+
+        let mut material_ids = HashMap::new();
+        scene.world.materials(&mut |material| {
+            let id = material_ids.len() as u32 + 1;
+            material_ids.entry(material_key(material)).or_insert(id);
+        });
+
+        Self {
+            layers,
+            light_of_object,
+            light_count: scene.lights.len(),
+            material_ids,
+        }
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn light_split(&self) -> LightSplit<'_> {
+        LightSplit::new(&self.light_of_object, self.light_count)
+    }
+
+    // Values of the layers for a camera ray whose radiance was split into `split`
+    pub fn values<'a>(
+        &'a self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        split: &'a LightSplit,
+    ) -> impl Iterator<Item = Colour> + 'a {
+        let geometry = first_surface(ray, world).map(|hit_record| {
+            let material_id = self
+                .material_ids
+                .get(&material_key(&hit_record.material()))
+                .copied()
+                .unwrap_or(0);
+            (hit_record, material_id, ray.direction().length())
+        });
+
+        self.layers.iter().map(move |layer| match layer.aov {
+            Aov::Emission => split.emission,
+            Aov::Direct => split.direct,
+            Aov::Indirect => split.indirect,
+            Aov::Lights => split.lights[layer.light],
+            aov => match &geometry {
+                Some((hit_record, material_id, length)) => match aov {
+                    Aov::Albedo => hit_record.material().albedo(hit_record),
+                    Aov::Normal => hit_record.outward_n().unit(),
+                    Aov::Depth => Colour::one() * hit_record.t() * *length,
+                    Aov::Position => hit_record.p(),
+                    Aov::ObjectId => Colour::one() * hit_record.object_id() as F,
+                    _ => Colour::one() * *material_id as F,
+                },
+                None => Colour::zero(),
+            },
+        })
+    }
+}
@@ -2,53 +2,766 @@ extern crate image;
 extern crate rand;
 extern crate rayon;
 
-use image::io::Reader as ImageReader;
-use rayon::prelude::*;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use image::ImageFormat;
 
-use std::{error::Error, fs, io::Write};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
 
-use raytracer::{ray::*, scenes::*, vec3::*};
+use raytracer::{
+    aov::*, checkpoint::*, denoise::*, film::*, filter::*, integrator::*, output::*, render::*,
+    sampler::*, scene_file::*, scenes::*, timeline::*, tonemap::*, vec3::*,
+};
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // Camera, World
-    let (camera, world) = _final_scene();
+#[derive(Parser)]
+#[command(name = "raytracer", about = "Render a built-in scene or a scene file")]
+struct Args {
+    /// Built-in scene name or path to a .toml scene file
+    #[arg(short, long, default_value = "final_scene")]
+    scene: String,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    height: Option<u32>,
+
+    /// Aspect ratio as a number or W:H, e.g. 1.5 or 3:2
+    #[arg(long, value_parser = parse_aspect_ratio)]
+    aspect: Option<F>,
+
+    /// Samples per pixel
+    #[arg(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..))]
+    samples: Option<u32>,
+
+    /// Maximum number of ray bounces
+    #[arg(short = 'd', long)]
+    max_depth: Option<u32>,
+
+    /// Maximum number of diffuse bounces
+    #[arg(long)]
+    diffuse_depth: Option<u32>,
+
+    /// Maximum number of specular reflections
+    #[arg(long)]
+    specular_depth: Option<u32>,
+
+    /// Maximum number of refractions through surfaces
+    #[arg(long)]
+    transmission_depth: Option<u32>,
+
+    /// Maximum number of scattering events in media
+    #[arg(long)]
+    volume_depth: Option<u32>,
+
+    /// Bounces before Russian roulette may end low contribution paths
+    #[arg(long)]
+    russian_roulette_depth: Option<u32>,
+
+    /// Background colour as R,G,B
+    #[arg(short, long, value_parser = parse_colour)]
+    background: Option<Colour>,
+
+    /// Output image path, or directory of frames when animating [default: image.png, frames]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Output format (png, ppm, hdr, ...), taken from the output extension if not given
+    #[arg(short, long)]
+    format: Option<String>,
+
+    /// Bits per channel, 16 is only supported for png
+    #[arg(long, value_parser = parse_bit_depth)]
+    bit_depth: Option<BitDepth>,
+
+    /// Tone map operator: clamp, reinhard, extended_reinhard, aces or hable
+    #[arg(short, long)]
+    tone_map: Option<ToneMapOperator>,
+
+    /// Exposure compensation in stops
+    #[arg(short, long, allow_hyphen_values = true)]
+    exposure: Option<F>,
+
+    /// Radiance mapped to white by extended_reinhard
+    #[arg(long)]
+    white_point: Option<F>,
+
+    /// Illuminant colour to balance to neutral, as R,G,B
+    #[arg(long, value_parser = parse_colour)]
+    white_balance: Option<Colour>,
+
+    /// Sampler: independent, stratified, halton, sobol or blue_noise
+    #[arg(long)]
+    sampler: Option<SamplerKind>,
+
+    /// Reconstruction filter each sample is spread over the pixels around it with: box,
+    /// tent, gaussian, mitchell or lanczos, the last two sharper but prone to ringing
+    #[arg(long)]
+    filter: Option<FilterKind>,
+
+    /// Radius of the filter in pixels [default: 0.5 box, 1 tent, 1.5 gaussian, 2 mitchell,
+    /// 3 lanczos]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_radius)]
+    filter_radius: Option<F>,
+
+    /// Integrator: path, or a debug view of the first hit: normals, uv, depth, albedo, ao
+    /// (ambient occlusion) or bvh_cost
+    #[arg(long)]
+    integrator: Option<IntegratorKind>,
+
+    /// Distance within which the ao integrator counts surfaces as occluding
+    #[arg(long)]
+    occlusion_radius: Option<F>,
+
+    /// Also write these AOVs, comma separated, or all: albedo, normal, depth, position,
+    /// object_id, material_id, emission, direct, indirect or lights (one image per light);
+    /// each goes next to the output, image.png has image.albedo.png
+    #[arg(long, value_name = "LIST")]
+    aov: Option<AovSet>,
+
+    /// Denoise the finished image, guided by its albedo, normal and depth; STRENGTH scales
+    /// how much noise is smoothed away [default: 1]
+    #[arg(long, value_name = "STRENGTH", num_args = 0..=1, default_missing_value = "1",
+        value_parser = parse_strength)]
+    denoise: Option<F>,
+
+    /// When denoising also write the image as rendered, image.png has image.raw.png
+    #[arg(long)]
+    keep_raw: bool,
+
+    /// Sample adaptively, stopping pixels whose relative error drops below THRESHOLD
+    /// [default: 0.02]; --samples becomes the maximum per pixel
+    #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "0.02",
+        value_parser = parse_threshold)]
+    adaptive: Option<F>,
+
+    /// Samples every pixel takes before adaptive sampling may stop it, with --adaptive or a
+    /// scene file's adaptive_threshold [default: 16]
+    #[arg(long)]
+    min_samples: Option<u32>,
+
+    /// Also write the number of samples taken per pixel as a greyscale image
+    #[arg(long, value_name = "PATH")]
+    sample_map: Option<PathBuf>,
+
+    /// Edge length of the square tiles rendered by each thread
+    #[arg(long)]
+    tile_size: Option<u32>,
+
+    /// Write the output image after every progressive pass, not only at the end
+    #[arg(short, long)]
+    preview: bool,
+
+    /// Number of render threads, defaults to one per core
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Seed for the samples; the same seed gives the same image on any number of threads
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Seed for the random choices made while building the scene, defaults to --seed
+    #[arg(long)]
+    scene_seed: Option<u64>,
+
+    /// Save the render to this checkpoint file after every pass and every --checkpoint-interval
+    #[arg(long, conflicts_with_all = ["animate", "frames"])]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints within a pass
+    #[arg(long, default_value_t = 60)]
+    checkpoint_interval: u64,
+
+    /// Continue the render saved in this checkpoint, saving progress back to it; a finished
+    /// render can be continued to a higher --samples
+    #[arg(long, conflicts_with_all = ["animate", "frames"])]
+    resume: Option<PathBuf>,
+
+    /// Render the scene's [animation] as a numbered sequence, frame_0001.png, ...
+    #[arg(short, long)]
+    animate: bool,
+
+    /// Frames to render as FIRST:LAST instead of the whole animation, implies --animate
+    #[arg(long, value_parser = parse_frames)]
+    frames: Option<(u32, u32)>,
+
+    /// Render frames again even when their image already exists
+    #[arg(long)]
+    overwrite: bool,
+}
+
+fn parse_frames(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid frame range '{}', expected FIRST:LAST", s);
+    let (first, last) = s.split_once(':').ok_or_else(invalid)?;
+    let first: u32 = first.trim().parse().map_err(|_| invalid())?;
+    let last: u32 = last.trim().parse().map_err(|_| invalid())?;
+
+    if first <= last {
+        Ok((first, last))
+    } else {
+        Err(format!("last frame comes before first in '{}'", s))
+    }
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<F, String> {
+    let ratio = if let Some((w, h)) = s.split_once(':') {
+        let w: F = w
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid width '{}'", w))?;
+        let h: F = h
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid height '{}'", h))?;
+        w / h
+    } else {
+        s.parse()
+            .map_err(|_| format!("invalid aspect ratio '{}'", s))?
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(format!("aspect ratio must be positive, got '{}'", s))
+    }
+}
+
+fn parse_threshold(s: &str) -> Result<F, String> {
+    match s.parse::<F>() {
+        Ok(threshold) if threshold > 0.0 && threshold.is_finite() => Ok(threshold),
+        _ => Err(format!(
+            "invalid threshold '{}', expected a positive number",
+            s
+        )),
+    }
+}
+
+fn parse_radius(s: &str) -> Result<F, String> {
+    match s.parse::<F>() {
+        Ok(radius) if radius > 0.0 && radius.is_finite() => Ok(radius),
+        _ => Err(format!(
+            "invalid radius '{}', expected a positive number",
+            s
+        )),
+    }
+}
+
+fn parse_strength(s: &str) -> Result<F, String> {
+    match s.parse::<F>() {
+        Ok(strength) if strength >= 0.0 => Ok(strength),
+        _ => Err(format!(
+            "invalid strength '{}', expected a number of at least 0",
+            s
+        )),
+    }
+}
+
+fn parse_bit_depth(s: &str) -> Result<BitDepth, String> {
+    match s {
+        "8" => Ok(BitDepth::Eight),
+        "16" => Ok(BitDepth::Sixteen),
+        _ => Err(format!("unsupported bit depth '{}', expected 8 or 16", s)),
+    }
+}
+
+fn parse_colour(s: &str) -> Result<Colour, String> {
+    let values = s
+        .split(',')
+        .map(|value| value.trim().parse::<F>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid colour '{}', expected R,G,B", s))?;
+
+    match values[..] {
+        [r, g, b] => Ok(Colour::new(r, g, b)),
+        _ => Err(format!("invalid colour '{}', expected R,G,B", s)),
+    }
+}
+
+fn scene_list() -> String {
+    let mut list = String::from("Built-in scenes:\n");
+    for scene in SCENES {
+        list += &format!("  {:<20}{}\n", scene.name, scene.description);
+    }
+
+    list
+}
+
+impl Args {
+    // Command line values take priority over the scene's own settings
+    fn apply(&self, settings: &mut RenderSettings) -> Result<(), Box<dyn Error>> {
+        let aspect_ratio = self.aspect.unwrap_or_else(|| settings.aspect_ratio());
+
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                settings.image_width = width;
+                settings.image_height = height;
+            }
+            (Some(width), None) => {
+                settings.image_width = width;
+                settings.set_aspect_ratio(aspect_ratio);
+            }
+            (None, Some(height)) => {
+                settings.image_height = height;
+                settings.image_width = ((height as F * aspect_ratio).round() as u32).max(1);
+            }
+            (None, None) => settings.set_aspect_ratio(aspect_ratio),
+        }
+
+        if let Some(samples) = self.samples {
+            settings.samples_per_pixel = samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        let limits = &mut settings.depth_limits;
+        for (depth, limit) in [
+            (self.diffuse_depth, &mut limits.diffuse),
+            (self.specular_depth, &mut limits.specular),
+            (self.transmission_depth, &mut limits.transmission),
+            (self.volume_depth, &mut limits.volume),
+        ] {
+            if let Some(depth) = depth {
+                *limit = depth;
+            }
+        }
+        if let Some(depth) = self.russian_roulette_depth {
+            settings.russian_roulette_depth = depth;
+        }
+        if let Some(background) = self.background {
+            settings.background = background;
+        }
+        if let Some(operator) = self.tone_map {
+            settings.tone_map.operator = operator;
+        }
+        if let Some(exposure) = self.exposure {
+            settings.tone_map.exposure = exposure;
+        }
+        if let Some(white_point) = self.white_point {
+            settings.tone_map.white_point = white_point;
+        }
+        if self.white_balance.is_some() {
+            settings.tone_map.white_balance = self.white_balance;
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler;
+        }
+        if let Some(integrator) = self.integrator {
+            settings.integrator = integrator;
+        }
+        if let Some(kind) = self.filter {
+            settings.filter = Filter::new(kind);
+        }
+        if let Some(radius) = self.filter_radius {
+            settings.filter.radius = radius;
+        }
+        if let Some(radius) = self.occlusion_radius {
+            settings.occlusion_radius = radius;
+        }
+        if let Some(aovs) = self.aov {
+            settings.aovs = aovs;
+        }
+        if let Some(strength) = self.denoise {
+            settings.denoise = Some(Denoiser {
+                strength,
+                ..settings.denoise.unwrap_or_default()
+            });
+        }
+        if let Some(tile_size) = self.tile_size {
+            settings.tile_size = tile_size;
+        }
+        if let Some(threshold) = self.adaptive {
+            settings.adaptive = Some(Adaptive {
+                threshold,
+                ..settings.adaptive.unwrap_or_default()
+            });
+        }
+        // Only changes adaptive sampling, rather than turning it on
+        if let Some(min_samples) = self.min_samples {
+            match &mut settings.adaptive {
+                Some(adaptive) => adaptive.min_samples = min_samples,
+                None => {
+                    return Err(
+                        "--min-samples needs --adaptive, or adaptive_threshold in the scene file"
+                            .into(),
+                    )
+                }
+            }
+        }
+        if self.seed.is_some() {
+            settings.seed = self.seed;
+        }
+        if self.scene_seed.is_some() {
+            settings.scene_seed = self.scene_seed;
+        }
+
+        Ok(())
+    }
+
+    fn load_scene(&self) -> Result<(RenderSettings, SceneSource), Box<dyn Error>> {
+        if let Some(builtin) = find_scene(&self.scene) {
+            let mut settings = RenderSettings {
+                background: builtin.background(),
+                ..RenderSettings::default()
+            };
+            self.apply(&mut settings)?;
+            settings.validate()?;
+
+            Ok((settings, SceneSource::Builtin(builtin)))
+        } else if self.scene.ends_with(".toml") || PathBuf::from(&self.scene).is_file() {
+            let scene = SceneFile::load(&self.scene)?;
+            let mut settings = scene.settings()?;
+            self.apply(&mut settings)?;
+            settings.validate()?;
+
+            Ok((settings, SceneSource::File(Box::new(scene))))
+        } else {
+            Err(format!("unknown scene '{}'\n\n{}", self.scene, scene_list()).into())
+        }
+    }
+}
+
+enum SceneSource {
+    Builtin(&'static BuiltinScene),
+    File(Box<SceneFile>),
+}
+
+impl SceneSource {
+    // The same `seed` always builds the same scene
+    fn build(&self, aspect_ratio: F, seed: u64) -> Result<Scene, Box<dyn Error>> {
+        with_seed(seed, || match self {
+            SceneSource::Builtin(builtin) => Ok((builtin.build)(aspect_ratio)),
+            SceneSource::File(scene) => Ok(scene.build(aspect_ratio)?),
+        })
+    }
+}
+
+// Saves the render state after every pass and every `interval` within a pass
+struct Checkpointer<'a> {
+    path: &'a Path,
+    interval: Duration,
+    settings_hash: u64,
+    scene_seed: u64,
+    last_write: Instant,
+}
+
+impl Checkpointer<'_> {
+    fn write(&mut self, state: &RenderState) -> Result<(), CheckpointError> {
+        write_checkpoint(self.path, self.settings_hash, self.scene_seed, state)?;
+        self.last_write = Instant::now();
+        Ok(())
+    }
+}
+
+// The finished image filtered by the settings' denoiser, if they have one
+fn denoise(film: &Film, settings: &RenderSettings) -> Option<Film> {
+    let denoiser = settings.denoise?;
+    eprintln!("\rDenoising");
+    let pixels = denoiser.denoise(film);
+    Some(Film::from_pixels(film.width(), film.height(), &pixels))
+}
+
+// Progressive render continuing from `state`, `preview` is written after every pass
+fn render(
+    settings: &RenderSettings,
+    scene: &Scene,
+    mut state: RenderState,
+    preview: Option<(&Output, &Path)>,
+    mut checkpointer: Option<Checkpointer>,
+) -> Result<Film, Box<dyn Error>> {
+    render_progressive(
+        settings,
+        scene,
+        &mut state,
+        |state, progress| -> Result<(), Box<dyn Error>> {
+            match progress {
+                Progress::Tile { done, tiles } => {
+                    eprint!("\rTiles: {} / {}", done, tiles);
+                    if let Some(checkpointer) = &mut checkpointer {
+                        if checkpointer.last_write.elapsed() >= checkpointer.interval {
+                            checkpointer.write(state)?;
+                        }
+                    }
+                }
+                Progress::Pass(pass) => {
+                    let film = &state.film;
+                    eprintln!(
+                        "\rPass {}: {} / {} spp, {:.1}% of pixels sampled",
+                        pass.number,
+                        pass.total_samples,
+                        settings.samples_per_pixel,
+                        100.0 * pass.pixels as F / (film.width() * film.height()) as F
+                    );
+                    if let Some(checkpointer) = &mut checkpointer {
+                        checkpointer.write(state)?;
+                    }
+                    if let Some((output, path)) = preview {
+                        if pass.total_samples < settings.samples_per_pixel {
+                            output.write(&state.film, path, &settings.tone_map)?;
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        },
+    )?;
 
-    // Background
-    let background = Colour::new(0.0, 0.0, 0.0);
+    Ok(state.film)
+}
 
-    // Create and initialise .ppm file
-    let name = "image".to_string();
-    let ppm_path = name + ".ppm";
-    let mut f = fs::File::create(&ppm_path)?;
+struct Output {
+    format: ImageFormat,
+    bit_depth: BitDepth,
+}
 
-    write!(f, "P3\n{} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT)?;
+impl Output {
+    fn write(&self, film: &Film, path: &Path, tone_map: &ToneMap) -> Result<(), Box<dyn Error>> {
+        write_image(film, path, self.format, self.bit_depth, tone_map)?;
+        Ok(())
+    }
 
-    for j in (0..IMAGE_HEIGHT).rev() {
-        eprint!("\rRemaining: {} / {}", j, IMAGE_HEIGHT);
-        for i in 0..IMAGE_WIDTH {
-            let colour: Colour = (0..SAMPLES_PER_PIXEL)
-                .into_par_iter()
-                .map(|_| {
-                    let u = (i as F + random()) / (IMAGE_WIDTH - 1) as F;
-                    let v = (j as F + random()) / (IMAGE_HEIGHT - 1) as F;
-                    let ray = camera.get_ray(u, v);
-                    let local_colour = ray_colour(&ray, background, &world, MAX_DEPTH);
-                    local_colour
-                })
-                .sum();
-            write!(f, "\n{}", colour.write_colour(SAMPLES_PER_PIXEL))?;
+    // Each AOV layer asked for next to the image at `path`
+    fn write_layers(
+        &self,
+        film: &Film,
+        path: &Path,
+        settings: &RenderSettings,
+    ) -> Result<(), Box<dyn Error>> {
+        for (index, layer) in film.layers().iter().enumerate() {
+            if !settings.aovs.contains(layer.aov) {
+                continue;
+            }
+            let path = layer_path(path, &layer.name());
+            eprintln!("Writing {}", path.display());
+            write_layer(
+                film,
+                index,
+                &path,
+                self.format,
+                self.bit_depth,
+                &settings.tone_map,
+            )?;
         }
+        Ok(())
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let matches = Args::command().after_help(scene_list()).get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
     }
+    let animate = args.animate || args.frames.is_some();
+    let default_output = if animate { "frames" } else { "image.png" };
+    let output_path = args.output.clone().unwrap_or_else(|| default_output.into());
 
-    // Convert to .png
-    eprintln!("\rConverting to .png");
+    let format = match &args.format {
+        Some(format) => ImageFormat::from_extension(format)
+            .ok_or_else(|| format!("unknown output format '{}'", format))?,
+        None if animate => ImageFormat::Png,
+        None => ImageFormat::from_path(&output_path)?,
+    };
+    let bit_depth = args.bit_depth.unwrap_or(BitDepth::Eight);
+    if args.bit_depth.is_some() && !supports_bit_depth(format, bit_depth) {
+        return Err(format!(
+            "{:?} output does not support {:?} bit depth",
+            format, bit_depth
+        )
+        .into());
+    }
+    let output = Output { format, bit_depth };
+
+    // Camera, World
+    let (mut settings, source) = args.load_scene()?;
+
+    if animate {
+        return render_animation(&args, &settings, &source, &output, &output_path);
+    }
 
-    let img = ImageReader::open(&ppm_path)?.decode()?;
+    // Built-in scenes are identified by name, scene files by their text and
+    // the files they load
+    let scene_id = match &source {
+        SceneSource::Builtin(builtin) => builtin.name.as_bytes().to_vec(),
+        SceneSource::File(scene) => {
+            let mut id = fs::read(&args.scene)?;
+            for path in scene.files()? {
+                let contents =
+                    fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                id.extend((contents.len() as u64).to_le_bytes());
+                id.extend(contents);
+            }
+            id
+        }
+    };
+    let settings_hash = settings_hash(&settings, &scene_id);
+
+    let resumed = match &args.resume {
+        Some(path) => {
+            let checkpoint = read_checkpoint(path, &settings, &scene_id)?;
+            let seed = checkpoint.state.seed;
+            if settings.seed.is_some_and(|s| s != seed) {
+                return Err(format!("--seed differs from the checkpoint's seed {}", seed).into());
+            }
+            let scene_seed = checkpoint.scene_seed;
+            if settings.scene_seed.is_some_and(|s| s != scene_seed) {
+                return Err(format!(
+                    "scene seed differs from the checkpoint's scene seed {}",
+                    scene_seed
+                )
+                .into());
+            }
+            eprintln!(
+                "Resuming {} at {} spp",
+                path.display(),
+                checkpoint.state.samples()
+            );
+            settings.seed = Some(seed);
+            settings.scene_seed = Some(scene_seed);
+            Some((path, checkpoint))
+        }
+        None => None,
+    };
 
-    let png_path = ppm_path.replace(".ppm", ".png");
-    img.save_with_format(png_path, image::ImageFormat::Png)?;
+    // Resolved up front so that a checkpoint can build the same scene again
+    let scene_seed = settings.resolve_scene_seed();
+    let scene = source.build(settings.aspect_ratio(), scene_seed)?;
+    let state = match resumed {
+        Some((path, checkpoint)) => {
+            checkpoint.check_lights(path, &settings, scene.lights.len())?;
+            checkpoint.state
+        }
+        None => {
+            RenderState::with_layers(&settings, settings.film_aovs().layers(scene.lights.len()))
+        }
+    };
+    let preview = if args.preview {
+        Some((&output, output_path.as_path()))
+    } else {
+        None
+    };
+    let checkpoint_path = args.checkpoint.as_ref().or(args.resume.as_ref());
+    let checkpointer = checkpoint_path.map(|path| Checkpointer {
+        path,
+        interval: Duration::from_secs(args.checkpoint_interval),
+        settings_hash,
+        scene_seed,
+        last_write: Instant::now(),
+    });
+    let film = render(&settings, &scene, state, preview, checkpointer)?;
+
+    if settings.adaptive.is_some() {
+        let samples: u64 = (0..film.height())
+            .flat_map(|y| (0..film.width()).map(move |x| (x, y)))
+            .map(|(x, y)| film.samples(x, y) as u64)
+            .sum();
+        eprintln!(
+            "Average {:.1} spp",
+            samples as F / (film.width() * film.height()) as F
+        );
+    }
+
+    let denoised = denoise(&film, &settings);
+    eprintln!("\rWriting {}", output_path.display());
+    output.write(
+        denoised.as_ref().unwrap_or(&film),
+        &output_path,
+        &settings.tone_map,
+    )?;
+    if denoised.is_some() && args.keep_raw {
+        let raw_path = layer_path(&output_path, "raw");
+        eprintln!("Writing {}", raw_path.display());
+        output.write(&film, &raw_path, &settings.tone_map)?;
+    }
+    output.write_layers(&film, &output_path, &settings)?;
+    if let Some(path) = &args.sample_map {
+        eprintln!("Writing {}", path.display());
+        write_sample_map(&film, path)?;
+    }
 
     eprintln!("\rDone.");
 
     Ok(())
 }
+
+// Frames already on disk are skipped, each frame is written under a temporary
+// name first so an interrupted render never leaves a partial image behind
+fn render_animation(
+    args: &Args,
+    settings: &RenderSettings,
+    source: &SceneSource,
+    output: &Output,
+    directory: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let scene_file = match source {
+        SceneSource::File(scene) => scene,
+        SceneSource::Builtin(builtin) => {
+            return Err(format!("built-in scene '{}' has no animation", builtin.name).into())
+        }
+    };
+    let mut timeline = scene_file
+        .timeline()?
+        .ok_or_else(|| format!("scene '{}' has no [animation] section", args.scene))?;
+    if let Some((first, last)) = args.frames {
+        timeline.first_frame = first;
+        timeline.last_frame = last;
+    }
+
+    fs::create_dir_all(directory)?;
+    // Shared by every frame so that random layouts hold still
+    let scene_seed = settings.resolve_scene_seed();
+    let extension = output.format.extensions_str()[0];
+    let frame_count = timeline.frames().count();
+
+    for (n, frame) in timeline.frames().enumerate() {
+        let path = directory.join(frame_file_name(frame, extension));
+        if path.exists() && !args.overwrite {
+            eprintln!("Skipping frame {}, {} exists", frame, path.display());
+            continue;
+        }
+
+        eprintln!("Frame {} ({} / {})", frame, n + 1, frame_count);
+        let scene = with_seed(scene_seed, || {
+            scene_file.build_frame(settings.aspect_ratio(), frame)
+        })?;
+        let layers = settings.film_aovs().layers(scene.lights.len());
+        let state = RenderState::with_layers(settings, layers);
+        let film = render(settings, &scene, state, None, None)?;
+
+        // The frame's image goes last, its layers are rendered again if it's missing
+        output.write_layers(&film, &path, settings)?;
+        let denoised = denoise(&film, settings);
+        if denoised.is_some() && args.keep_raw {
+            output.write(&film, &layer_path(&path, "raw"), &settings.tone_map)?;
+        }
+        let partial = path.with_extension(format!("partial.{}", extension));
+        output.write(
+            denoised.as_ref().unwrap_or(&film),
+            &partial,
+            &settings.tone_map,
+        )?;
+        fs::rename(&partial, &path)?;
+        eprintln!("\rWrote {}", path.display());
+    }
+
+    eprintln!("Done.");
+
+    Ok(())
+}
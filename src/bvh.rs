@@ -1,82 +1,645 @@
-use rand::{thread_rng, Rng};
-use std::sync::Arc;
+use rand::Rng;
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    fmt::{Display, Formatter},
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc,
+    },
+};
 
 use crate::{aabb::AABB, hittable::*, ray::Ray, vec3::*};
 
-pub struct BVH {
+// Relative costs of visiting a node and intersecting a primitive, used by the SAH
+const TRAVERSAL_COST: F = 0.125;
+const INTERSECTION_COST: F = 1.0;
+
+// Work done by BVH traversals, nodes visited and primitives intersected
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraversalCost {
+    pub nodes: u64,
+    pub intersections: u64,
+}
+
+impl TraversalCost {
+    // In units of one primitive intersection, weighted as in the SAH
+    pub fn cost(&self) -> F {
+        self.nodes as F * TRAVERSAL_COST + self.intersections as F * INTERSECTION_COST
+    }
+}
+
+thread_local! {
+    static TRAVERSAL: Cell<TraversalCost> = Cell::new(TraversalCost::default());
+}
+
+// Number of measure_traversal calls running on any thread. Rays traced while
+// there are none skip the thread local altogether
+static MEASURING: AtomicUsize = AtomicUsize::new(0);
+
+struct Measuring;
+
+impl Measuring {
+    fn start() -> Self {
+        MEASURING.fetch_add(1, atomic::Ordering::Relaxed);
+        Measuring
+    }
+}
+
+impl Drop for Measuring {
+    fn drop(&mut self) {
+        MEASURING.fetch_sub(1, atomic::Ordering::Relaxed);
+    }
+}
+
+fn count_traversal(nodes: u64, intersections: u64) {
+    if MEASURING.load(atomic::Ordering::Relaxed) == 0 {
+        return;
+    }
+    TRAVERSAL.with(|traversal| {
+        let mut cost = traversal.get();
+        cost.nodes += nodes;
+        cost.intersections += intersections;
+        traversal.set(cost);
+    });
+}
+
+// Runs `f` and returns the traversal work of every BVH it hit on this thread,
+// including BVHs nested inside others such as triangle meshes
+pub fn measure_traversal<R>(f: impl FnOnce() -> R) -> (R, TraversalCost) {
+    let measuring = Measuring::start();
+    let before = TRAVERSAL.with(Cell::get);
+    let result = f();
+    let after = TRAVERSAL.with(Cell::get);
+    drop(measuring);
+
+    let cost = TraversalCost {
+        nodes: after.nodes - before.nodes,
+        intersections: after.intersections - before.intersections,
+    };
+    (result, cost)
+}
+
+// Deepest tree the SAH builder makes, and the traversal stack size of LinearBVH
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SahOptions {
+    // Number of centroid buckets along the split axis
+    pub bins: usize,
+    // Nodes with more primitives than this are always split
+    pub max_leaf_size: usize,
+}
+
+impl Default for SahOptions {
+    fn default() -> Self {
+        Self {
+            bins: 12,
+            max_leaf_size: 4,
+        }
+    }
+}
+
+enum Node {
+    Interior {
+        bbox: AABB,
+        axis: usize,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+    Leaf {
+        bbox: AABB,
+        objects: Vec<Arc<H>>,
+    },
+}
+
+impl Node {
+    fn leaf(objects: Vec<Arc<H>>, bbox: AABB) -> Self {
+        Node::Leaf { bbox, objects }
+    }
+
+    fn interior(left: Node, right: Node, axis: usize) -> Self {
+        Node::Interior {
+            bbox: AABB::surrounding_box(left.bbox(), right.bbox()),
+            axis,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn bbox(&self) -> AABB {
+        match self {
+            Node::Interior { bbox, .. } | Node::Leaf { bbox, .. } => *bbox,
+        }
+    }
+
+    fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
+        count_traversal(1, 0);
+        if !self.bbox().hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        match self {
+            Node::Interior { left, right, .. } => {
+                let hit_record = left.hit(ray, t_min, t_max);
+
+                let t_max = if let Some(ref record) = hit_record {
+                    record.t()
+                } else {
+                    t_max
+                };
+
+                right.hit(ray, t_min, t_max).or(hit_record)
+            }
+            Node::Leaf { objects, .. } => {
+                count_traversal(0, objects.len() as u64);
+                let mut hit_record = None;
+                let mut closest = t_max;
+
+                for object in objects {
+                    if let Some(record) = object.hit(ray, t_min, closest) {
+                        closest = record.t();
+                        hit_record = Some(record);
+                    }
+                }
+
+                hit_record
+            }
+        }
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        match self {
+            Node::Interior { left, right, .. } => {
+                left.materials(visit);
+                right.materials(visit);
+            }
+            Node::Leaf { objects, .. } => {
+                for object in objects {
+                    object.materials(visit);
+                }
+            }
+        }
+    }
+}
+
+struct Primitive {
+    object: Arc<H>,
     bbox: AABB,
-    left: Arc<H>,
-    right: Arc<H>,
+    centroid: Point3,
+}
+
+impl Primitive {
+    fn new(object: Arc<H>, time0: F, time1: F) -> Self {
+        let bbox = object
+            .bounding_box(time0, time1)
+            .expect("No bounding box in bvh node constructor");
+
+        Self {
+            object,
+            bbox,
+            centroid: bbox.centroid(),
+        }
+    }
+}
+
+fn surrounding<'a>(boxes: impl Iterator<Item = &'a AABB>) -> Option<AABB> {
+    boxes.fold(None, |acc, &bbox| match acc {
+        Some(acc) => Some(AABB::surrounding_box(acc, bbox)),
+        None => Some(bbox),
+    })
+}
+
+// Moves the items matching `pred` to the front, returning how many there are
+fn partition<T>(items: &mut [T], pred: impl Fn(&T) -> bool) -> usize {
+    let mut first = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first, i);
+            first += 1;
+        }
+    }
+
+    first
+}
+
+fn leaf_of(primitives: &[Primitive], bbox: AABB) -> Node {
+    let objects = primitives
+        .iter()
+        .map(|primitive| Arc::clone(&primitive.object))
+        .collect();
+    Node::leaf(objects, bbox)
+}
+
+fn build_sah(primitives: &mut [Primitive], options: &SahOptions, depth: usize) -> Node {
+    let bbox = surrounding(primitives.iter().map(|p| &p.bbox)).unwrap();
+    let n = primitives.len();
+
+    if n == 1 {
+        return leaf_of(primitives, bbox);
+    }
+
+    let centroids: Vec<_> = primitives
+        .iter()
+        .map(|p| AABB::new(p.centroid, p.centroid))
+        .collect();
+    let centroid_bounds = surrounding(centroids.iter()).unwrap();
+    let extent = centroid_bounds.max() - centroid_bounds.min();
+
+    let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+        0
+    } else if extent.y() > extent.z() {
+        1
+    } else {
+        2
+    };
+
+    let mut mid = 0;
+
+    // Past half the stack limit only median splits are made, which bounds the depth
+    if extent.ix(axis) > 0.0 && depth < MAX_DEPTH / 2 {
+        let bins = options.bins;
+        let min = centroid_bounds.min().ix(axis);
+        let scale = bins as F / extent.ix(axis);
+        let bin_of = |p: &Primitive| (((p.centroid.ix(axis) - min) * scale) as usize).min(bins - 1);
+
+        let mut counts = vec![0; bins];
+        let mut boxes: Vec<Option<AABB>> = vec![None; bins];
+        for primitive in primitives.iter() {
+            let bin = bin_of(primitive);
+            counts[bin] += 1;
+            boxes[bin] = surrounding(boxes[bin].iter().chain(Some(&primitive.bbox)));
+        }
+
+        // Sweep from the right to get the area and count above every split
+        let mut right = vec![(0, 0.0); bins];
+        let mut right_box = None;
+        let mut right_count = 0;
+        for bin in (1..bins).rev() {
+            right_count += counts[bin];
+            right_box = surrounding(right_box.iter().chain(boxes[bin].iter()));
+            right[bin] = (right_count, right_box.map_or(0.0, |b| b.surface_area()));
+        }
+
+        let area = bbox.surface_area();
+        let mut best: Option<(usize, F)> = None;
+        let mut left_box = None;
+        let mut left_count = 0;
+        for split in 1..bins {
+            left_count += counts[split - 1];
+            left_box = surrounding(left_box.iter().chain(boxes[split - 1].iter()));
+            let (right_count, right_area) = right[split];
+
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+
+            let left_area = left_box.map_or(0.0, |b| b.surface_area());
+            let cost = if area > 0.0 {
+                TRAVERSAL_COST
+                    + INTERSECTION_COST
+                        * (left_count as F * left_area + right_count as F * right_area)
+                        / area
+            } else {
+                TRAVERSAL_COST + INTERSECTION_COST * n as F
+            };
+
+            if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                best = Some((split, cost));
+            }
+        }
+
+        let leaf_cost = INTERSECTION_COST * n as F;
+
+        match best {
+            Some((_, cost)) if n <= options.max_leaf_size && leaf_cost <= cost => {
+                return leaf_of(primitives, bbox);
+            }
+            Some((split, _)) => mid = partition(primitives, |p| bin_of(p) < split),
+            None if n <= options.max_leaf_size => return leaf_of(primitives, bbox),
+            None => {}
+        }
+    } else if n <= options.max_leaf_size {
+        // Centroids coincide or the tree is too deep for the SAH, keep a small leaf
+        return leaf_of(primitives, bbox);
+    }
+
+    if mid == 0 || mid == n {
+        mid = n / 2;
+        primitives.select_nth_unstable_by(mid, |a, b| {
+            a.centroid
+                .ix(axis)
+                .partial_cmp(&b.centroid.ix(axis))
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    let (left, right) = primitives.split_at_mut(mid);
+    Node::interior(
+        build_sah(left, options, depth + 1),
+        build_sah(right, options, depth + 1),
+        axis,
+    )
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BVHStats {
+    // Number of levels, a single leaf has depth 1
+    pub depth: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub primitives: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    // Expected cost of a ray through the root, relative to one intersection
+    pub sah_cost: F,
+}
+
+impl BVHStats {
+    pub fn mean_leaf_size(&self) -> F {
+        self.primitives as F / self.leaves as F
+    }
+
+    fn visit(&mut self, node: &Node, depth: usize, root_area: F) {
+        let relative_area = if root_area > 0.0 {
+            node.bbox().surface_area() / root_area
+        } else {
+            1.0
+        };
+
+        self.nodes += 1;
+        self.depth = self.depth.max(depth);
+
+        match node {
+            Node::Interior { left, right, .. } => {
+                self.sah_cost += TRAVERSAL_COST * relative_area;
+                self.visit(left, depth + 1, root_area);
+                self.visit(right, depth + 1, root_area);
+            }
+            Node::Leaf { objects, .. } => {
+                self.leaves += 1;
+                self.primitives += objects.len();
+                self.min_leaf_size = self.min_leaf_size.min(objects.len());
+                self.max_leaf_size = self.max_leaf_size.max(objects.len());
+                self.sah_cost += INTERSECTION_COST * objects.len() as F * relative_area;
+            }
+        }
+    }
+}
+
+impl Display for BVHStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "depth:      {}", self.depth)?;
+        writeln!(
+            f,
+            "nodes:      {} ({} interior, {} leaves)",
+            self.nodes,
+            self.nodes - self.leaves,
+            self.leaves
+        )?;
+        writeln!(f, "primitives: {}", self.primitives)?;
+        writeln!(
+            f,
+            "leaf size:  min {}, mean {:.2}, max {}",
+            self.min_leaf_size,
+            self.mean_leaf_size(),
+            self.max_leaf_size
+        )?;
+        write!(f, "SAH cost:   {:.3}", self.sah_cost)
+    }
+}
+
+pub struct BVH {
+    root: Node,
 }
 
 impl BVH {
+    // Splits at the middle object along a random axis, without sorting
     pub fn new(src_objects: &HittableList, start: usize, end: usize, time0: F, time1: F) -> Self {
-        let left: Arc<H>;
-        let right: Arc<H>;
+        Self {
+            root: Self::midpoint(src_objects, start, end, time0, time1),
+        }
+    }
 
-        let objects = src_objects.clone();
+    fn midpoint(objects: &HittableList, start: usize, end: usize, time0: F, time1: F) -> Node {
+        let leaf = |index: usize| {
+            let primitive = Primitive::new(objects.ix(index), time0, time1);
+            Node::leaf(vec![primitive.object], primitive.bbox)
+        };
 
-        let mut rng = thread_rng();
-        let axis = rng.gen_range(0, 3) as usize;
+        let axis = with_rng(|rng| rng.gen_range(0, 3));
 
         let object_span = end - start;
 
         match object_span {
-            1 => {
-                left = Arc::clone(&(objects.ix(start)));
-                right = Arc::clone(&(objects.ix(start)));
-            }
+            1 => leaf(start),
             2 => {
                 if AABB::box_compare(objects.ix(start), objects.ix(start + 1), axis) {
-                    left = Arc::clone(&(objects.ix(start)));
-                    right = Arc::clone(&(objects.ix(start + 1)));
+                    Node::interior(leaf(start), leaf(start + 1), axis)
                 } else {
-                    left = Arc::clone(&(objects.ix(start + 1)));
-                    right = Arc::clone(&(objects.ix(start)));
+                    Node::interior(leaf(start + 1), leaf(start), axis)
                 }
             }
             _ => {
                 let mid = start + object_span / 2;
 
-                left = Arc::new(BVH::new(objects, start, mid, time0, time1));
-                right = Arc::new(BVH::new(objects, mid, end, time0, time1));
+                Node::interior(
+                    Self::midpoint(objects, start, mid, time0, time1),
+                    Self::midpoint(objects, mid, end, time0, time1),
+                    axis,
+                )
             }
         }
+    }
 
-        let box_left = left.bounding_box(time0, time1);
-        let box_right = right.bounding_box(time0, time1);
+    // Binned surface area heuristic over the centroids of all objects in the list
+    pub fn sah(objects: &HittableList, options: SahOptions, time0: F, time1: F) -> Self {
+        assert!(!objects.is_empty(), "bvh needs at least one object");
 
-        if let (Some(box_left), Some(box_right)) = (box_left, box_right) {
-            let bbox = AABB::surrounding_box(box_left, box_right);
-            BVH { bbox, left, right }
-        } else {
-            panic!("No bounding box in bvh node constructor")
+        let options = SahOptions {
+            bins: options.bins.max(2),
+            max_leaf_size: options.max_leaf_size.max(1),
+        };
+
+        let mut primitives: Vec<_> = (0..objects.len())
+            .map(|index| Primitive::new(objects.ix(index), time0, time1))
+            .collect();
+
+        Self {
+            root: build_sah(&mut primitives, &options, 1),
         }
     }
+
+    pub fn stats(&self) -> BVHStats {
+        let mut stats = BVHStats {
+            depth: 0,
+            nodes: 0,
+            leaves: 0,
+            primitives: 0,
+            min_leaf_size: usize::MAX,
+            max_leaf_size: 0,
+            sah_cost: 0.0,
+        };
+        stats.visit(&self.root, 1, self.root.bbox().surface_area());
+
+        stats
+    }
 }
 
 impl Hittable for BVH {
     fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
-        if self.bbox.hit(&ray, t_min, t_max) {
-            let hit_record = self.left.hit(&ray, t_min, t_max);
+        self.root.hit(ray, t_min, t_max)
+    }
 
-            let t_max = if let Some(ref record) = hit_record {
-                record.t()
-            } else {
-                t_max
-            };
+    fn bounding_box(&self, _time0: F, _time1: F) -> Option<AABB> {
+        Some(self.root.bbox())
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.root.materials(visit)
+    }
+}
 
-            if let Some(record) = self.right.hit(&ray, t_min, t_max) {
-                return Some(record);
+struct LinearNode {
+    bbox: AABB,
+    // First object for leaves, second child for interior nodes whose first
+    // child directly follows them
+    offset: u32,
+    // Number of objects, zero for interior nodes
+    count: u32,
+    axis: u8,
+}
+
+// BVH flattened depth first into one Vec, traversed without recursion
+pub struct LinearBVH {
+    nodes: Vec<LinearNode>,
+    objects: Vec<Arc<H>>,
+}
+
+impl LinearBVH {
+    pub fn new(objects: &HittableList, options: SahOptions, time0: F, time1: F) -> Self {
+        BVH::sah(objects, options, time0, time1).into()
+    }
+
+    fn flatten(&mut self, node: Node) {
+        match node {
+            Node::Interior {
+                bbox,
+                axis,
+                left,
+                right,
+            } => {
+                let index = self.nodes.len();
+                self.nodes.push(LinearNode {
+                    bbox,
+                    offset: 0,
+                    count: 0,
+                    axis: axis as u8,
+                });
+
+                self.flatten(*left);
+                self.nodes[index].offset = self.nodes.len() as u32;
+                self.flatten(*right);
+            }
+            Node::Leaf { bbox, objects } => {
+                self.nodes.push(LinearNode {
+                    bbox,
+                    offset: self.objects.len() as u32,
+                    count: objects.len() as u32,
+                    axis: 0,
+                });
+                self.objects.extend(objects);
             }
+        }
+    }
+}
 
-            hit_record
-        } else {
-            None
+impl From<BVH> for LinearBVH {
+    fn from(bvh: BVH) -> Self {
+        let mut linear = LinearBVH {
+            nodes: Vec::new(),
+            objects: Vec::new(),
+        };
+        linear.flatten(bvh.root);
+
+        linear
+    }
+}
+
+impl Hittable for LinearBVH {
+    fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let inv_direction = Vec3::new(
+            1.0 / direction.x(),
+            1.0 / direction.y(),
+            1.0 / direction.z(),
+        );
+        let negative = [
+            inv_direction.x() < 0.0,
+            inv_direction.y() < 0.0,
+            inv_direction.z() < 0.0,
+        ];
+
+        let mut hit_record = None;
+        let mut closest = t_max;
+
+        let mut stack = [0; MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut index = 0;
+        let (mut nodes, mut intersections) = (0, 0);
+
+        loop {
+            let node = &self.nodes[index];
+            nodes += 1;
+
+            if node
+                .bbox
+                .hit_inverse(origin, inv_direction, negative, t_min, closest)
+            {
+                if node.count > 0 {
+                    intersections += node.count as u64;
+                    let start = node.offset as usize;
+                    for object in &self.objects[start..start + node.count as usize] {
+                        if let Some(record) = object.hit(ray, t_min, closest) {
+                            closest = record.t();
+                            hit_record = Some(record);
+                        }
+                    }
+                } else {
+                    // Visit the child nearer along the split axis first, so hits
+                    // there shrink `closest` before the far child is tested
+                    let (near, far) = if negative[node.axis as usize] {
+                        (node.offset as usize, index + 1)
+                    } else {
+                        (index + 1, node.offset as usize)
+                    };
+
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    index = near;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            index = stack[stack_size];
         }
+
+        count_traversal(nodes, intersections);
+        hit_record
     }
 
     fn bounding_box(&self, _time0: F, _time1: F) -> Option<AABB> {
-        Some(self.bbox)
+        Some(self.nodes[0].bbox)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        for object in self.objects.iter() {
+            object.materials(visit);
+        }
     }
 }
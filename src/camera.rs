@@ -1,4 +1,4 @@
-use crate::{ray::*, vec3::*};
+use crate::{ray::*, sampler::*, vec3::*};
 
 pub struct Camera {
     origin: Point3,
@@ -13,6 +13,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Point3,
         look_at: Point3,
@@ -29,9 +30,8 @@ impl Camera {
         let viewport_height: F = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let w = (look_from - look_at).unit();
-        let u = cross(&v_up, &w);
-        let v = cross(&w, &u);
+        let frame = Onb::from_w_up(look_from - look_at, v_up);
+        let (u, v, w) = (frame.u(), frame.v(), frame.w());
 
         let origin = look_from;
         let horizontal = u * viewport_width * focus_distance;
@@ -53,15 +53,17 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: F, t: F) -> Ray {
-        let rd = Vec3::random_in_unit_disc() * self.lens_radius;
-        let offset = self.u * rd.x() + self.v * rd.y();
+    // Lens position and shutter time come from the next dimensions of `sampler`
+    pub fn get_ray(&self, s: F, t: F, sampler: &mut dyn Sampler) -> Ray {
+        let (x, y) = sample_disc(sampler.next_2d());
+        let offset = (self.u * x + self.v * y) * self.lens_radius;
+        let time = self.time0 + sampler.next_1d() * (self.time1 - self.time0);
 
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * s + self.vertical * t
                 - (self.origin + offset),
-            random_range(self.time0, self.time1),
+            time,
         )
     }
 }
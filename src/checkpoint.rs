@@ -0,0 +1,405 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{aov::*, film::*, render::*, sampler::*, vec3::*};
+
+// Little-endian throughout:
+//   magic, version u32, settings hash u64, scene seed u64, render seed u64,
+//   width u32, height u32, tile count u32, per tile x0 y0 x1 y1 samples u32,
+//   AOV layer count u32, per layer its AOV's index in `Aov::ALL` and light u32,
+//   per pixel the weighted radiance sum as 3 f64, the filter weight f64, the
+//   sample count u32, the mean and m2 f64 of its luminance and the sum of each
+//   layer as 3 f64
+const MAGIC: &[u8; 8] = b"RTCHKPT\0";
+const VERSION: u32 = 5;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io { path: PathBuf, source: io::Error },
+    Invalid { path: PathBuf, message: String },
+    Mismatch { path: PathBuf },
+}
+
+impl CheckpointError {
+    fn invalid(path: &Path, message: impl Into<String>) -> Self {
+        CheckpointError::Invalid {
+            path: path.to_path_buf(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckpointError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            CheckpointError::Invalid { path, message } => {
+                write!(f, "{}: not a valid checkpoint, {}", path.display(), message)
+            }
+            CheckpointError::Mismatch { path } => write!(
+                f,
+                "{}: checkpoint was made with a different scene or render settings",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CheckpointError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// FNV-1a, unlike std's hasher its output is stable between builds
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xCBF2_9CE4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+
+    fn write_f64(&mut self, value: F) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+}
+
+// Identifies everything that changes the radiance in the film. `scene` is the
+// built-in scene name, or the scene file's text followed by the contents of
+// the OBJ, material and image files it loads. Samples per pixel are left out
+// so a resumed render can go on to a higher count, unless the sampler spreads
+// its strata over them. Tone mapping only affects the written image and the
+// seeds are stored in the checkpoint itself.
+pub fn settings_hash(settings: &RenderSettings, scene: &[u8]) -> u64 {
+    let mut hash = Fnv::new();
+
+    hash.write(&(scene.len() as u64).to_le_bytes());
+    hash.write(scene);
+    hash.write(&settings.image_width.to_le_bytes());
+    hash.write(&settings.image_height.to_le_bytes());
+    hash.write(&settings.max_depth.to_le_bytes());
+    let limits = &settings.depth_limits;
+    for depth in &[
+        limits.diffuse,
+        limits.specular,
+        limits.transmission,
+        limits.volume,
+        settings.russian_roulette_depth,
+    ] {
+        hash.write(&depth.to_le_bytes());
+    }
+    hash.write(&settings.tile_size.to_le_bytes());
+    hash.write(settings.sampler.name().as_bytes());
+    if settings.sampler == SamplerKind::Stratified {
+        hash.write(&settings.samples_per_pixel.to_le_bytes());
+    }
+    hash.write(settings.integrator.name().as_bytes());
+    hash.write_f64(settings.occlusion_radius);
+    hash.write_f64(settings.background.x());
+    hash.write_f64(settings.background.y());
+    hash.write_f64(settings.background.z());
+    hash.write(&settings.film_aovs().bits().to_le_bytes());
+    hash.write(settings.filter.kind.name().as_bytes());
+    hash.write_f64(settings.filter.radius);
+
+    hash.0
+}
+
+pub struct Checkpoint {
+    pub settings_hash: u64,
+    // Seed the scene was built with, the render's own seed is in `state`
+    pub scene_seed: u64,
+    pub state: RenderState,
+}
+
+impl Checkpoint {
+    // The scene is built with the seed read from the checkpoint, so its lights
+    // can only be counted afterwards
+    pub fn check_lights<P: AsRef<Path>>(
+        &self,
+        path: P,
+        settings: &RenderSettings,
+        light_count: usize,
+    ) -> Result<(), CheckpointError> {
+        let layers = self.state.film.layers();
+        if layers != settings.film_aovs().layers(light_count).as_slice() {
+            let found = layers
+                .iter()
+                .filter(|layer| layer.aov == Aov::Lights)
+                .count();
+            return Err(CheckpointError::invalid(
+                path.as_ref(),
+                format!(
+                    "AOV layers for {} lights, the scene has {}",
+                    found, light_count
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> CheckpointError + '_ {
+    move |source| CheckpointError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+fn write_state(
+    writer: &mut impl Write,
+    settings_hash: u64,
+    scene_seed: u64,
+    state: &RenderState,
+) -> io::Result<()> {
+    let film = &state.film;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&settings_hash.to_le_bytes())?;
+    writer.write_all(&scene_seed.to_le_bytes())?;
+    writer.write_all(&state.seed.to_le_bytes())?;
+    writer.write_all(&film.width().to_le_bytes())?;
+    writer.write_all(&film.height().to_le_bytes())?;
+
+    writer.write_all(&(state.tiles.len() as u32).to_le_bytes())?;
+    for tile in &state.tiles {
+        for value in &[
+            tile.tile.x0,
+            tile.tile.y0,
+            tile.tile.x1,
+            tile.tile.y1,
+            tile.samples,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    writer.write_all(&(film.layers().len() as u32).to_le_bytes())?;
+    for layer in film.layers() {
+        let aov = Aov::ALL.iter().position(|aov| *aov == layer.aov).unwrap();
+        writer.write_all(&(aov as u32).to_le_bytes())?;
+        writer.write_all(&(layer.light as u32).to_le_bytes())?;
+    }
+
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let sum = film.sum(x, y);
+            let luminance = film.luminance(x, y);
+            for value in &[sum.x(), sum.y(), sum.z()] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&film.weight(x, y).to_le_bytes())?;
+            writer.write_all(&luminance.count().to_le_bytes())?;
+            writer.write_all(&luminance.mean().to_le_bytes())?;
+            writer.write_all(&luminance.m2().to_le_bytes())?;
+            for sum in film.aov_sums(x, y) {
+                for value in &[sum.x(), sum.y(), sum.z()] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+// Written under a temporary name first, so a render killed while saving still
+// leaves the previous checkpoint intact
+pub fn write_checkpoint<P: AsRef<Path>>(
+    path: P,
+    settings_hash: u64,
+    scene_seed: u64,
+    state: &RenderState,
+) -> Result<(), CheckpointError> {
+    let path = path.as_ref();
+    let partial = path.with_extension("partial");
+
+    let file = File::create(&partial).map_err(io_error(&partial))?;
+    write_state(&mut BufWriter::new(file), settings_hash, scene_seed, state)
+        .map_err(io_error(&partial))?;
+    fs::rename(&partial, path).map_err(io_error(path))
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    read_bytes(reader).map(u32::from_le_bytes)
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    read_bytes(reader).map(u64::from_le_bytes)
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<F> {
+    read_bytes(reader).map(F::from_le_bytes)
+}
+
+fn read_state(
+    reader: &mut impl Read,
+    path: &Path,
+    settings: &RenderSettings,
+    expected_hash: u64,
+) -> Result<Checkpoint, CheckpointError> {
+    let io = io_error(path);
+    let truncated = |err: io::Error| match err.kind() {
+        io::ErrorKind::UnexpectedEof => CheckpointError::invalid(path, "file is truncated"),
+        _ => io_error(path)(err),
+    };
+
+    if &read_bytes::<8>(reader).map_err(truncated)? != MAGIC {
+        return Err(CheckpointError::invalid(path, "unrecognised header"));
+    }
+    let version = read_u32(reader).map_err(truncated)?;
+    if version != VERSION {
+        return Err(CheckpointError::invalid(
+            path,
+            format!("unsupported version {}", version),
+        ));
+    }
+
+    // Before reading the rest, which could be a film of any size
+    let settings_hash = read_u64(reader).map_err(truncated)?;
+    if settings_hash != expected_hash {
+        return Err(CheckpointError::Mismatch {
+            path: path.to_path_buf(),
+        });
+    }
+    let scene_seed = read_u64(reader).map_err(truncated)?;
+    let seed = read_u64(reader).map_err(truncated)?;
+    let width = read_u32(reader).map_err(truncated)?;
+    let height = read_u32(reader).map_err(truncated)?;
+    if (width, height) != (settings.image_width, settings.image_height) {
+        return Err(CheckpointError::invalid(
+            path,
+            format!(
+                "{}x{} film in a {}x{} render",
+                width, height, settings.image_width, settings.image_height
+            ),
+        ));
+    }
+
+    let tile_count = read_u32(reader).map_err(truncated)?;
+    let mut tiles = Vec::new();
+    for _ in 0..tile_count {
+        let tile = Tile {
+            x0: read_u32(reader).map_err(truncated)?,
+            y0: read_u32(reader).map_err(truncated)?,
+            x1: read_u32(reader).map_err(truncated)?,
+            y1: read_u32(reader).map_err(truncated)?,
+        };
+        if tile.x0 >= tile.x1 || tile.y0 >= tile.y1 || tile.x1 > width || tile.y1 > height {
+            return Err(CheckpointError::invalid(path, "tile outside of the image"));
+        }
+        tiles.push(TileState {
+            tile,
+            samples: read_u32(reader).map_err(truncated)?,
+        });
+    }
+
+    let layer_count = read_u32(reader).map_err(truncated)?;
+    let mut layers = Vec::new();
+    for _ in 0..layer_count {
+        let aov = read_u32(reader).map_err(truncated)?;
+        let aov = match Aov::ALL.get(aov as usize) {
+            Some(aov) => *aov,
+            None => return Err(CheckpointError::invalid(path, "unknown AOV")),
+        };
+        let light = read_u32(reader).map_err(truncated)? as usize;
+        layers.push(Layer { aov, light });
+    }
+    // Light layers are numbered from zero, how many the scene has is checked
+    // by `check_lights` once it is built
+    let light_count = layers
+        .iter()
+        .filter(|layer| layer.aov == Aov::Lights)
+        .count();
+    if layers != settings.film_aovs().layers(light_count) {
+        return Err(CheckpointError::invalid(
+            path,
+            "AOV layers differ from the render's",
+        ));
+    }
+
+    let read_colour = |reader: &mut _| -> Result<Colour, CheckpointError> {
+        Ok(Colour::new(
+            read_f64(reader).map_err(truncated)?,
+            read_f64(reader).map_err(truncated)?,
+            read_f64(reader).map_err(truncated)?,
+        ))
+    };
+
+    let mut film = Film::with_layers(width, height, layers);
+    for y in 0..height {
+        for x in 0..width {
+            let sum = read_colour(reader)?;
+            let weight = read_f64(reader).map_err(truncated)?;
+            let luminance = Welford::new(
+                read_u32(reader).map_err(truncated)?,
+                read_f64(reader).map_err(truncated)?,
+                read_f64(reader).map_err(truncated)?,
+            );
+            let aovs = (0..layer_count)
+                .map(|_| read_colour(reader))
+                .collect::<Result<_, _>>()?;
+            film.add_samples(
+                x,
+                y,
+                &PixelSamples {
+                    sum,
+                    weight,
+                    luminance,
+                    aovs,
+                },
+            );
+        }
+    }
+
+    if reader.read(&mut [0]).map_err(io)? != 0 {
+        return Err(CheckpointError::invalid(
+            path,
+            "unexpected data after the film",
+        ));
+    }
+
+    Ok(Checkpoint {
+        settings_hash,
+        scene_seed,
+        state: RenderState { film, tiles, seed },
+    })
+}
+
+// Fails with `Mismatch` unless the checkpoint was written for `settings` and
+// `scene`, as identified by `settings_hash`
+pub fn read_checkpoint<P: AsRef<Path>>(
+    path: P,
+    settings: &RenderSettings,
+    scene: &[u8],
+) -> Result<Checkpoint, CheckpointError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(io_error(path))?;
+    read_state(
+        &mut BufReader::new(file),
+        path,
+        settings,
+        settings_hash(settings, scene),
+    )
+}
@@ -0,0 +1,209 @@
+use image::{ImageBuffer, ImageResult, Rgb};
+
+use std::path::Path;
+
+use crate::{film::*, output::*, tonemap::*, vec3::*};
+
+// Display values in [0, 1], rows top to bottom
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Colour>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: Vec<Colour>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Tone mapped and quantised exactly as a 16 bit PNG of the film would be
+    pub fn from_film(film: &Film, tone_map: &ToneMap) -> Self {
+        Self::from_rgb16(&to_rgb16(film, tone_map))
+    }
+
+    fn from_rgb16(buffer: &ImageBuffer<Rgb<u16>, Vec<u16>>) -> Self {
+        let max = u16::MAX as F;
+        let pixels = buffer
+            .pixels()
+            .map(|Rgb([r, g, b])| Colour::new(*r as F / max, *g as F / max, *b as F / max))
+            .collect();
+
+        Self::new(buffer.width(), buffer.height(), pixels)
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Ok(Self::from_rgb16(&image::open(path)?.to_rgb16()))
+    }
+
+    // Always a 16 bit PNG, so reading it back gives the same image
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let buffer: ImageBuffer<Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(self.width, self.height, |x, y| {
+                let colour = self.pixel(x, y) * u16::MAX as F;
+                Rgb([
+                    colour.x().round() as u16,
+                    colour.y().round() as u16,
+                    colour.z().round() as u16,
+                ])
+            });
+
+        buffer.save_with_format(path, image::ImageFormat::Png)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Colour {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn pixels(&self) -> &[Colour] {
+        &self.pixels
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics {
+    pub rmse: F,
+    pub rel_mse: F,
+    pub ssim: F,
+}
+
+fn assert_same_size(reference: &Image, image: &Image) {
+    assert_eq!(
+        (reference.width, reference.height),
+        (image.width, image.height),
+        "images differ in size"
+    );
+}
+
+fn channels(colour: Colour) -> [F; 3] {
+    [colour.x(), colour.y(), colour.z()]
+}
+
+// Mean over every channel of every pixel of `f(reference, image)`
+fn channel_mean(reference: &Image, image: &Image, f: impl Fn(F, F) -> F) -> F {
+    assert_same_size(reference, image);
+
+    let total: F = reference
+        .pixels
+        .iter()
+        .zip(&image.pixels)
+        .flat_map(|(&a, &b)| {
+            let (a, b) = (channels(a), channels(b));
+            (0..3).map(move |i| (a[i], b[i]))
+        })
+        .map(|(a, b)| f(a, b))
+        .sum();
+
+    total / (3 * reference.pixels.len()) as F
+}
+
+// Root mean square error over all channels
+pub fn rmse(reference: &Image, image: &Image) -> F {
+    channel_mean(reference, image, |a, b| (a - b) * (a - b)).sqrt()
+}
+
+// Squared error relative to the reference value, so errors in dark regions
+// count as much as in bright ones
+pub fn rel_mse(reference: &Image, image: &Image) -> F {
+    const EPSILON: F = 0.01;
+
+    channel_mean(reference, image, |a, b| {
+        (a - b) * (a - b) / (a * a + EPSILON)
+    })
+}
+
+// Mean structural similarity of the luminance, Wang et al. 2004, over square
+// windows of WINDOW pixels clipped to the image. 1 for identical images.
+pub fn ssim(reference: &Image, image: &Image) -> F {
+    const WINDOW: u32 = 8;
+    const C1: F = 0.01 * 0.01;
+    const C2: F = 0.03 * 0.03;
+
+    assert_same_size(reference, image);
+
+    let (width, height) = (reference.width, reference.height);
+    let luminance_x: Vec<F> = reference.pixels.iter().map(|&c| luminance(c)).collect();
+    let luminance_y: Vec<F> = image.pixels.iter().map(|&c| luminance(c)).collect();
+
+    let window = |start: u32, size: u32| start..(start + WINDOW.min(size)).min(size);
+    let windows_x = width.saturating_sub(WINDOW) + 1;
+    let windows_y = height.saturating_sub(WINDOW) + 1;
+    let mut total = 0.0;
+
+    for y0 in 0..windows_y {
+        for x0 in 0..windows_x {
+            let indices: Vec<usize> = window(y0, height)
+                .flat_map(|y| window(x0, width).map(move |x| (y * width + x) as usize))
+                .collect();
+            let n = indices.len() as F;
+
+            let mean_x = indices.iter().map(|&i| luminance_x[i]).sum::<F>() / n;
+            let mean_y = indices.iter().map(|&i| luminance_y[i]).sum::<F>() / n;
+            let (mut var_x, mut var_y, mut covariance) = (0.0, 0.0, 0.0);
+            for &i in &indices {
+                let (dx, dy) = (luminance_x[i] - mean_x, luminance_y[i] - mean_y);
+                var_x += dx * dx;
+                var_y += dy * dy;
+                covariance += dx * dy;
+            }
+            let (var_x, var_y, covariance) = (var_x / n, var_y / n, covariance / n);
+
+            total += ((2.0 * mean_x * mean_y + C1) * (2.0 * covariance + C2))
+                / ((mean_x * mean_x + mean_y * mean_y + C1) * (var_x + var_y + C2));
+        }
+    }
+
+    total / (windows_x * windows_y) as F
+}
+
+pub fn compare(reference: &Image, image: &Image) -> Metrics {
+    Metrics {
+        rmse: rmse(reference, image),
+        rel_mse: rel_mse(reference, image),
+        ssim: ssim(reference, image),
+    }
+}
+
+// Black through red and yellow to white, for `t` from 0 to 1
+pub fn heat(t: F) -> Colour {
+    let t = clamp(t, 0.0, 1.0) * 3.0;
+    Colour::new(
+        clamp(t, 0.0, 1.0),
+        clamp(t - 1.0, 0.0, 1.0),
+        clamp(t - 2.0, 0.0, 1.0),
+    )
+}
+
+// Per pixel error as a heatmap, white for the largest error in the image
+pub fn diff_heatmap(reference: &Image, image: &Image) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    assert_same_size(reference, image);
+
+    let error = |x, y| (reference.pixel(x, y) - image.pixel(x, y)).length();
+    let max = (0..reference.height)
+        .flat_map(|y| (0..reference.width).map(move |x| error(x, y)))
+        .fold(0.0, F::max);
+
+    ImageBuffer::from_fn(reference.width, reference.height, |x, y| {
+        let t = if max > 0.0 { error(x, y) / max } else { 0.0 };
+        let colour = heat(t) * u8::MAX as F;
+        Rgb([
+            colour.x().round() as u8,
+            colour.y().round() as u8,
+            colour.z().round() as u8,
+        ])
+    })
+}
@@ -0,0 +1,141 @@
+use crate::{bvh::*, compare::heat, hittable::*, integrator::*, ray::*, sampler::*, vec3::*};
+
+// Views of the first surface along each camera ray, for checking geometry and
+// the acceleration structure without waiting for the path tracer to converge.
+// Rays that hit nothing are black.
+
+fn first_hit(ray: &Ray, world: &dyn Hittable) -> Option<HitRecord> {
+    world.hit(ray, 0.001, F::INFINITY)
+}
+
+// Outward facing normal mapped from [-1, 1] to [0, 1] per axis
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        _background: Colour,
+        world: &dyn Hittable,
+        _lights: &HittableList,
+        _sampler: &mut dyn Sampler,
+    ) -> Colour {
+        match first_hit(ray, world) {
+            Some(hit_record) => (hit_record.outward_n().unit() + Colour::one()) * 0.5,
+            None => Colour::zero(),
+        }
+    }
+}
+
+// Texture coordinates u and v as red and green
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        _background: Colour,
+        world: &dyn Hittable,
+        _lights: &HittableList,
+        _sampler: &mut dyn Sampler,
+    ) -> Colour {
+        match first_hit(ray, world) {
+            Some(hit_record) => {
+                let tp = hit_record.tp();
+                Colour::new(tp.u(), tp.v(), 0.0)
+            }
+            None => Colour::zero(),
+        }
+    }
+}
+
+// Distance from the camera in scene units, unscaled, so it is best written as
+// .hdr or brought into range with a negative exposure
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        _background: Colour,
+        world: &dyn Hittable,
+        _lights: &HittableList,
+        _sampler: &mut dyn Sampler,
+    ) -> Colour {
+        match first_hit(ray, world) {
+            Some(hit_record) => Colour::one() * hit_record.t() * ray.direction().length(),
+            None => Colour::zero(),
+        }
+    }
+}
+
+pub struct AlbedoIntegrator;
+
+impl Integrator for AlbedoIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        _background: Colour,
+        world: &dyn Hittable,
+        _lights: &HittableList,
+        _sampler: &mut dyn Sampler,
+    ) -> Colour {
+        match first_hit(ray, world) {
+            Some(hit_record) => hit_record.material().albedo(&hit_record),
+            None => Colour::zero(),
+        }
+    }
+}
+
+// White where a cosine weighted ray leaves the surface without hitting anything
+// within `radius`, averaging to the unoccluded fraction of the hemisphere
+pub struct AmbientOcclusionIntegrator {
+    pub radius: F,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        _background: Colour,
+        world: &dyn Hittable,
+        _lights: &HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Colour {
+        let hit_record = match first_hit(ray, world) {
+            Some(hit_record) => hit_record,
+            None => return Colour::zero(),
+        };
+
+        let local = sample_cosine_hemisphere(sampler.next_2d());
+        let direction = Onb::from_w(hit_record.n()).to_world(local);
+        let occlusion_ray = Ray::new(hit_record.p(), direction, ray.time());
+
+        match world.hit(&occlusion_ray, 0.001, self.radius / direction.length()) {
+            Some(_) => Colour::zero(),
+            None => Colour::one(),
+        }
+    }
+}
+
+// Cost of finding the first hit, in primitive intersections, as a heatmap on a
+// log scale from black for none to white for MAX_COST or more
+pub struct BvhCostIntegrator;
+
+impl BvhCostIntegrator {
+    const MAX_COST: F = 1024.0;
+}
+
+impl Integrator for BvhCostIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        _background: Colour,
+        world: &dyn Hittable,
+        _lights: &HittableList,
+        _sampler: &mut dyn Sampler,
+    ) -> Colour {
+        let (_, traversal) = measure_traversal(|| first_hit(ray, world));
+        heat((1.0 + traversal.cost()).ln() / (1.0 + Self::MAX_COST).ln())
+    }
+}
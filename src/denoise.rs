@@ -0,0 +1,192 @@
+use rayon::prelude::*;
+
+use crate::{aov::*, film::*, tonemap::*, vec3::*};
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), with the edge
+// stopping functions of SVGF (Schied et al. 2017). Each iteration blurs with a
+// 5x5 B-spline kernel whose taps are spread twice as far apart as the last, and
+// a neighbour only counts in as far as it lies on the same surface, judged by
+// the albedo, normal and depth AOVs, and differs in luminance by no more than
+// the noise of the pixel explains. Guides the film lacks are left out.
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    // Scales how much of a luminance difference is put down to noise, 0 leaves
+    // the image as rendered and higher blurs more
+    pub strength: F,
+    pub iterations: u32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            iterations: 5,
+        }
+    }
+}
+
+// The AOVs the denoiser is guided by
+pub fn denoise_guides() -> AovSet {
+    AovSet::default()
+        .with(Aov::Albedo)
+        .with(Aov::Normal)
+        .with(Aov::Depth)
+}
+
+const KERNEL: [F; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+struct Guides {
+    albedo: Option<Vec<Colour>>,
+    normal: Option<Vec<Vec3>>,
+    depth: Option<Vec<F>>,
+}
+
+impl Guides {
+    fn new(film: &Film) -> Self {
+        let layer = |aov: Aov| {
+            let index = film.layers().iter().position(|layer| layer.aov == aov)?;
+            Some(film.aov_pixels(index).collect::<Vec<_>>())
+        };
+
+        Self {
+            albedo: layer(Aov::Albedo),
+            normal: layer(Aov::Normal),
+            depth: layer(Aov::Depth).map(|depth| depth.iter().map(|d| d.x()).collect()),
+        }
+    }
+
+    // How far pixel `q`, `distance` pixels away, lies on the same surface as `p`
+    fn weight(&self, p: usize, q: usize, distance: F) -> F {
+        let mut weight = 1.0;
+
+        if let Some(albedo) = &self.albedo {
+            weight *= (-(albedo[p] - albedo[q]).length_squared() / Denoiser::SIGMA_ALBEDO).exp();
+        }
+        if let Some(normal) = &self.normal {
+            // Rays that hit nothing only match each other
+            weight *= match (normal[p].near_zero(), normal[q].near_zero()) {
+                (true, true) => 1.0,
+                (false, false) => dot(&normal[p], &normal[q])
+                    .max(0.0)
+                    .powf(Denoiser::SIGMA_NORMAL),
+                _ => 0.0,
+            };
+        }
+        if let Some(depth) = &self.depth {
+            // Relative to the distance, and to how far apart the pixels are
+            let scale = Denoiser::SIGMA_DEPTH * depth[p] * distance + 1e-6;
+            weight *= (-(depth[p] - depth[q]).abs() / scale).exp();
+        }
+
+        weight
+    }
+}
+
+impl Denoiser {
+    // Normals are compared by their cosine to this power
+    const SIGMA_NORMAL: F = 64.0;
+    // Of squared albedo difference
+    const SIGMA_ALBEDO: F = 0.01;
+    // Of depth difference relative to depth, per pixel apart
+    const SIGMA_DEPTH: F = 0.02;
+    // Standard deviations of luminance difference at strength 1
+    const SIGMA_LUMINANCE: F = 4.0;
+
+    // Denoised linear radiance, row-major like the film
+    pub fn denoise(&self, film: &Film) -> Vec<Colour> {
+        let (width, height) = (film.width() as usize, film.height() as usize);
+        let mut colour: Vec<Colour> = film.pixels().collect();
+        if self.strength <= 0.0 {
+            return colour;
+        }
+
+        let guides = Guides::new(film);
+        // Variance of each pixel's mean luminance
+        let mut variance: Vec<F> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x as u32, y as u32)))
+            .map(|(x, y)| {
+                let luminance = film.luminance(x, y);
+                match luminance.count() {
+                    0 => 0.0,
+                    n => luminance.variance() / n as F,
+                }
+            })
+            .collect();
+        let sigma = Self::SIGMA_LUMINANCE * self.strength;
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            // Smoothed, as single pixels' estimates are themselves noisy
+            let deviation: Vec<F> = blur_3x3(&variance, width, height)
+                .into_iter()
+                .map(F::sqrt)
+                .collect();
+
+            let filtered: Vec<(Colour, F)> = (0..width * height)
+                .into_par_iter()
+                .map(|p| {
+                    let (x, y) = ((p % width) as isize, (p / width) as isize);
+                    let luminance_p = luminance(colour[p]);
+                    let scale = sigma * deviation[p] + 1e-6;
+
+                    let mut sum = Colour::zero();
+                    let mut variance_sum = 0.0;
+                    let mut weights = 0.0;
+                    for dy in -2..=2_isize {
+                        for dx in -2..=2_isize {
+                            let (qx, qy) = (x + dx * step, y + dy * step);
+                            if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                                continue;
+                            }
+                            let q = qy as usize * width + qx as usize;
+
+                            let kernel = KERNEL[dx.unsigned_abs()] * KERNEL[dy.unsigned_abs()];
+                            let weight = if p == q {
+                                kernel
+                            } else {
+                                let distance = ((dx * dx + dy * dy) as F).sqrt() * step as F;
+                                let difference = (luminance_p - luminance(colour[q])).abs();
+                                kernel * guides.weight(p, q, distance) * (-difference / scale).exp()
+                            };
+
+                            sum = sum + colour[q] * weight;
+                            variance_sum += weight * weight * variance[q];
+                            weights += weight;
+                        }
+                    }
+
+                    (sum / weights, variance_sum / (weights * weights))
+                })
+                .collect();
+
+            colour = filtered.iter().map(|(colour, _)| *colour).collect();
+            variance = filtered.iter().map(|(_, variance)| *variance).collect();
+        }
+
+        colour
+    }
+}
+
+fn blur_3x3(values: &[F], width: usize, height: usize) -> Vec<F> {
+    const WEIGHTS: [F; 2] = [1.0 / 2.0, 1.0 / 4.0];
+
+    (0..width * height)
+        .map(|p| {
+            let (x, y) = ((p % width) as isize, (p / width) as isize);
+            let mut sum = 0.0;
+            let mut weights = 0.0;
+            for dy in -1..=1_isize {
+                for dx in -1..=1_isize {
+                    let (qx, qy) = (x + dx, y + dy);
+                    if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                        continue;
+                    }
+                    let weight = WEIGHTS[dx.unsigned_abs()] * WEIGHTS[dy.unsigned_abs()];
+                    sum += weight * values[qy as usize * width + qx as usize];
+                    weights += weight;
+                }
+            }
+            sum / weights
+        })
+        .collect()
+}
@@ -0,0 +1,294 @@
+use crate::{aov::*, tonemap::*, vec3::*};
+
+// Rectangle of pixels from (x0, y0) up to but excluding (x1, y1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    // Grown by `margin` pixels on every side, within a `width` by `height` film
+    pub fn expand(&self, margin: u32, width: u32, height: u32) -> Tile {
+        Tile {
+            x0: self.x0.saturating_sub(margin),
+            y0: self.y0.saturating_sub(margin),
+            x1: (self.x1 + margin).min(width),
+            y1: (self.y1 + margin).min(height),
+        }
+    }
+
+    // Row-major, matching the order of `Film::add_tile`
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let (x0, x1) = (self.x0, self.x1);
+        (self.y0..self.y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+}
+
+// Running mean and variance by Welford's algorithm
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Welford {
+    count: u32,
+    mean: F,
+    // Sum of squared differences from the mean
+    m2: F,
+}
+
+impl Welford {
+    pub fn new(count: u32, mean: F, m2: F) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> F {
+        self.mean
+    }
+
+    pub fn m2(&self) -> F {
+        self.m2
+    }
+
+    pub fn add(&mut self, value: F) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as F;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Combines the statistics of two sets of samples, Chan et al.
+    pub fn merge(&mut self, other: &Welford) {
+        if other.count == 0 {
+            return;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as F / count as F;
+        self.m2 += other.m2 + delta * delta * (self.count as F * other.count as F) / count as F;
+        self.count = count;
+    }
+
+    // Unbiased sample variance
+    pub fn variance(&self) -> F {
+        match self.count {
+            0 | 1 => 0.0,
+            n => self.m2 / (n - 1) as F,
+        }
+    }
+
+    // Standard error of the mean over the mean. Means below `floor` count as
+    // `floor`, so that near black pixels are judged by their absolute error.
+    pub fn relative_error(&self, floor: F) -> F {
+        match self.count {
+            0 => F::INFINITY,
+            n => (self.variance() / n as F).sqrt() / self.mean.abs().max(floor),
+        }
+    }
+}
+
+// Samples for one pixel: the filter weighted radiance total of every sample
+// within reach and the total weight, the statistics of the luminance of the
+// pixel's own samples, and their totals for the film's AOV layers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PixelSamples {
+    pub sum: Colour,
+    pub weight: F,
+    pub luminance: Welford,
+    pub aovs: Vec<Colour>,
+}
+
+impl PixelSamples {
+    // One of the pixel's own samples, with a weight of 1
+    pub fn add(&mut self, colour: Colour) {
+        self.splat(colour, 1.0);
+        self.add_own(colour);
+    }
+
+    // A sample's contribution through the reconstruction filter
+    pub fn splat(&mut self, colour: Colour, weight: F) {
+        self.sum = self.sum + colour * weight;
+        self.weight += weight;
+    }
+
+    // Counts a sample taken for this pixel, whose radiance is splatted separately
+    pub fn add_own(&mut self, colour: Colour) {
+        self.luminance.add(luminance(colour));
+    }
+
+    pub fn add_aovs(&mut self, values: impl Iterator<Item = Colour>) {
+        for (index, value) in values.enumerate() {
+            match self.aovs.get_mut(index) {
+                Some(sum) => *sum = *sum + value,
+                None => self.aovs.push(value),
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &PixelSamples) {
+        self.sum = self.sum + other.sum;
+        self.weight += other.weight;
+        self.luminance.merge(&other.luminance);
+        self.add_aovs(other.aovs.iter().copied());
+    }
+
+    pub fn count(&self) -> u32 {
+        self.luminance.count()
+    }
+}
+
+// Accumulates linear radiance per pixel, row-major from the top-left corner,
+// along with any AOV layers
+pub struct Film {
+    width: u32,
+    height: u32,
+    layers: Vec<Layer>,
+    pixels: Vec<PixelSamples>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_layers(width, height, Vec::new())
+    }
+
+    pub fn with_layers(width: u32, height: u32, layers: Vec<Layer>) -> Self {
+        let pixel = PixelSamples {
+            aovs: vec![Colour::zero(); layers.len()],
+            ..PixelSamples::default()
+        };
+
+        Self {
+            width,
+            height,
+            layers,
+            pixels: vec![pixel; (width * height) as usize],
+        }
+    }
+
+    // One sample per pixel, e.g. an image filtered from another film
+    pub fn from_pixels(width: u32, height: u32, pixels: &[Colour]) -> Self {
+        let mut film = Self::new(width, height);
+        for (pixel, colour) in film.pixels.iter_mut().zip(pixels) {
+            pixel.add(*colour);
+        }
+        film
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, colour: Colour) {
+        let index = self.index(x, y);
+        self.pixels[index].add(colour);
+    }
+
+    pub fn add_samples(&mut self, x: u32, y: u32, samples: &PixelSamples) {
+        let index = self.index(x, y);
+        self.pixels[index].merge(samples);
+    }
+
+    // Square tiles covering the film, smaller at the right and bottom edges
+    pub fn tiles(&self, size: u32) -> Vec<Tile> {
+        let size = size.max(1);
+        let mut tiles = Vec::new();
+
+        for y0 in (0..self.height).step_by(size as usize) {
+            for x0 in (0..self.width).step_by(size as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + size).min(self.width),
+                    y1: (y0 + size).min(self.height),
+                });
+            }
+        }
+
+        tiles
+    }
+
+    // `samples` holds the new samples for each pixel of the tile
+    pub fn add_tile(&mut self, tile: &Tile, samples: &[PixelSamples]) {
+        for ((x, y), samples) in tile.pixels().zip(samples) {
+            self.add_samples(x, y, samples);
+        }
+    }
+
+    pub fn samples(&self, x: u32, y: u32) -> u32 {
+        self.pixels[self.index(x, y)].count()
+    }
+
+    // Unnormalised filter weighted total of all samples
+    pub fn sum(&self, x: u32, y: u32) -> Colour {
+        self.pixels[self.index(x, y)].sum
+    }
+
+    pub fn weight(&self, x: u32, y: u32) -> F {
+        self.pixels[self.index(x, y)].weight
+    }
+
+    pub fn luminance(&self, x: u32, y: u32) -> Welford {
+        self.pixels[self.index(x, y)].luminance
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Colour {
+        let pixel = &self.pixels[self.index(x, y)];
+
+        // Negative lobes can leave edge pixels with next to no weight
+        if pixel.weight.abs() < 1e-9 {
+            Colour::zero()
+        } else {
+            pixel.sum / pixel.weight
+        }
+    }
+
+    pub fn pixels(&self) -> impl Iterator<Item = Colour> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| self.pixel(x, y)))
+    }
+
+    // Unnormalised totals of the AOV layers
+    pub fn aov_sums(&self, x: u32, y: u32) -> &[Colour] {
+        &self.pixels[self.index(x, y)].aovs
+    }
+
+    // Mean of the samples of layer `index`, except for IDs which only the
+    // first sample adds to the film
+    pub fn aov(&self, x: u32, y: u32, index: usize) -> Colour {
+        let pixel = &self.pixels[self.index(x, y)];
+        let sum = pixel.aovs[index];
+
+        match pixel.count() {
+            0 => Colour::zero(),
+            _ if self.layers[index].aov.is_id() => sum,
+            n => sum / n as F,
+        }
+    }
+
+    pub fn aov_pixels(&self, index: usize) -> impl Iterator<Item = Colour> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| self.aov(x, y, index)))
+    }
+}
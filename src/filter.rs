@@ -0,0 +1,160 @@
+use std::{
+    f64::consts::PI,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::vec3::*;
+
+// Reconstruction filters weight each sample by its offset in pixels from the
+// centre of every pixel within `radius`, and pixels are the weighted mean of
+// the samples around them. Mitchell and Lanczos have negative lobes, which
+// sharpen edges at the cost of some ringing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FilterKind {
+    // Each sample counts only towards its own pixel at the default radius
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    // Windowed sinc with as many lobes as the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    pub fn default_radius(&self) -> F {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl Display for FilterKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FilterKind::ALL
+            .iter()
+            .find(|kind| kind.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = FilterKind::ALL.iter().map(|kind| kind.name()).collect();
+                format!(
+                    "unknown filter '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // Half the width of the filter's support, in pixels
+    pub radius: F,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+fn sinc(x: F) -> F {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn mitchell(x: F) -> F {
+    const B: F = 1.0 / 3.0;
+    const C: F = 1.0 / 3.0;
+
+    let x = x.abs();
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x.powi(3)
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    } else if x < 2.0 {
+        (-B - 6.0 * C) * x.powi(3)
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        0.0
+    };
+
+    value / 6.0
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    // Weight of a sample `offset` pixels from a pixel's centre along one axis
+    fn evaluate_1d(&self, offset: F) -> F {
+        let (x, r) = (offset.abs(), self.radius);
+        if x > r {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / r,
+            FilterKind::Gaussian => {
+                // Three standard deviations to the edge, shifted down to reach zero there
+                let gaussian = |x: F| (-4.5 * (x / r).powi(2)).exp();
+                gaussian(x) - gaussian(r)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+
+    // Separable in x and y
+    pub fn evaluate(&self, dx: F, dy: F) -> F {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    // How many pixels beyond its own a sample may reach
+    pub fn margin(&self) -> u32 {
+        (self.radius - 0.5).ceil().max(0.0) as u32
+    }
+}
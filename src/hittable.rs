@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{aabb::AABB, material::*, ray::*, texture::*, vec3::*};
+use crate::{aabb::AABB, material::*, ray::*, sampler::*, texture::*, vec3::*};
 
 pub type H = dyn Hittable + Send + Sync;
 pub type M = dyn Material + Send + Sync;
@@ -9,6 +9,19 @@ pub type T = dyn Texture + Send + Sync;
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord>;
     fn bounding_box(&self, time0: F, time1: F) -> Option<AABB>;
+
+    // Light sampling: solid angle density of `random` choosing `direction` from `origin`
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> F {
+        0.0
+    }
+
+    // Light sampling: direction from `origin` towards a random point on the object
+    fn random(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // Calls `visit` with every material the object uses, in a fixed order
+    fn materials(&self, _visit: &mut dyn FnMut(&Arc<M>)) {}
 }
 
 pub struct HitRecord {
@@ -18,6 +31,8 @@ pub struct HitRecord {
     tp: TexturePoint,
     front_face: bool,
     material: Arc<M>,
+    // Set by the outermost `HittableList`, see `Aov::ObjectId`
+    object_id: u32,
 }
 
 impl HitRecord {
@@ -36,6 +51,7 @@ impl HitRecord {
             tp,
             front_face,
             material,
+            object_id: 0,
         }
     }
 
@@ -58,6 +74,17 @@ impl HitRecord {
     pub fn material(&self) -> Arc<M> {
         Arc::clone(&self.material)
     }
+    pub fn object_id(&self) -> u32 {
+        self.object_id
+    }
+    // The normal on the side the object's geometry faces, whichever side was hit
+    pub fn outward_n(&self) -> Vec3 {
+        if self.front_face {
+            self.n
+        } else {
+            -self.n
+        }
+    }
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         self.front_face = dot(&ray.direction(), &outward_normal) < 0.0;
         self.n = if self.front_face {
@@ -67,6 +94,10 @@ impl HitRecord {
         };
     }
 
+    pub fn set_n(&mut self, new_n: Vec3) {
+        self.n = new_n;
+    }
+
     pub fn set_p(&mut self, new_p: Point3) {
         self.p = new_p;
     }
@@ -74,6 +105,10 @@ impl HitRecord {
     pub fn set_t(&mut self, new_t: F) {
         self.t = new_t;
     }
+
+    pub fn set_object_id(&mut self, object_id: u32) {
+        self.object_id = object_id;
+    }
 }
 
 pub struct HittableList {
@@ -98,6 +133,20 @@ impl HittableList {
     pub fn ix(&self, index: usize) -> Arc<H> {
         Arc::clone(&self.objects[index])
     }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Hittable for HittableList {
@@ -105,9 +154,10 @@ impl Hittable for HittableList {
         let mut hit_record: Option<HitRecord> = None;
         let mut closest = t_max;
 
-        for object in self.objects.iter() {
-            if let Some(temp_record) = object.hit(ray, t_min, closest) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(mut temp_record) = object.hit(ray, t_min, closest) {
                 closest = temp_record.t();
+                temp_record.set_object_id(index as u32 + 1);
                 hit_record = Some(temp_record);
             }
         }
@@ -136,4 +186,36 @@ impl Hittable for HittableList {
 
         output_box
     }
+
+    // Objects are sampled with equal probability, so the density is the mean of theirs
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: F = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+
+        sum / self.objects.len() as F
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        // Any direction will do, the density of every one is 0
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        let u = sampler.next_1d();
+        let index = ((u * self.objects.len() as F) as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin, sampler)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        for object in self.objects.iter() {
+            object.materials(visit);
+        }
+    }
 }
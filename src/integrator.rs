@@ -0,0 +1,348 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{
+    aov::*, debug_integrator::*, hittable::*, material::*, ray::*, render::*, sampler::*, vec3::*,
+};
+
+// Estimates the light arriving along a camera ray. `lights` holds the emissive
+// objects of `world` that may be sampled directly.
+pub trait Integrator: Send + Sync {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        background: Colour,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Colour;
+
+    // As `radiance`, also adding the estimate to `split` by where its light
+    // came from. Views that don't trace light leave `split` empty.
+    fn radiance_split(
+        &self,
+        ray: &Ray,
+        background: Colour,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+        _split: &mut LightSplit,
+    ) -> Colour {
+        self.radiance(ray, background, world, lights, sampler)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum IntegratorKind {
+    #[default]
+    Path,
+    Normals,
+    Uv,
+    Depth,
+    Albedo,
+    AmbientOcclusion,
+    BvhCost,
+}
+
+impl IntegratorKind {
+    pub const ALL: [IntegratorKind; 7] = [
+        IntegratorKind::Path,
+        IntegratorKind::Normals,
+        IntegratorKind::Uv,
+        IntegratorKind::Depth,
+        IntegratorKind::Albedo,
+        IntegratorKind::AmbientOcclusion,
+        IntegratorKind::BvhCost,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegratorKind::Path => "path",
+            IntegratorKind::Normals => "normals",
+            IntegratorKind::Uv => "uv",
+            IntegratorKind::Depth => "depth",
+            IntegratorKind::Albedo => "albedo",
+            IntegratorKind::AmbientOcclusion => "ao",
+            IntegratorKind::BvhCost => "bvh_cost",
+        }
+    }
+
+    pub fn create(&self, settings: &RenderSettings) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Box::new(PathIntegrator::new(settings)),
+            IntegratorKind::Normals => Box::new(NormalsIntegrator),
+            IntegratorKind::Uv => Box::new(UvIntegrator),
+            IntegratorKind::Depth => Box::new(DepthIntegrator),
+            IntegratorKind::Albedo => Box::new(AlbedoIntegrator),
+            IntegratorKind::AmbientOcclusion => Box::new(AmbientOcclusionIntegrator {
+                radius: settings.occlusion_radius,
+            }),
+            IntegratorKind::BvhCost => Box::new(BvhCostIntegrator),
+        }
+    }
+}
+
+impl Display for IntegratorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        IntegratorKind::ALL
+            .iter()
+            .find(|kind| kind.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = IntegratorKind::ALL.iter().map(|kind| kind.name()).collect();
+                format!(
+                    "unknown integrator '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// Weight for a sample drawn with density `pdf_f` when `pdf_g` could also have produced it
+fn power_heuristic(pdf_f: F, pdf_g: F) -> F {
+    let (f, g) = (pdf_f * pdf_f, pdf_g * pdf_g);
+    if f + g > 0.0 {
+        f / (f + g)
+    } else {
+        0.0
+    }
+}
+
+// Most bounces of each kind a path may take, on top of the overall `max_depth`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DepthLimits {
+    pub diffuse: u32,
+    pub specular: u32,
+    pub transmission: u32,
+    pub volume: u32,
+}
+
+impl Default for DepthLimits {
+    fn default() -> Self {
+        Self {
+            diffuse: u32::MAX,
+            specular: u32::MAX,
+            transmission: u32::MAX,
+            volume: u32::MAX,
+        }
+    }
+}
+
+impl DepthLimits {
+    fn limit(&self, lobe: Lobe) -> u32 {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Specular => self.specular,
+            Lobe::Transmission => self.transmission,
+            Lobe::Volume => self.volume,
+        }
+    }
+}
+
+// Bounces of each kind taken so far along a path
+#[derive(Default)]
+struct Bounces([u32; 4]);
+
+impl Bounces {
+    // Counts a bounce through `lobe`, false if that goes over its limit
+    fn take(&mut self, lobe: Lobe, limits: &DepthLimits) -> bool {
+        let count = &mut self.0[lobe as usize];
+        *count += 1;
+        *count <= limits.limit(lobe)
+    }
+}
+
+// Path tracing with multiple importance sampling: at every non-specular bounce a
+// shadow ray is sent towards a random point on `lights`, and emission found by
+// the BSDF sampled ray is weighted against it with the power heuristic. Paths
+// are followed iteratively, and after `russian_roulette_depth` bounces a path
+// whose throughput has dropped is ended at random, with the survivors weighted
+// up to keep the estimate unbiased.
+#[derive(Clone, Copy, Debug)]
+pub struct PathIntegrator {
+    pub max_depth: u32,
+    pub depth_limits: DepthLimits,
+    pub russian_roulette_depth: u32,
+}
+
+impl PathIntegrator {
+    // Paths that survive roulette are never weighted up by more than this
+    const MIN_SURVIVAL: F = 0.05;
+
+    pub fn new(settings: &RenderSettings) -> Self {
+        Self {
+            max_depth: settings.max_depth,
+            depth_limits: settings.depth_limits,
+            russian_roulette_depth: settings.russian_roulette_depth,
+        }
+    }
+}
+
+impl PathIntegrator {
+    fn trace(
+        &self,
+        ray: &Ray,
+        background: Colour,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+        mut split: Option<&mut LightSplit>,
+    ) -> Colour {
+        let mut radiance = Colour::zero();
+        let mut throughput = Colour::one();
+        let mut ray = *ray;
+        // The previous hit point and the BSDF pdf of `ray`, None for camera
+        // rays and specular bounces which count emission in full
+        let mut scattered_from: Option<(Point3, F)> = None;
+        let mut bounces = Bounces::default();
+
+        for depth in 0..self.max_depth {
+            ray = ray.with_medium_sample(sampler.next_1d());
+            let hit_record = match world.hit(&ray, 0.001, F::INFINITY) {
+                Some(hit_record) => hit_record,
+                None => {
+                    let light = throughput * background;
+                    radiance = radiance + light;
+                    if let Some(split) = split.as_deref_mut() {
+                        split.add(depth, 0, light);
+                    }
+                    break;
+                }
+            };
+
+            let material = hit_record.material();
+            let p = hit_record.p();
+            let mut emitted = material.emit(hit_record.tp(), p);
+
+            if let Some((origin, bsdf_pdf)) = scattered_from {
+                if !emitted.near_zero() {
+                    let light_pdf = lights.pdf_value(origin, ray.direction());
+                    emitted = emitted * power_heuristic(bsdf_pdf, light_pdf);
+                }
+            }
+            let light = throughput * emitted;
+            radiance = radiance + light;
+            if let Some(split) = split.as_deref_mut() {
+                split.add(depth, hit_record.object_id(), light);
+            }
+
+            if !lights.is_empty() {
+                if let Some((light, object_id)) =
+                    direct_light(&ray, &hit_record, world, lights, sampler)
+                {
+                    let light = throughput * light;
+                    radiance = radiance + light;
+                    if let Some(split) = split.as_deref_mut() {
+                        split.add(depth + 1, object_id, light);
+                    }
+                }
+            }
+
+            let sample = match material.sample(&ray, &hit_record, sampler) {
+                Some(sample) => sample,
+                None => break,
+            };
+            if !bounces.take(sample.lobe(), &self.depth_limits) {
+                break;
+            }
+
+            throughput = throughput * sample.weight();
+            if throughput.near_zero() {
+                break;
+            }
+
+            if depth + 1 >= self.russian_roulette_depth {
+                let survival = throughput
+                    .x()
+                    .max(throughput.y())
+                    .max(throughput.z())
+                    .clamp(Self::MIN_SURVIVAL, 1.0);
+                if sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+
+            scattered_from = if sample.is_specular() {
+                None
+            } else {
+                Some((p, sample.pdf()))
+            };
+            ray = *sample.ray();
+        }
+
+        radiance
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        background: Colour,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Colour {
+        self.trace(ray, background, world, lights, sampler, None)
+    }
+
+    fn radiance_split(
+        &self,
+        ray: &Ray,
+        background: Colour,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+        split: &mut LightSplit,
+    ) -> Colour {
+        self.trace(ray, background, world, lights, sampler, Some(split))
+    }
+}
+
+// Light reaching `hit_record` straight from a random point on `lights`,
+// weighted against finding the same light by sampling the BSDF, with the ID of
+// the object the shadow ray found it on
+fn direct_light(
+    ray_in: &Ray,
+    hit_record: &HitRecord,
+    world: &dyn Hittable,
+    lights: &HittableList,
+    sampler: &mut dyn Sampler,
+) -> Option<(Colour, u32)> {
+    let material = hit_record.material();
+    let p = hit_record.p();
+
+    let direction = lights.random(p, sampler);
+    let light_pdf = lights.pdf_value(p, direction);
+    let f = material.eval(ray_in, hit_record, direction);
+    if light_pdf <= 0.0 || f.near_zero() {
+        return None;
+    }
+
+    let shadow_ray = Ray::new(p, direction, ray_in.time()).with_medium_sample(sampler.next_1d());
+    let light_record = world.hit(&shadow_ray, 0.001, F::INFINITY)?;
+    let light = light_record
+        .material()
+        .emit(light_record.tp(), light_record.p());
+    if light.near_zero() {
+        return None;
+    }
+
+    let bsdf_pdf = material.pdf(ray_in, hit_record, direction);
+    Some((
+        f * light * power_heuristic(light_pdf, bsdf_pdf) / light_pdf,
+        light_record.object_id(),
+    ))
+}
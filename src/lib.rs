@@ -1,15 +1,31 @@
 pub mod aabb;
 pub mod aarect;
+pub mod aov;
 pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
+pub mod compare;
+pub mod debug_integrator;
+pub mod denoise;
+pub mod film;
+pub mod filter;
 pub mod hittable;
+pub mod integrator;
 pub mod material;
 pub mod medium;
 pub mod moving_sphere;
+pub mod obj;
+pub mod output;
 pub mod perlin;
 pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene_file;
 pub mod scenes;
 pub mod sphere;
 pub mod texture;
+pub mod timeline;
+pub mod tonemap;
 pub mod transform;
+pub mod triangle;
 pub mod vec3;
@@ -1,33 +1,100 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
-use crate::{hittable::*, ray::*, texture::*, vec3::*};
+use crate::{hittable::*, ray::*, sampler::*, texture::*, vec3::*};
 
+// Materials are sampled through `sample`, which also reports the lobe it chose.
+// Non-specular lobes can additionally be evaluated for any direction with
+// `eval` and `pdf`, which is what light sampling and MIS build on.
 pub trait Material {
-    fn scatter(&self, _ray_in: &Ray, _hit_record: &mut HitRecord) -> Option<ScatterRecord> {
+    fn sample(
+        &self,
+        _ray_in: &Ray,
+        _hit_record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
         None
     }
 
+    // BSDF times cosine towards `direction`, zero for specular lobes
+    fn eval(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Colour {
+        Colour::zero()
+    }
+
+    // Solid angle density with which `sample` picks `direction`
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> F {
+        0.0
+    }
+
     fn emit(&self, _tp: TexturePoint, _p: Point3) -> Colour {
         Colour::zero()
     }
+
+    // Fraction of light the surface reflects or transmits, for debug views
+    fn albedo(&self, _hit_record: &HitRecord) -> Colour {
+        Colour::zero()
+    }
+
+    // Whether hits are scattering events inside a medium rather than surfaces
+    fn is_volume(&self) -> bool {
+        false
+    }
 }
 
-pub struct ScatterRecord {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    Diffuse,
+    // Discrete or near discrete directions that cannot be evaluated by `eval`
+    Specular,
+    // Refraction through the surface, discrete like `Specular`
+    Transmission,
+    // Scattering inside a participating medium
+    Volume,
+}
+
+pub struct BsdfSample {
     ray: Ray,
-    colour: Colour,
+    weight: Colour,
+    pdf: F,
+    lobe: Lobe,
 }
 
-impl ScatterRecord {
-    pub fn new(ray: Ray, colour: Colour) -> Self {
-        Self { ray, colour }
+impl BsdfSample {
+    // `weight` is the BSDF times cosine divided by the pdf
+    pub fn new(ray: Ray, weight: Colour, pdf: F, lobe: Lobe) -> Self {
+        Self {
+            ray,
+            weight,
+            pdf,
+            lobe,
+        }
+    }
+
+    pub fn specular(ray: Ray, weight: Colour) -> Self {
+        Self::new(ray, weight, 0.0, Lobe::Specular)
+    }
+
+    pub fn transmission(ray: Ray, weight: Colour) -> Self {
+        Self::new(ray, weight, 0.0, Lobe::Transmission)
     }
 
     pub fn ray(&self) -> &Ray {
         &self.ray
     }
 
-    pub fn colour(&self) -> Colour {
-        self.colour
+    pub fn weight(&self) -> Colour {
+        self.weight
+    }
+
+    pub fn pdf(&self) -> F {
+        self.pdf
+    }
+
+    pub fn lobe(&self) -> Lobe {
+        self.lobe
+    }
+
+    pub fn is_specular(&self) -> bool {
+        matches!(self.lobe, Lobe::Specular | Lobe::Transmission)
     }
 }
 
@@ -54,19 +121,45 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray_in: &Ray, hit_record: &mut HitRecord) -> Option<ScatterRecord> {
-        let mut scatter_direction = hit_record.n() + Vec3::random_on_unit_sphere();
-
-        // Catch degenerate scatter direction
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.n();
+    fn sample(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        // Cosine weighted about the normal
+        let local = sample_cosine_hemisphere(sampler.next_2d());
+        let scatter_direction = Onb::from_w(hit_record.n()).to_world(local);
+
+        let pdf = self.pdf(ray_in, hit_record, scatter_direction);
+        if pdf <= 0.0 {
+            return None;
         }
 
         let ray_scattered = Ray::new(hit_record.p(), scatter_direction, ray_in.time());
         let attentuation = self.albedo.value(hit_record.tp(), hit_record.p());
 
-        let scatter_record = ScatterRecord::new(ray_scattered, attentuation);
-        Some(scatter_record)
+        Some(BsdfSample::new(
+            ray_scattered,
+            attentuation,
+            pdf,
+            Lobe::Diffuse,
+        ))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo.value(hit_record.tp(), hit_record.p())
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Colour {
+        let cosine = dot(&hit_record.n(), &direction.unit()).max(0.0);
+        let albedo = self.albedo.value(hit_record.tp(), hit_record.p());
+
+        albedo * cosine / PI
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> F {
+        dot(&hit_record.n(), &direction.unit()).max(0.0) / PI
     }
 }
 
@@ -83,23 +176,32 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &mut HitRecord) -> Option<ScatterRecord> {
+    fn sample(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
         let reflected = reflect(ray_in.direction(), hit_record.n());
 
         let ray_scattered = Ray::new(
             hit_record.p(),
-            reflected + Vec3::random_on_unit_sphere() * self.fuzz,
+            reflected + sample_sphere(sampler.next_2d()) * self.fuzz,
             ray_in.time(),
         );
         let attentuation = self.albedo;
 
+        // Fuzzy reflection is treated as specular, its density is not tracked
         if dot(&ray_scattered.direction(), &hit_record.n()) > 0.0 {
-            let scatter_record = ScatterRecord::new(ray_scattered, attentuation);
-            Some(scatter_record)
+            Some(BsdfSample::specular(ray_scattered, attentuation))
         } else {
             None
         }
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Colour {
+        self.albedo
+    }
 }
 
 pub struct Dielectric {
@@ -118,7 +220,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit_record: &mut HitRecord) -> Option<ScatterRecord> {
+    fn sample(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
         let refractive_ratio = if hit_record.front_face() {
             1.0 / self.refractive_index
         } else {
@@ -130,19 +237,22 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refractive_ratio * sin_theta > 1.0;
-        let direction: Vec3;
+        let u = sampler.next_1d();
+        let attentuation = Colour::one();
 
-        if cannot_refract || self.reflectance(cos_theta, refractive_ratio) > random() {
-            direction = reflect(unit_direction, hit_record.n());
+        if cannot_refract || self.reflectance(cos_theta, refractive_ratio) > u {
+            let direction = reflect(unit_direction, hit_record.n());
+            let ray_scattered = Ray::new(hit_record.p(), direction, ray_in.time());
+            Some(BsdfSample::specular(ray_scattered, attentuation))
         } else {
-            direction = refract(unit_direction, hit_record.n(), refractive_ratio);
+            let direction = refract(unit_direction, hit_record.n(), refractive_ratio);
+            let ray_scattered = Ray::new(hit_record.p(), direction, ray_in.time());
+            Some(BsdfSample::transmission(ray_scattered, attentuation))
         }
+    }
 
-        let ray_scattered = Ray::new(hit_record.p(), direction, ray_in.time());
-        let attentuation = Colour::one();
-
-        let scatter_record = ScatterRecord::new(ray_scattered, attentuation);
-        Some(scatter_record)
+    fn albedo(&self, _hit_record: &HitRecord) -> Colour {
+        Colour::one()
     }
 }
 
@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
-use crate::{aabb::AABB, hittable::*, material::*, ray::Ray, vec3::*};
+use crate::{aabb::AABB, hittable::*, material::*, ray::Ray, sampler::*, vec3::*};
 
 const INFINITY: f64 = f64::INFINITY;
 const DT: f64 = 10e-4;
@@ -39,7 +39,9 @@ impl Hittable for ConstantMedium {
                 } else {
                     let ray_length = ray.direction().length();
                     let distance_in_boundary = (hit_record2.t() - hit_record1.t()) * ray_length;
-                    let hit_distance = self.neg_inv_density * random().ln();
+                    // Rays off the path, such as for AOVs, bring no sample
+                    let u = ray.medium_sample().unwrap_or_else(random);
+                    let hit_distance = self.neg_inv_density * (1.0 - u).ln();
 
                     if hit_distance > distance_in_boundary {
                         None
@@ -64,6 +66,11 @@ impl Hittable for ConstantMedium {
     fn bounding_box(&self, time0: F, time1: F) -> Option<AABB> {
         self.boundary.bounding_box(time0, time1)
     }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.boundary.materials(visit);
+        visit(&self.phase_function)
+    }
 }
 
 pub struct Isotropic {
@@ -77,10 +84,38 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray_in: &Ray, hit_record: &mut HitRecord) -> Option<ScatterRecord> {
-        let ray_scattered = Ray::new(hit_record.p(), Vec3::random_on_unit_sphere(), ray_in.time());
+    fn sample(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let direction = sample_sphere(sampler.next_2d());
+        let ray_scattered = Ray::new(hit_record.p(), direction, ray_in.time());
         let attentuation = self.albedo.value(hit_record.tp(), hit_record.p());
 
-        Some(ScatterRecord::new(ray_scattered, attentuation))
+        Some(BsdfSample::new(
+            ray_scattered,
+            attentuation,
+            1.0 / (4.0 * PI),
+            Lobe::Volume,
+        ))
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, _direction: Vec3) -> Colour {
+        let albedo = self.albedo.value(hit_record.tp(), hit_record.p());
+        albedo / (4.0 * PI)
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> F {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo.value(hit_record.tp(), hit_record.p())
+    }
+
+    fn is_volume(&self) -> bool {
+        true
     }
 }
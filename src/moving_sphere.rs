@@ -87,4 +87,8 @@ impl Hittable for MovingSphere {
 
         Some(AABB::surrounding_box(box0, box1))
     }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        visit(&self.material)
+    }
 }
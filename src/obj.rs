@@ -0,0 +1,513 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{hittable::*, material::*, texture::*, triangle::*, vec3::*};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    // Nothing to hit, and no bounding box to put in a BVH
+    NoFaces {
+        path: PathBuf,
+    },
+    Mesh {
+        path: PathBuf,
+        source: MeshError,
+    },
+}
+
+impl ObjError {
+    fn parse(path: &Path, line: usize, message: impl Into<String>) -> Self {
+        ObjError::Parse {
+            path: path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::NoFaces { path } => write!(f, "{}: no faces", path.display()),
+            ObjError::Mesh { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Mesh { source, .. } => Some(source),
+            ObjError::Parse { .. } | ObjError::NoFaces { .. } => None,
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn parse_floats<const N: usize>(
+    path: &Path,
+    line: usize,
+    args: &[&str],
+) -> Result<[F; N], ObjError> {
+    if args.len() < N {
+        return Err(ObjError::parse(
+            path,
+            line,
+            format!("expected {} numbers, found {}", N, args.len()),
+        ));
+    }
+
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg
+            .parse()
+            .map_err(|_| ObjError::parse(path, line, format!("invalid number '{}'", arg)))?;
+    }
+
+    Ok(values)
+}
+
+// Parsed .mtl entry, converted to a material once the whole block is read
+struct MaterialDescription {
+    kd: Colour,
+    ks: Colour,
+    ke: Colour,
+    ns: F,
+    ni: F,
+    d: F,
+    illum: u32,
+    map_kd: Option<Arc<T>>,
+}
+
+impl Default for MaterialDescription {
+    fn default() -> Self {
+        Self {
+            kd: Colour::new(0.8, 0.8, 0.8),
+            ks: Colour::zero(),
+            ke: Colour::zero(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+}
+
+impl MaterialDescription {
+    fn max_component(colour: Colour) -> F {
+        colour.x().max(colour.y()).max(colour.z())
+    }
+
+    fn build(self) -> Arc<M> {
+        if Self::max_component(self.ke) > 0.0 {
+            // Emission takes priority over every other lobe
+            Arc::new(DiffuseLight::new(Arc::new(SolidColour::new(self.ke))))
+        } else if self.d < 1.0 || self.illum == 4 || self.illum == 6 || self.illum == 7 {
+            Arc::new(Dielectric::new(self.ni))
+        } else if self.illum == 3 || Self::max_component(self.ks) > Self::max_component(self.kd) {
+            // Map the Phong exponent onto a roughness for the metal fuzz
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else if let Some(texture) = self.map_kd {
+            Arc::new(Lambertian::new(texture))
+        } else {
+            Arc::new(Lambertian::colour(self.kd))
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<M>>, ObjError> {
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MaterialDescription)> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, _)) if keyword.starts_with('#') => continue,
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(ObjError::parse(path, number, "newmtl without a name"));
+            }
+            if let Some((name, description)) = current.take() {
+                materials.insert(name, description.build());
+            }
+            current = Some((args.join(" "), MaterialDescription::default()));
+            continue;
+        }
+
+        let description = match current.as_mut() {
+            Some((_, description)) => description,
+            None => {
+                return Err(ObjError::parse(
+                    path,
+                    number,
+                    format!("'{}' before any newmtl", keyword),
+                ))
+            }
+        };
+
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = parse_floats(path, number, args)?;
+                description.kd = Colour::new(r, g, b);
+            }
+            "Ks" => {
+                let [r, g, b] = parse_floats(path, number, args)?;
+                description.ks = Colour::new(r, g, b);
+            }
+            "Ke" => {
+                let [r, g, b] = parse_floats(path, number, args)?;
+                description.ke = Colour::new(r, g, b);
+            }
+            "Ns" => description.ns = parse_floats::<1>(path, number, args)?[0],
+            "Ni" => description.ni = parse_floats::<1>(path, number, args)?[0],
+            "d" => description.d = parse_floats::<1>(path, number, args)?[0],
+            "Tr" => description.d = 1.0 - parse_floats::<1>(path, number, args)?[0],
+            "illum" => {
+                description.illum = args
+                    .first()
+                    .and_then(|arg| arg.parse().ok())
+                    .ok_or_else(|| ObjError::parse(path, number, "invalid illum model"))?;
+            }
+            "map_Kd" => {
+                // Options such as -s or -o are not supported, the file name comes last
+                let filename = args
+                    .last()
+                    .ok_or_else(|| ObjError::parse(path, number, "map_Kd without a file"))?;
+                let image_path = directory.join(filename);
+
+                let image = Image::open(&image_path).map_err(|err| {
+                    ObjError::parse(
+                        path,
+                        number,
+                        format!("couldn't open texture {}: {}", image_path.display(), err),
+                    )
+                })?;
+                description.map_kd = Some(Arc::new(image));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, description)) = current {
+        materials.insert(name, description.build());
+    }
+
+    Ok(materials)
+}
+
+// Triangles sharing a group and material, with vertices de-duplicated on (v, vt, vn)
+struct MeshBuilder {
+    material: Arc<M>,
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    positions: Vec<Point3>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<TexturePoint>>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl MeshBuilder {
+    fn new(material: Arc<M>) -> Self {
+        Self {
+            material,
+            vertices: HashMap::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>), data: &ObjData) -> usize {
+        let positions = &mut self.positions;
+        let normals = &mut self.normals;
+        let uvs = &mut self.uvs;
+
+        *self.vertices.entry(key).or_insert_with(|| {
+            let (v, vt, vn) = key;
+            positions.push(data.positions[v]);
+            uvs.push(vt.map(|vt| data.uvs[vt]));
+            normals.push(vn.map(|vn| data.normals[vn]));
+            positions.len() - 1
+        })
+    }
+
+    fn build(self, time0: F, time1: F) -> Result<TriangleMesh, MeshError> {
+        let mut mesh = Mesh::new(self.positions, self.indices, self.material);
+
+        // Attributes are only used if every vertex of the mesh provides them
+        if let Some(normals) = self.normals.into_iter().collect::<Option<Vec<_>>>() {
+            mesh = mesh.with_normals(normals)?;
+        }
+        if let Some(uvs) = self.uvs.into_iter().collect::<Option<Vec<_>>>() {
+            mesh = mesh.with_uvs(uvs)?;
+        }
+
+        TriangleMesh::new(mesh, time0, time1)
+    }
+}
+
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<TexturePoint>,
+}
+
+impl ObjData {
+    // OBJ indices are 1-based, negative values count back from the latest element
+    fn resolve(
+        path: &Path,
+        line: usize,
+        index: &str,
+        len: usize,
+        kind: &str,
+    ) -> Result<usize, ObjError> {
+        let value: i64 = index.parse().map_err(|_| {
+            ObjError::parse(path, line, format!("invalid {} index '{}'", kind, index))
+        })?;
+
+        let resolved = if value > 0 {
+            value - 1
+        } else {
+            len as i64 + value
+        };
+
+        if value == 0 || resolved < 0 || resolved >= len as i64 {
+            Err(ObjError::parse(
+                path,
+                line,
+                format!("{} index {} out of range (1..={})", kind, value, len),
+            ))
+        } else {
+            Ok(resolved as usize)
+        }
+    }
+
+    fn face_vertex(
+        &self,
+        path: &Path,
+        line: usize,
+        arg: &str,
+    ) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+        let mut parts = arg.split('/');
+
+        let v = match parts.next() {
+            Some(v) if !v.is_empty() => {
+                Self::resolve(path, line, v, self.positions.len(), "vertex")?
+            }
+            _ => {
+                return Err(ObjError::parse(
+                    path,
+                    line,
+                    "face vertex without a position",
+                ))
+            }
+        };
+        let vt = match parts.next() {
+            Some(vt) if !vt.is_empty() => {
+                Some(Self::resolve(path, line, vt, self.uvs.len(), "texture")?)
+            }
+            _ => None,
+        };
+        let vn = match parts.next() {
+            Some(vn) if !vn.is_empty() => {
+                Some(Self::resolve(path, line, vn, self.normals.len(), "normal")?)
+            }
+            _ => None,
+        };
+
+        Ok((v, vt, vn))
+    }
+}
+
+// The arguments of every `keyword` line of the file at `path`
+fn arguments(path: &Path, keyword: &str) -> Result<Vec<Vec<String>>, ObjError> {
+    Ok(read_file(path)?
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some(first) if first == keyword => Some(tokens.map(String::from).collect()),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+// The material libraries and textures that `load_obj` reads besides `path`
+pub fn obj_dependencies<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, ObjError> {
+    let path = path.as_ref();
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut files = Vec::new();
+    for filename in arguments(path, "mtllib")?.concat() {
+        let library = directory.join(filename);
+        let library_directory = library.parent().unwrap_or_else(|| Path::new(""));
+        for args in arguments(&library, "map_Kd")? {
+            files.extend(args.last().map(|filename| library_directory.join(filename)));
+        }
+        files.push(library);
+    }
+    Ok(files)
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P, time0: F, time1: F) -> Result<HittableList, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let default_material: Arc<M> = Arc::new(Lambertian::rgb(0.8, 0.8, 0.8));
+
+    let mut data = ObjData::default();
+    let mut materials: HashMap<String, Arc<M>> = HashMap::new();
+
+    let mut group = String::from("default");
+    let mut material_name = String::new();
+    let mut material = Arc::clone(&default_material);
+
+    // Keep meshes in first-seen order so output is stable between runs
+    let mut meshes: Vec<MeshBuilder> = Vec::new();
+    let mut mesh_lookup: HashMap<(String, String), usize> = HashMap::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, _)) if keyword.starts_with('#') => continue,
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(path, number, args)?;
+                data.positions.push(Point3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(path, number, args)?;
+                data.normals.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                // v defaults to 0 and a third, w, component is ignored
+                let [u] = parse_floats(path, number, args)?;
+                let v = match args.get(1) {
+                    Some(_) => parse_floats::<2>(path, number, args)?[1],
+                    None => 0.0,
+                };
+                data.uvs.push(TexturePoint::new(u, v));
+            }
+            "g" | "o" => {
+                group = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+            }
+            "mtllib" => {
+                for filename in args {
+                    materials.extend(load_mtl(&directory.join(filename))?);
+                }
+            }
+            "usemtl" => {
+                material_name = args.join(" ");
+                material = match materials.get(&material_name) {
+                    Some(material) => Arc::clone(material),
+                    None => {
+                        return Err(ObjError::parse(
+                            path,
+                            number,
+                            format!("unknown material '{}'", material_name),
+                        ))
+                    }
+                };
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ObjError::parse(
+                        path,
+                        number,
+                        format!("face needs at least 3 vertices, found {}", args.len()),
+                    ));
+                }
+
+                let face = args
+                    .iter()
+                    .map(|arg| data.face_vertex(path, number, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let key = (group.clone(), material_name.clone());
+                let index = *mesh_lookup.entry(key).or_insert_with(|| {
+                    meshes.push(MeshBuilder::new(Arc::clone(&material)));
+                    meshes.len() - 1
+                });
+                let mesh = &mut meshes[index];
+
+                // Triangulate polygons as a fan around the first vertex
+                let first = mesh.vertex(face[0], &data);
+                for pair in face[1..].windows(2) {
+                    let second = mesh.vertex(pair[0], &data);
+                    let third = mesh.vertex(pair[1], &data);
+                    mesh.indices.push([first, second, third]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if meshes.is_empty() {
+        return Err(ObjError::NoFaces {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let mut world = HittableList::new();
+    for mesh in meshes {
+        let mesh = mesh.build(time0, time1).map_err(|source| ObjError::Mesh {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        world.add(Arc::new(mesh));
+    }
+
+    Ok(world)
+}
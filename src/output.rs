@@ -0,0 +1,249 @@
+use image::{
+    codecs::{
+        hdr::HdrEncoder,
+        pnm::{PnmEncoder, PnmSubtype, SampleEncoding},
+    },
+    ColorType, ImageBuffer, ImageFormat, ImageResult, Luma, Rgb,
+};
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::{aov::*, film::*, tonemap::*, vec3::*};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+fn quantise(value: F, max: F) -> F {
+    // Scale by max + 1 and clamp so that 1.0 maps onto the top level
+    (value * (max + 1.0)).floor().min(max)
+}
+
+fn encode_pixels(pixels: impl Iterator<Item = Colour>, max: F) -> Vec<F> {
+    pixels
+        .flat_map(|colour| {
+            let [r, g, b] = [colour.x(), colour.y(), colour.z()];
+            [quantise(r, max), quantise(g, max), quantise(b, max)]
+        })
+        .collect()
+}
+
+// Display values in [0, 1], already encoded
+fn rgb8(
+    width: u32,
+    height: u32,
+    pixels: impl Iterator<Item = Colour>,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let values = encode_pixels(pixels, u8::MAX as F);
+    let pixels = values.into_iter().map(|value| value as u8).collect();
+
+    ImageBuffer::from_raw(width, height, pixels).unwrap()
+}
+
+fn rgb16(
+    width: u32,
+    height: u32,
+    pixels: impl Iterator<Item = Colour>,
+) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+    let values = encode_pixels(pixels, u16::MAX as F);
+    let pixels = values.into_iter().map(|value| value as u16).collect();
+
+    ImageBuffer::from_raw(width, height, pixels).unwrap()
+}
+
+pub fn to_rgb8(film: &Film, tone_map: &ToneMap) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let pixels = film.pixels().map(|colour| tone_map.encode(colour));
+    rgb8(film.width(), film.height(), pixels)
+}
+
+pub fn to_rgb16(film: &Film, tone_map: &ToneMap) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+    let pixels = film.pixels().map(|colour| tone_map.encode(colour));
+    rgb16(film.width(), film.height(), pixels)
+}
+
+fn write_hdr_pixels<P: AsRef<Path>>(
+    width: u32,
+    height: u32,
+    pixels: impl Iterator<Item = Colour>,
+    path: P,
+) -> ImageResult<()> {
+    let pixels: Vec<Rgb<f32>> = pixels
+        .map(|c| Rgb([c.x() as f32, c.y() as f32, c.z() as f32]))
+        .collect();
+
+    let writer = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(writer).encode(&pixels, width as usize, height as usize)
+}
+
+// Radiance .hdr keeps the linear film values, without tone mapping or clamping
+pub fn write_hdr<P: AsRef<Path>>(film: &Film, path: P) -> ImageResult<()> {
+    write_hdr_pixels(film.width(), film.height(), film.pixels(), path)
+}
+
+fn write_ppm_buffer<P: AsRef<Path>>(
+    image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    path: P,
+) -> ImageResult<()> {
+    let writer = BufWriter::new(File::create(path)?);
+
+    PnmEncoder::new(writer)
+        .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+        .encode(
+            image.as_raw().as_slice(),
+            image.width(),
+            image.height(),
+            ColorType::Rgb8,
+        )
+}
+
+pub fn write_ppm<P: AsRef<Path>>(film: &Film, path: P, tone_map: &ToneMap) -> ImageResult<()> {
+    write_ppm_buffer(&to_rgb8(film, tone_map), path)
+}
+
+// Writes display values in [0, 1] in any format but HDR
+fn write_encoded<P: AsRef<Path>>(
+    width: u32,
+    height: u32,
+    pixels: impl Iterator<Item = Colour>,
+    path: P,
+    format: ImageFormat,
+    bit_depth: BitDepth,
+) -> ImageResult<()> {
+    match (format, bit_depth) {
+        (ImageFormat::Pnm, _) => write_ppm_buffer(&rgb8(width, height, pixels), path),
+        (ImageFormat::Png, BitDepth::Sixteen) => {
+            rgb16(width, height, pixels).save_with_format(path, ImageFormat::Png)
+        }
+        _ => rgb8(width, height, pixels).save_with_format(path, format),
+    }
+}
+
+pub fn supports_bit_depth(format: ImageFormat, bit_depth: BitDepth) -> bool {
+    match bit_depth {
+        BitDepth::Eight => format != ImageFormat::Hdr,
+        BitDepth::Sixteen => format == ImageFormat::Png,
+    }
+}
+
+// Writes the film in `format`, PPM output is binary (P6) and HDR ignores the bit depth
+pub fn write_image<P: AsRef<Path>>(
+    film: &Film,
+    path: P,
+    format: ImageFormat,
+    bit_depth: BitDepth,
+    tone_map: &ToneMap,
+) -> ImageResult<()> {
+    match format {
+        ImageFormat::Hdr => write_hdr(film, path),
+        _ => {
+            let pixels = film.pixels().map(|colour| tone_map.encode(colour));
+            write_encoded(film.width(), film.height(), pixels, path, format, bit_depth)
+        }
+    }
+}
+
+// Where AOV layer `name` of the image at `path` goes, image.png has image.albedo.png
+pub fn layer_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, name, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
+    };
+    path.with_file_name(name)
+}
+
+// Lowest and highest value of any channel
+fn range(pixels: &[Colour]) -> (F, F) {
+    pixels
+        .iter()
+        .flat_map(|c| [c.x(), c.y(), c.z()])
+        .fold((F::INFINITY, F::NEG_INFINITY), |(low, high), x| {
+            (low.min(x), high.max(x))
+        })
+}
+
+// Writes AOV layer `index` of the film. HDR keeps the raw values, for other
+// formats light is tone mapped like the beauty image, albedo is shown as a
+// colour, normals are mapped from [-1, 1] to [0, 1], depth and position are
+// scaled to the range found in the image and IDs are given random colours.
+pub fn write_layer<P: AsRef<Path>>(
+    film: &Film,
+    index: usize,
+    path: P,
+    format: ImageFormat,
+    bit_depth: BitDepth,
+    tone_map: &ToneMap,
+) -> ImageResult<()> {
+    let (width, height) = (film.width(), film.height());
+    let pixels: Vec<_> = film.aov_pixels(index).collect();
+    if format == ImageFormat::Hdr {
+        return write_hdr_pixels(width, height, pixels.into_iter(), path);
+    }
+
+    let aov = film.layers()[index].aov;
+    let pixels: Vec<_> = match aov {
+        Aov::Albedo => pixels
+            .iter()
+            .map(|colour| ToneMap::default().encode(*colour))
+            .collect(),
+        Aov::Normal => pixels.iter().map(|n| (*n + Colour::one()) * 0.5).collect(),
+        Aov::Depth | Aov::Position => {
+            // Misses are left black, outside of the range. Object IDs tell them
+            // apart from hits at zero, without them only the value can.
+            let hit: Vec<_> = match film.layers().iter().position(|l| l.aov == Aov::ObjectId) {
+                Some(ids) => film.aov_pixels(ids).map(|id| id.x() != 0.0).collect(),
+                None => pixels
+                    .iter()
+                    .map(|colour| *colour != Colour::zero())
+                    .collect(),
+            };
+            let hits: Vec<_> = pixels
+                .iter()
+                .zip(&hit)
+                .filter(|(_, hit)| **hit)
+                .map(|(colour, _)| *colour)
+                .collect();
+            let (low, high) = range(&hits);
+            let scale = if high > low { 1.0 / (high - low) } else { 0.0 };
+            pixels
+                .iter()
+                .zip(&hit)
+                .map(|(colour, hit)| {
+                    if *hit {
+                        (*colour - Colour::one() * low) * scale
+                    } else {
+                        Colour::zero()
+                    }
+                })
+                .collect()
+        }
+        Aov::ObjectId | Aov::MaterialId => {
+            pixels.iter().map(|id| id_colour(id.x() as u32)).collect()
+        }
+        _ => pixels
+            .iter()
+            .map(|colour| tone_map.encode(*colour))
+            .collect(),
+    };
+
+    write_encoded(width, height, pixels.into_iter(), path, format, bit_depth)
+}
+
+// Greyscale map of the samples taken per pixel, white for the most sampled pixels
+pub fn write_sample_map<P: AsRef<Path>>(film: &Film, path: P) -> ImageResult<()> {
+    let samples = |x, y| film.samples(x, y) as F;
+    let max = (0..film.height())
+        .flat_map(|y| (0..film.width()).map(move |x| samples(x, y)))
+        .fold(1.0, F::max);
+
+    ImageBuffer::from_fn(film.width(), film.height(), |x, y| {
+        Luma([(samples(x, y) / max * u8::MAX as F).round() as u8])
+    })
+    .save(path)
+}
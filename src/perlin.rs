@@ -1,4 +1,4 @@
-use rand::{prelude::SliceRandom, thread_rng};
+use rand::prelude::SliceRandom;
 
 use crate::vec3::*;
 
@@ -11,6 +11,12 @@ pub struct Perlin {
     perm_z: Vec<usize>,
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
     pub fn new() -> Self {
         let random_vectors: Vec<Vec3> = (0..POINT_COUNT)
@@ -29,13 +35,13 @@ impl Perlin {
     }
 
     fn generate_perm() -> Vec<usize> {
-        let mut rng = thread_rng();
-        let mut perm: Vec<usize> = (0..POINT_COUNT).map(|i| i).collect();
-        perm.shuffle(&mut rng);
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        with_rng(|rng| perm.shuffle(rng));
 
         perm
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, p: Point3) -> F {
         let u = p.x() - p.x().floor();
         let v = p.y() - p.y().floor();
@@ -50,11 +56,10 @@ impl Perlin {
         for di in 0..2 {
             for dj in 0..2 {
                 for dk in 0..2 {
-                    c[di][dj][dk] = self.random_vectors[(self.perm_x
+                    c[di][dj][dk] = self.random_vectors[self.perm_x
                         [(i + di as i32) as usize & 255]
                         ^ self.perm_y[(j + dj as i32) as usize & 255]
-                        ^ self.perm_z[(k + dk as i32) as usize & 255])
-                        as usize];
+                        ^ self.perm_z[(k + dk as i32) as usize & 255]];
                 }
             }
         }
@@ -62,6 +67,7 @@ impl Perlin {
         Perlin::perlin_interpolation(c, u, v, w)
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn perlin_interpolation(c: [[[Vec3; 2]; 2]; 2], u: F, v: F, w: F) -> F {
         let mut accum = 0.0;
 
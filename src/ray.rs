@@ -1,9 +1,11 @@
-use crate::{hittable::*, vec3::*};
+use crate::vec3::*;
 
+#[derive(Clone, Copy, Debug)]
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
     time: F,
+    medium_sample: Option<F>,
 }
 
 impl Ray {
@@ -12,6 +14,25 @@ impl Ray {
             origin,
             direction,
             time,
+            medium_sample: None,
+        }
+    }
+
+    // The same ray at the same time and with the same medium sample, seen from
+    // another object space
+    pub fn moved(&self, origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            ..*self
+        }
+    }
+
+    // Carries `u` from the path's sampler to the media the ray passes through
+    pub fn with_medium_sample(self, u: F) -> Ray {
+        Ray {
+            medium_sample: Some(u),
+            ..self
         }
     }
 
@@ -27,25 +48,11 @@ impl Ray {
         self.time
     }
 
-    pub fn at(&self, t: F) -> Vec3 {
-        self.origin + self.direction * t
+    pub fn medium_sample(&self) -> Option<F> {
+        self.medium_sample
     }
-}
 
-pub fn ray_colour(ray_in: &Ray, background: Colour, world: &HittableList, depth: u32) -> Colour {
-    if depth <= 0 {
-        Colour::zero()
-    } else if let Some(mut hit_record) = world.hit(ray_in, 0.001, f64::INFINITY) {
-        let emitted = hit_record.material().emit(hit_record.tp(), hit_record.p());
-
-        if let Some(scatter_record) = hit_record.material().scatter(ray_in, &mut hit_record) {
-            emitted
-                + ray_colour(scatter_record.ray(), background, world, depth - 1)
-                    * scatter_record.colour()
-        } else {
-            emitted
-        }
-    } else {
-        background
+    pub fn at(&self, t: F) -> Vec3 {
+        self.origin + self.direction * t
     }
 }
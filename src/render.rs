@@ -0,0 +1,478 @@
+use rayon::prelude::*;
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{Display, Formatter},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use crate::{
+    aov::*, denoise::*, film::*, filter::*, integrator::*, sampler::*, scenes::Scene, tonemap::*,
+    vec3::*,
+};
+
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub depth_limits: DepthLimits,
+    // Bounces before paths may be ended by Russian roulette
+    pub russian_roulette_depth: u32,
+    pub background: Colour,
+    pub tone_map: ToneMap,
+    // Seeds the samples, a random seed is drawn for each render without one
+    pub seed: Option<u64>,
+    // Seeds the random choices made while building the scene, falls back to `seed`
+    pub scene_seed: Option<u64>,
+    // Edge length in pixels of the square tiles handed to worker threads
+    pub tile_size: u32,
+    // With adaptive sampling `samples_per_pixel` is the most any pixel gets
+    pub adaptive: Option<Adaptive>,
+    pub sampler: SamplerKind,
+    pub integrator: IntegratorKind,
+    // Distance within which the ambient occlusion view counts surfaces as occluding
+    pub occlusion_radius: F,
+    pub aovs: AovSet,
+    // Filters the finished image, rendering the AOVs that guide it
+    pub denoise: Option<Denoiser>,
+    // Reconstruction filter that spreads each sample over the pixels around it
+    pub filter: Filter,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            image_width: 800,
+            image_height: 800,
+            samples_per_pixel: 1000,
+            max_depth: 50,
+            depth_limits: DepthLimits::default(),
+            russian_roulette_depth: 3,
+            background: Colour::zero(),
+            tone_map: ToneMap::default(),
+            seed: None,
+            scene_seed: None,
+            tile_size: 32,
+            adaptive: None,
+            sampler: SamplerKind::default(),
+            integrator: IntegratorKind::default(),
+            occlusion_radius: 1.0,
+            aovs: AovSet::default(),
+            denoise: None,
+            filter: Filter::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsError {
+    ImageSize { width: u32, height: u32 },
+    NoSamples,
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::ImageSize { width, height } => write!(
+                f,
+                "image must be at least {0}x{0} pixels, not {1}x{2}",
+                RenderSettings::MIN_IMAGE_SIZE,
+                width,
+                height
+            ),
+            SettingsError::NoSamples => write!(f, "samples per pixel must be at least 1"),
+        }
+    }
+}
+
+impl Error for SettingsError {}
+
+impl RenderSettings {
+    // Pixels are mapped onto the camera's view by dividing by one less than the size
+    pub const MIN_IMAGE_SIZE: u32 = 2;
+
+    // Settings a render can be made with, anything else would divide by zero
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.image_width < Self::MIN_IMAGE_SIZE || self.image_height < Self::MIN_IMAGE_SIZE {
+            return Err(SettingsError::ImageSize {
+                width: self.image_width,
+                height: self.image_height,
+            });
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(SettingsError::NoSamples);
+        }
+
+        Ok(())
+    }
+
+    pub fn aspect_ratio(&self) -> F {
+        self.image_width as F / self.image_height as F
+    }
+
+    // Keeps the width and derives the height, rounding to at least one pixel
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: F) {
+        self.image_height = ((self.image_width as F / aspect_ratio).round() as u32).max(1);
+    }
+
+    // The AOVs the film holds, those asked for, any the denoiser needs and the
+    // object IDs that mark which pixels of depth and position are misses
+    pub fn film_aovs(&self) -> AovSet {
+        let aovs = match self.denoise {
+            Some(_) => self.aovs.union(denoise_guides()),
+            None => self.aovs,
+        };
+        if aovs.contains(Aov::Depth) || aovs.contains(Aov::Position) {
+            aovs.with(Aov::ObjectId)
+        } else {
+            aovs
+        }
+    }
+
+    // The scene seed if there is one, else the render seed, else a random seed
+    pub fn resolve_scene_seed(&self) -> u64 {
+        self.scene_seed.or(self.seed).unwrap_or_else(rand::random)
+    }
+}
+
+// A pixel stops taking samples once it has `min_samples` and the standard error
+// of its luminance is below `threshold` times its mean
+#[derive(Clone, Copy, Debug)]
+pub struct Adaptive {
+    pub threshold: F,
+    pub min_samples: u32,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self {
+            threshold: 0.02,
+            min_samples: 16,
+        }
+    }
+}
+
+impl Adaptive {
+    // Below this luminance the error is measured against this luminance instead
+    const ERROR_FLOOR: F = 0.01;
+
+    pub fn converged(&self, luminance: &Welford) -> bool {
+        luminance.count() >= self.min_samples
+            && luminance.relative_error(Self::ERROR_FLOOR) < self.threshold
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pass {
+    // Counting from 1
+    pub number: usize,
+    // Samples per pixel taken in this pass and in all passes so far
+    pub samples: u32,
+    pub total_samples: u32,
+    // Pixels that took samples in this pass, fewer than all with adaptive sampling
+    pub pixels: usize,
+}
+
+// Samples for the pass after `done` samples, doubling the total each time
+pub fn next_pass_samples(done: u32, target: u32) -> u32 {
+    done.max(1).min(target.saturating_sub(done))
+}
+
+// Progress of one tile, enough to carry on exactly where it stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileState {
+    pub tile: Tile,
+    // Samples per pixel taken so far
+    pub samples: u32,
+}
+
+// SplitMix64, spreads consecutive sample indices over unrelated seeds
+fn sample_seed(seed: u64, index: u32) -> u64 {
+    let mut z = seed.wrapping_add((index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Film with the per-tile progress of a render
+pub struct RenderState {
+    pub film: Film,
+    pub tiles: Vec<TileState>,
+    // Every random number of the render follows from this seed
+    pub seed: u64,
+}
+
+impl RenderState {
+    // Takes the settings' seed, or a random one if it has none
+    pub fn new(settings: &RenderSettings) -> Self {
+        Self::with_layers(settings, Vec::new())
+    }
+
+    // As `new`, with a film that also renders the AOV `layers`
+    pub fn with_layers(settings: &RenderSettings, layers: Vec<Layer>) -> Self {
+        let film = Film::with_layers(settings.image_width, settings.image_height, layers);
+        let tiles = film
+            .tiles(settings.tile_size)
+            .into_iter()
+            .map(|tile| TileState { tile, samples: 0 })
+            .collect();
+        let seed = settings.seed.unwrap_or_else(rand::random);
+
+        Self { film, tiles, seed }
+    }
+
+    // Samples per pixel every tile has reached
+    pub fn samples(&self) -> u32 {
+        self.tiles
+            .iter()
+            .map(|tile| tile.samples)
+            .min()
+            .unwrap_or(0)
+    }
+
+    // `samples` covers `region`, the tile and the pixels its samples reach
+    fn add_tile(&mut self, index: usize, region: &Tile, samples: &[PixelSamples], total: u32) {
+        self.film.add_tile(region, samples);
+        self.tiles[index].samples = total;
+    }
+
+    // Indices of the samples each pixel of the tile takes to reach `total`,
+    // none for pixels that adaptive sampling has finished with
+    fn pixel_samples(
+        &self,
+        settings: &RenderSettings,
+        index: usize,
+        total: u32,
+    ) -> Vec<Range<u32>> {
+        let state = &self.tiles[index];
+        let samples = total.saturating_sub(state.samples);
+
+        state
+            .tile
+            .pixels()
+            .map(|(x, y)| {
+                let luminance = self.film.luminance(x, y);
+                let first = luminance.count();
+                match settings.adaptive {
+                    Some(adaptive) if adaptive.converged(&luminance) => first..first,
+                    _ => first..first + samples,
+                }
+            })
+            .collect()
+    }
+}
+
+// `samples` holds the sample indices to take for each pixel of the tile. Each
+// sample reseeds the thread's generator from the seed, its pixel and its index,
+// so the image doesn't depend on the tiling or on which thread took the sample.
+// Samples are splatted through the filter onto every pixel within its radius,
+// so the result covers the tile grown by the filter's margin. Luminance
+// statistics and AOVs count towards the sample's own pixel only. AOVs are
+// looked up after the radiance, so that they don't change it.
+fn render_tile(
+    settings: &RenderSettings,
+    scene: &Scene,
+    aovs: Option<&AovContext>,
+    seed: u64,
+    tile: &Tile,
+    samples: &[Range<u32>],
+) -> (Tile, Vec<PixelSamples>) {
+    let width = settings.image_width;
+    let height = settings.image_height;
+    let mut sampler = settings.sampler.create(settings.samples_per_pixel, seed);
+    let integrator = settings.integrator.create(settings);
+    let filter = settings.filter;
+    let margin = filter.margin() as i64;
+    let region = tile.expand(margin as u32, width, height);
+    let mut pixels = vec![PixelSamples::default(); (region.width() * region.height()) as usize];
+    let region_index = |x: i64, y: i64| -> Option<usize> {
+        let inside = x >= region.x0 as i64
+            && x < region.x1 as i64
+            && y >= region.y0 as i64
+            && y < region.y1 as i64;
+        inside.then(|| {
+            ((y - region.y0 as i64) * region.width() as i64 + x - region.x0 as i64) as usize
+        })
+    };
+
+    for ((i, j), samples) in tile.pixels().zip(samples) {
+        let own = region_index(i as i64, j as i64).unwrap();
+        let stream = j as u64 * width as u64 + i as u64;
+        for index in samples.clone() {
+            seed_thread_rng(sample_seed(seed, index), stream);
+            sampler.start_pixel_sample(i, j, index);
+
+            // Film rows run top to bottom, v runs bottom to top
+            let (du, dv) = sampler.next_2d();
+            let u = (i as F + du) / (width - 1) as F;
+            let v = ((height - 1 - j) as F + dv) / (height - 1) as F;
+            let ray = scene.camera.get_ray(u, v, sampler.as_mut());
+            let radiance = match aovs {
+                None => integrator.radiance(
+                    &ray,
+                    settings.background,
+                    &scene.world,
+                    &scene.lights,
+                    sampler.as_mut(),
+                ),
+                Some(aovs) => {
+                    let mut split = aovs.light_split();
+                    let radiance = integrator.radiance_split(
+                        &ray,
+                        settings.background,
+                        &scene.world,
+                        &scene.lights,
+                        sampler.as_mut(),
+                        &mut split,
+                    );
+                    // IDs are taken from the first sample alone, which
+                    // leaves them unchanged by the film's sum
+                    let values = aovs.values(&ray, &scene.world, &split);
+                    pixels[own].add_aovs(aovs.layers().iter().zip(values).map(|(layer, value)| {
+                        if layer.aov.is_id() && index > 0 {
+                            Colour::zero()
+                        } else {
+                            value
+                        }
+                    }));
+                    radiance
+                }
+            };
+
+            // Offset of the sample from its pixel's centre, in film space
+            let (dx, dy) = (du - 0.5, 0.5 - dv);
+            for y in -margin..=margin {
+                for x in -margin..=margin {
+                    if let Some(pixel) = region_index(i as i64 + x, j as i64 + y) {
+                        let weight = filter.evaluate(dx - x as F, dy - y as F);
+                        if weight != 0.0 {
+                            pixels[pixel].splat(radiance, weight);
+                        }
+                    }
+                }
+            }
+            pixels[own].add_own(radiance);
+        }
+    }
+
+    (region, pixels)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Progress {
+    // `done` of the pass's `tiles` are finished and merged into the film
+    Tile { done: usize, tiles: usize },
+    Pass(Pass),
+}
+
+// Renders tiles in parallel in passes of 1, 1, 2, 4, ... samples per pixel until
+// every tile holds `samples_per_pixel`, continuing from whatever `state` already
+// holds. With adaptive sampling, pixels that have converged are skipped and the
+// render ends early once none are left. Tiles behind the others, e.g. after
+// resuming a checkpoint written mid pass, catch up in the next pass. Finished
+// tiles are merged into the film on the calling thread in tile order, whatever
+// order they finish in, so that samples splatted across tile borders add up the
+// same way on any number of threads. `on_progress` sees the state after every
+// tile and every pass. The first error it returns stops the render.
+pub fn render_progressive<E>(
+    settings: &RenderSettings,
+    scene: &Scene,
+    state: &mut RenderState,
+    mut on_progress: impl FnMut(&RenderState, Progress) -> Result<(), E>,
+) -> Result<(), E> {
+    let target = settings.samples_per_pixel;
+    let mut number = 0;
+    let layers = state.film.layers();
+    let aovs = (!layers.is_empty()).then(|| AovContext::new(scene, layers.to_vec()));
+
+    loop {
+        let done = state.samples();
+        if done >= target {
+            return Ok(());
+        }
+        let total = done + next_pass_samples(done, target);
+
+        let tiles: Vec<_> = (0..state.tiles.len())
+            .filter(|&index| state.tiles[index].samples < total)
+            .map(|index| (index, state.pixel_samples(settings, index, total)))
+            .collect();
+        let pixels = tiles
+            .iter()
+            .flat_map(|(_, samples)| samples)
+            .filter(|samples| !samples.is_empty())
+            .count();
+        if pixels == 0 {
+            return Ok(());
+        }
+
+        let state_tiles = state.tiles.clone();
+        let seed = state.seed;
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = AtomicBool::new(false);
+        // Tiles are taken in order, so few wait to be merged behind a slow one
+        let next = AtomicUsize::new(0);
+
+        // Workers run on the rayon pool, fed from a scoped thread so that this
+        // thread stays free to collect their results
+        thread::scope(|scope| {
+            let (tiles, state_tiles, cancelled, next, aovs) =
+                (&tiles, &state_tiles, &cancelled, &next, aovs.as_ref());
+            scope.spawn(move || {
+                (0..rayon::current_num_threads())
+                    .into_par_iter()
+                    .for_each_with(sender, |sender, _| loop {
+                        let position = next.fetch_add(1, Ordering::Relaxed);
+                        if position >= tiles.len() || cancelled.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        let (index, samples) = &tiles[position];
+                        let tile = &state_tiles[*index].tile;
+                        let result = render_tile(settings, scene, aovs, seed, tile, samples);
+                        // Only fails once the receiver has given up
+                        if sender.send((position, result)).is_err() {
+                            return;
+                        }
+                    });
+            });
+
+            let mut waiting = BTreeMap::new();
+            let mut merged = 0;
+            for (position, result) in receiver {
+                waiting.insert(position, result);
+                while let Some((region, pixels)) = waiting.remove(&merged) {
+                    state.add_tile(tiles[merged].0, &region, &pixels, total);
+                    merged += 1;
+                    let progress = Progress::Tile {
+                        done: merged,
+                        tiles: tiles.len(),
+                    };
+                    if let Err(err) = on_progress(state, progress) {
+                        cancelled.store(true, Ordering::Relaxed);
+                        return Err(err);
+                    }
+                }
+            }
+
+            Ok(())
+        })?;
+
+        number += 1;
+        on_progress(
+            state,
+            Progress::Pass(Pass {
+                number,
+                samples: total - done,
+                total_samples: total,
+                pixels,
+            }),
+        )?;
+    }
+}
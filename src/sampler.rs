@@ -0,0 +1,563 @@
+use std::{
+    f64::consts::PI,
+    fmt::{Display, Formatter},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use crate::vec3::*;
+
+// Source of the random numbers for the samples of a pixel. Every value a path
+// needs, from the pixel jitter onwards, is the next dimension of the sample.
+pub trait Sampler {
+    // Starts sample `index` of pixel (x, y), going back to the first dimension
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+    fn next_1d(&mut self) -> F;
+    fn next_2d(&mut self) -> (F, F);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue_noise",
+        }
+    }
+
+    // `seed` decorrelates the scrambling of different renders, stratification
+    // is spread over `samples_per_pixel`
+    pub fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(Independent),
+            SamplerKind::Stratified => Box::new(Stratified::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(Halton::new(seed)),
+            SamplerKind::Sobol => Box::new(Sobol::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoise::new(seed)),
+        }
+    }
+}
+
+impl Display for SamplerKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SamplerKind::ALL
+            .iter()
+            .find(|kind| kind.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = SamplerKind::ALL.iter().map(|kind| kind.name()).collect();
+                format!(
+                    "unknown sampler '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// Warps from uniform samples on the unit square
+
+// Uniform direction on the unit sphere
+pub fn sample_sphere(u: (F, F)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Uniform point on the unit disc, Shirley and Chiu's concentric mapping
+pub fn sample_disc(u: (F, F)) -> (F, F) {
+    let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+
+    if a == 0.0 && b == 0.0 {
+        (0.0, 0.0)
+    } else if a.abs() > b.abs() {
+        let theta = PI / 4.0 * (b / a);
+        (a * theta.cos(), a * theta.sin())
+    } else {
+        let theta = PI / 2.0 - PI / 4.0 * (a / b);
+        (b * theta.cos(), b * theta.sin())
+    }
+}
+
+// Cosine weighted direction about +z
+pub fn sample_cosine_hemisphere(u: (F, F)) -> Vec3 {
+    let (x, y) = sample_disc(u);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+    Vec3::new(x, y, z)
+}
+
+// Hashing, to give every pixel and dimension its own scramble
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7FB5_D329_728E_A185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81DA_DEF4_BC2D_D44D);
+    v ^ (v >> 33)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9E37_79B9_7F4A_7C15, |hash, &value| {
+        mix_bits(hash ^ value.wrapping_add(0x632B_E59B_D9B4_E019))
+    })
+}
+
+// [0, 1) from the bits of a 32 bit fraction
+fn to_unit(bits: u32) -> F {
+    bits as F / (1u64 << 32) as F
+}
+
+fn fract(value: F) -> F {
+    value - value.floor()
+}
+
+// Independent uniform random numbers, the sampling the renderer always had
+pub struct Independent;
+
+impl Sampler for Independent {
+    fn start_pixel_sample(&mut self, _x: u32, _y: u32, _index: u32) {}
+
+    fn next_1d(&mut self) -> F {
+        random()
+    }
+
+    fn next_2d(&mut self) -> (F, F) {
+        (random(), random())
+    }
+}
+
+// Kensler's hash based permutation of 0..length, from Correlated Multi-Jittered Sampling
+fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xE170_893D);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_EB3F);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_FA69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74DC_B303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9E50_1CC3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xC860_A3DF);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < length {
+            return (i.wrapping_add(seed)) % length;
+        }
+    }
+}
+
+// Jittered strata, samples_per_pixel of them in 1D and the nearest square
+// number in 2D. Each pixel and dimension visits its strata in its own order,
+// samples past the last stratum start over.
+pub struct Stratified {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl Stratified {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn stratum(&mut self, count: u32) -> u32 {
+        let seed = hash(&[self.pixel, self.dimension]) as u32;
+        self.dimension += 1;
+        permute(self.index % count, count, seed)
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> F {
+        let stratum = self.stratum(self.samples_per_pixel);
+        (stratum as F + random()) / self.samples_per_pixel as F
+    }
+
+    fn next_2d(&mut self) -> (F, F) {
+        let n = (self.samples_per_pixel as F).sqrt().round().max(1.0) as u32;
+        let stratum = self.stratum(n * n);
+        let (x, y) = (stratum % n, stratum / n);
+
+        ((x as F + random()) / n as F, (y as F + random()) / n as F)
+    }
+}
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// Digits of `index` in `base` mirrored about the radix point
+pub fn radical_inverse(base: u32, mut index: u32) -> F {
+    let inverse_base = 1.0 / base as F;
+    let mut inverse = 0.0;
+    let mut scale = inverse_base;
+
+    while index > 0 {
+        inverse += (index % base) as F * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+
+    inverse
+}
+
+// Halton sequence with one prime base per dimension, shifted by a random
+// offset per pixel and dimension (Cranley-Patterson rotation). Dimensions past
+// the last base fall back to independent random numbers.
+pub struct Halton {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl Halton {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for Halton {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> F {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let offset = to_unit(hash(&[self.pixel, dimension as u64]) as u32);
+                fract(radical_inverse(base, self.index) + offset)
+            }
+            None => random(),
+        }
+    }
+
+    fn next_2d(&mut self) -> (F, F) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+// Bits of the second Sobol dimension as a 32 bit fraction, the first is the
+// bit reversed index
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut value = 0;
+    let mut direction = 1 << 31;
+
+    while index != 0 {
+        if index & 1 != 0 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    value
+}
+
+// Laine-Karras style hash, each bit only depends on the bits below it
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x ^= x.wrapping_mul(0x3D20_ADEA);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x0552_6C56);
+    x ^= x.wrapping_mul(0x53A2_2864);
+    x
+}
+
+// Owen scrambling of a 32 bit fraction, Burley's hash based construction
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Owen scrambled, index shuffled 2D Sobol points, a fresh scramble for every
+// dimension pair pads the sequence out to any number of dimensions
+fn owen_sobol_2d(index: u32, seed: u64) -> (F, F) {
+    let shuffled = nested_uniform_scramble(index, hash(&[seed, 0]) as u32);
+    let x = nested_uniform_scramble(shuffled.reverse_bits(), hash(&[seed, 1]) as u32);
+    let y = nested_uniform_scramble(sobol_second_dimension(shuffled), hash(&[seed, 2]) as u32);
+
+    (to_unit(x), to_unit(y))
+}
+
+fn owen_sobol_1d(index: u32, seed: u64) -> F {
+    let shuffled = nested_uniform_scramble(index, hash(&[seed, 0]) as u32);
+    to_unit(nested_uniform_scramble(
+        shuffled.reverse_bits(),
+        hash(&[seed, 1]) as u32,
+    ))
+}
+
+pub struct Sobol {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl Sobol {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn dimension_seed(&mut self) -> u64 {
+        self.dimension += 1;
+        hash(&[self.pixel, self.dimension])
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> F {
+        let seed = self.dimension_seed();
+        owen_sobol_1d(self.index, seed)
+    }
+
+    fn next_2d(&mut self) -> (F, F) {
+        let seed = self.dimension_seed();
+        owen_sobol_2d(self.index, seed)
+    }
+}
+
+pub const BLUE_NOISE_SIZE: u32 = 64;
+
+// Void and cluster threshold map (Ulichney), every rank from 0 to size² - 1
+// once, spread so that nearby pixels have very different ranks
+fn void_and_cluster(size: usize) -> Vec<u32> {
+    const SIGMA: F = 1.5;
+    let n = size * size;
+
+    // Gaussian weight for each toroidal offset
+    let kernel: Vec<F> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as F;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+    let offset = |a: usize, b: usize| {
+        let dx = (a % size + size - b % size) % size;
+        let dy = (a / size + size - b / size) % size;
+        dy * size + dx
+    };
+
+    let mut ones = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let toggle = |ones: &mut Vec<bool>, energy: &mut Vec<F>, pixel: usize| {
+        let sign = if ones[pixel] { -1.0 } else { 1.0 };
+        ones[pixel] = !ones[pixel];
+        for (i, e) in energy.iter_mut().enumerate() {
+            *e += sign * kernel[offset(i, pixel)];
+        }
+    };
+    // Densest one and emptiest zero
+    let tightest_cluster = |ones: &[bool], energy: &[F]| {
+        (0..n)
+            .filter(|&i| ones[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |ones: &[bool], energy: &[F]| {
+        (0..n)
+            .filter(|&i| !ones[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // A tenth of the pixels, scattered by a fixed hash so the map never changes
+    let initial = n / 10;
+    let mut placed = 0;
+    for i in 0.. {
+        let pixel = (hash(&[i]) % n as u64) as usize;
+        if !ones[pixel] {
+            toggle(&mut ones, &mut energy, pixel);
+            placed += 1;
+            if placed == initial {
+                break;
+            }
+        }
+    }
+
+    // Even out the initial pattern by moving its densest points into the emptiest gaps
+    loop {
+        let cluster = tightest_cluster(&ones, &energy);
+        toggle(&mut ones, &mut energy, cluster);
+        let void = largest_void(&ones, &energy);
+        toggle(&mut ones, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; n];
+    let (initial_ones, initial_energy) = (ones.clone(), energy.clone());
+
+    // Ranks below the initial pattern by removing clusters, above it by filling voids
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&ones, &energy);
+        toggle(&mut ones, &mut energy, cluster);
+        ranks[cluster] = rank as u32;
+    }
+    ones = initial_ones;
+    energy = initial_energy;
+    for rank in initial..n {
+        let void = largest_void(&ones, &energy);
+        toggle(&mut ones, &mut energy, void);
+        ranks[void] = rank as u32;
+    }
+
+    ranks
+}
+
+// Blue noise values in [0, 1), BLUE_NOISE_SIZE² of them row by row, computed on first use
+pub fn blue_noise() -> &'static [F] {
+    static MASK: OnceLock<Vec<F>> = OnceLock::new();
+
+    MASK.get_or_init(|| {
+        let size = BLUE_NOISE_SIZE as usize;
+        void_and_cluster(size)
+            .into_iter()
+            .map(|rank| (rank as F + 0.5) / (size * size) as F)
+            .collect()
+    })
+}
+
+// Blue noise dithered sampling (Georgiev and Fajardo): every pixel takes the
+// same Owen scrambled Sobol points, shifted by a blue noise value. Neighbouring
+// pixels get very different shifts, so the error at low sample counts looks
+// like fine grained blue noise rather than clumps. Each dimension reads the
+// mask at its own toroidal offset.
+pub struct BlueNoise {
+    seed: u64,
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u64,
+}
+
+impl BlueNoise {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn shift(&self, seed: u64) -> F {
+        let offset = hash(&[seed, 3]);
+        let x = (self.x as u64 + offset) % BLUE_NOISE_SIZE as u64;
+        let y = (self.y as u64 + (offset >> 32)) % BLUE_NOISE_SIZE as u64;
+        blue_noise()[(y * BLUE_NOISE_SIZE as u64 + x) as usize]
+    }
+
+    fn dimension_seed(&mut self) -> u64 {
+        self.dimension += 1;
+        hash(&[self.seed, self.dimension])
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> F {
+        let seed = self.dimension_seed();
+        fract(owen_sobol_1d(self.index, seed) + self.shift(seed))
+    }
+
+    fn next_2d(&mut self) -> (F, F) {
+        let seed = self.dimension_seed();
+        let (u, v) = owen_sobol_2d(self.index, seed);
+
+        (fract(u + self.shift(seed)), fract(v + self.shift(!seed)))
+    }
+}
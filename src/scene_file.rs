@@ -0,0 +1,1079 @@
+use serde::Deserialize;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    aarect::*, bvh::*, camera::*, denoise::*, filter::*, hittable::*, integrator::*, material::*,
+    medium::*, moving_sphere::*, obj::*, render::*, scenes::Scene, sphere::*, texture::*,
+    timeline::*, tonemap::*, transform::*, triangle::*, vec3::*,
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Syntax {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    // `entry` is the path to the offending value, e.g. objects[2].object.material
+    Invalid {
+        entry: String,
+        message: String,
+    },
+    Obj {
+        entry: String,
+        source: ObjError,
+    },
+}
+
+impl SceneError {
+    fn invalid(entry: &str, message: impl Into<String>) -> Self {
+        SceneError::Invalid {
+            entry: entry.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Syntax { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Invalid { entry, message } => write!(f, "{}: {}", entry, message),
+            SceneError::Obj { entry, source } => write!(f, "{}: {}", entry, source),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Syntax { source, .. } => Some(source),
+            SceneError::Obj { source, .. } => Some(source),
+            SceneError::Invalid { .. } => None,
+        }
+    }
+}
+
+fn vec3([x, y, z]: [F; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct ImageDescription {
+    width: u32,
+    aspect_ratio: F,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    diffuse_depth: Option<u32>,
+    specular_depth: Option<u32>,
+    transmission_depth: Option<u32>,
+    volume_depth: Option<u32>,
+    russian_roulette_depth: u32,
+    background: [F; 3],
+    tone_map: String,
+    exposure: F,
+    white_point: F,
+    white_balance: Option<[F; 3]>,
+    // Enables adaptive sampling, `samples_per_pixel` is then the maximum
+    adaptive_threshold: Option<F>,
+    min_samples_per_pixel: u32,
+    sampler: String,
+    integrator: String,
+    occlusion_radius: F,
+    // AOV names, or all
+    aovs: Vec<String>,
+    // Denoises the finished image with this strength
+    denoise_strength: Option<F>,
+    filter: String,
+    // In pixels, the filter's own default without one
+    filter_radius: Option<F>,
+    seed: Option<u64>,
+    scene_seed: Option<u64>,
+}
+
+impl Default for ImageDescription {
+    fn default() -> Self {
+        let settings = RenderSettings::default();
+        let background = settings.background;
+        let tone_map = settings.tone_map;
+
+        Self {
+            width: settings.image_width,
+            aspect_ratio: settings.aspect_ratio(),
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            diffuse_depth: None,
+            specular_depth: None,
+            transmission_depth: None,
+            volume_depth: None,
+            russian_roulette_depth: settings.russian_roulette_depth,
+            background: [background.x(), background.y(), background.z()],
+            tone_map: tone_map.operator.to_string(),
+            exposure: tone_map.exposure,
+            white_point: tone_map.white_point,
+            white_balance: None,
+            adaptive_threshold: None,
+            min_samples_per_pixel: Adaptive::default().min_samples,
+            sampler: settings.sampler.to_string(),
+            integrator: settings.integrator.to_string(),
+            occlusion_radius: settings.occlusion_radius,
+            aovs: Vec::new(),
+            denoise_strength: None,
+            filter: settings.filter.kind.to_string(),
+            filter_radius: None,
+            seed: None,
+            scene_seed: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationDescription {
+    #[serde(default = "default_first_frame")]
+    first_frame: u32,
+    last_frame: u32,
+    #[serde(default = "default_shutter")]
+    shutter: F,
+}
+
+fn default_first_frame() -> u32 {
+    1
+}
+
+fn default_shutter() -> F {
+    0.5
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct Key<V> {
+    frame: F,
+    value: V,
+}
+
+// Parameters take a constant, or keys over frame numbers that are linearly
+// interpolated, e.g. fuzz = [{ frame = 1, value = 0.0 }, { frame = 48, value = 0.5 }]
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum Param<V> {
+    Constant(V),
+    Keyed(Vec<Key<V>>),
+}
+
+impl<V> From<V> for Param<V> {
+    fn from(value: V) -> Self {
+        Param::Constant(value)
+    }
+}
+
+impl<V: Copy> Param<V> {
+    fn at<W: Lerp>(
+        &self,
+        frame: F,
+        entry: &str,
+        convert: impl Fn(V) -> W,
+    ) -> Result<W, SceneError> {
+        match self {
+            Param::Constant(value) => Ok(convert(*value)),
+            Param::Keyed(keys) if keys.is_empty() => {
+                Err(SceneError::invalid(entry, "needs at least one key"))
+            }
+            Param::Keyed(keys) => {
+                let keys = keys
+                    .iter()
+                    .map(|key| (key.frame, convert(key.value)))
+                    .collect();
+                Ok(Track::new(keys).at(frame))
+            }
+        }
+    }
+}
+
+fn scalar(value: F) -> F {
+    value
+}
+
+fn default_v_up() -> Param<[F; 3]> {
+    [0.0, 1.0, 0.0].into()
+}
+
+fn default_aperture() -> Param<F> {
+    0.0.into()
+}
+
+fn default_focus_distance() -> Param<F> {
+    10.0.into()
+}
+
+fn default_time1() -> F {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    look_from: Param<[F; 3]>,
+    look_at: Param<[F; 3]>,
+    #[serde(default = "default_v_up")]
+    v_up: Param<[F; 3]>,
+    vfov: Param<F>,
+    #[serde(default = "default_aperture")]
+    aperture: Param<F>,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: Param<F>,
+    #[serde(default)]
+    time0: F,
+    #[serde(default = "default_time1")]
+    time1: F,
+}
+
+// A texture slot takes either an inline colour or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Colour(Param<[F; 3]>),
+    Named(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Solid { colour: Param<[F; 3]> },
+    Checkered { odd: TextureRef, even: TextureRef },
+    Noise { scale: Param<F> },
+    Image { filename: PathBuf },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: Param<[F; 3]>,
+        fuzz: Param<F>,
+    },
+    Dielectric {
+        refractive_index: Param<F>,
+    },
+    DiffuseLight {
+        emit: TextureRef,
+    },
+    Isotropic {
+        albedo: TextureRef,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum PlaneDescription {
+    Xy,
+    Yz,
+    Zx,
+}
+
+impl From<PlaneDescription> for Plane {
+    fn from(plane: PlaneDescription) -> Self {
+        match plane {
+            PlaneDescription::Xy => Plane::XY,
+            PlaneDescription::Yz => Plane::YZ,
+            PlaneDescription::Zx => Plane::ZX,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDescription {
+    axis: [F; 3],
+    angle: F,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDescription {
+    // Frame numbers in an animation
+    #[serde(alias = "frame")]
+    time: F,
+    #[serde(default)]
+    translate: [F; 3],
+    rotate: Option<RotationDescription>,
+    #[serde(default = "default_scale")]
+    scale: [F; 3],
+}
+
+fn default_scale() -> [F; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum BvhBuilder {
+    #[default]
+    Sah,
+    Midpoint,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        centre: [F; 3],
+        radius: F,
+        material: String,
+    },
+    MovingSphere {
+        centre0: [F; 3],
+        centre1: [F; 3],
+        time0: F,
+        time1: F,
+        radius: F,
+        material: String,
+    },
+    Rect {
+        plane: PlaneDescription,
+        a0: F,
+        a1: F,
+        b0: F,
+        b1: F,
+        k: F,
+        material: String,
+    },
+    Box {
+        min: [F; 3],
+        max: [F; 3],
+        material: String,
+    },
+    Triangle {
+        vertices: [[F; 3]; 3],
+        material: String,
+    },
+    Obj {
+        filename: PathBuf,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDescription>,
+        albedo: TextureRef,
+        density: F,
+    },
+    Translate {
+        offset: [F; 3],
+        object: Box<ObjectDescription>,
+    },
+    Rotate {
+        plane: PlaneDescription,
+        angle: F,
+        object: Box<ObjectDescription>,
+    },
+    // Scale, then rotate, then translate, or a full matrix instead
+    Transform {
+        scale: Option<[F; 3]>,
+        rotate: Option<RotationDescription>,
+        translate: Option<[F; 3]>,
+        matrix: Option<[[F; 4]; 4]>,
+        object: Box<ObjectDescription>,
+    },
+    // Keyframes interpolated over the shutter interval, for motion blur
+    Animated {
+        keyframes: Vec<KeyframeDescription>,
+        object: Box<ObjectDescription>,
+    },
+    Bvh {
+        #[serde(default)]
+        builder: BvhBuilder,
+        bins: Option<usize>,
+        leaf_size: Option<usize>,
+        objects: Vec<ObjectDescription>,
+    },
+    List {
+        objects: Vec<ObjectDescription>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    image: ImageDescription,
+    animation: Option<AnimationDescription>,
+    camera: CameraDescription,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+    // Relative file names in the scene are resolved against this
+    #[serde(skip)]
+    directory: PathBuf,
+}
+
+impl SceneFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::parse(&source, path)
+    }
+
+    // `source` as if read from `path`, which names it in errors and locates the
+    // files it refers to
+    pub fn parse<P: AsRef<Path>>(source: &str, path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let mut scene: SceneFile = toml::from_str(source).map_err(|source| SceneError::Syntax {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        scene.directory = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+        Ok(scene)
+    }
+
+    pub fn settings(&self) -> Result<RenderSettings, SceneError> {
+        let image = &self.image;
+        let operator = image
+            .tone_map
+            .parse()
+            .map_err(|message| SceneError::invalid("image.tone_map", message))?;
+
+        let sampler = image
+            .sampler
+            .parse()
+            .map_err(|message| SceneError::invalid("image.sampler", message))?;
+
+        let integrator = image
+            .integrator
+            .parse()
+            .map_err(|message| SceneError::invalid("image.integrator", message))?;
+
+        let aovs = image
+            .aovs
+            .join(",")
+            .parse()
+            .map_err(|message| SceneError::invalid("image.aovs", message))?;
+
+        let mut filter = Filter::new(
+            image
+                .filter
+                .parse()
+                .map_err(|message| SceneError::invalid("image.filter", message))?,
+        );
+        if let Some(radius) = image.filter_radius {
+            if !(radius > 0.0 && radius.is_finite()) {
+                return Err(SceneError::invalid(
+                    "image.filter_radius",
+                    "must be a positive number",
+                ));
+            }
+            filter.radius = radius;
+        }
+
+        let mut settings = RenderSettings {
+            image_width: image.width,
+            samples_per_pixel: image.samples_per_pixel,
+            max_depth: image.max_depth,
+            depth_limits: DepthLimits {
+                diffuse: image.diffuse_depth.unwrap_or(u32::MAX),
+                specular: image.specular_depth.unwrap_or(u32::MAX),
+                transmission: image.transmission_depth.unwrap_or(u32::MAX),
+                volume: image.volume_depth.unwrap_or(u32::MAX),
+            },
+            russian_roulette_depth: image.russian_roulette_depth,
+            background: vec3(image.background),
+            sampler,
+            integrator,
+            occlusion_radius: image.occlusion_radius,
+            aovs,
+            denoise: image.denoise_strength.map(|strength| Denoiser {
+                strength,
+                ..Denoiser::default()
+            }),
+            filter,
+            seed: image.seed,
+            scene_seed: image.scene_seed,
+            tone_map: ToneMap {
+                operator,
+                exposure: image.exposure,
+                white_point: image.white_point,
+                white_balance: image.white_balance.map(vec3),
+            },
+            ..RenderSettings::default()
+        };
+        settings.set_aspect_ratio(image.aspect_ratio);
+
+        if let Some(threshold) = image.adaptive_threshold {
+            if !(threshold > 0.0 && threshold.is_finite()) {
+                return Err(SceneError::invalid(
+                    "image.adaptive_threshold",
+                    "must be a positive number",
+                ));
+            }
+            settings.adaptive = Some(Adaptive {
+                threshold,
+                min_samples: image.min_samples_per_pixel,
+            });
+        }
+
+        settings.validate().map_err(|err| {
+            let entry = match err {
+                SettingsError::ImageSize { .. } if image.width < RenderSettings::MIN_IMAGE_SIZE => {
+                    "image.width"
+                }
+                SettingsError::ImageSize { .. } => "image.aspect_ratio",
+                SettingsError::NoSamples => "image.samples_per_pixel",
+            };
+            SceneError::invalid(entry, err.to_string())
+        })?;
+
+        Ok(settings)
+    }
+
+    // Emissive spheres, rects, boxes and triangles, optionally translated,
+    // rotated or transformed, can be sampled as lights
+    fn is_light(&self, object: &ObjectDescription) -> bool {
+        match object {
+            ObjectDescription::Sphere { material, .. }
+            | ObjectDescription::Rect { material, .. }
+            | ObjectDescription::Box { material, .. }
+            | ObjectDescription::Triangle { material, .. } => matches!(
+                self.materials.get(material),
+                Some(MaterialDescription::DiffuseLight { .. })
+            ),
+            ObjectDescription::Translate { object, .. }
+            | ObjectDescription::Rotate { object, .. }
+            | ObjectDescription::Transform { object, .. } => self.is_light(object),
+            _ => false,
+        }
+    }
+
+    // Every file that building the scene reads, image textures and OBJ files
+    // with what they in turn load, for telling apart scenes whose text is the same
+    pub fn files(&self) -> Result<Vec<PathBuf>, SceneError> {
+        let mut files: Vec<_> = self
+            .textures
+            .values()
+            .filter_map(|texture| match texture {
+                TextureDescription::Image { filename } => Some(self.directory.join(filename)),
+                _ => None,
+            })
+            .collect();
+        for (i, object) in self.objects.iter().enumerate() {
+            self.object_files(object, &format!("objects[{}]", i), &mut files)?;
+        }
+        Ok(files)
+    }
+
+    fn object_files(
+        &self,
+        object: &ObjectDescription,
+        entry: &str,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), SceneError> {
+        match object {
+            ObjectDescription::Obj { filename } => {
+                let path = self.directory.join(filename);
+                let dependencies = obj_dependencies(&path).map_err(|source| SceneError::Obj {
+                    entry: format!("{}.filename", entry),
+                    source,
+                })?;
+                files.push(path);
+                files.extend(dependencies);
+            }
+            ObjectDescription::ConstantMedium { boundary, .. } => {
+                self.object_files(boundary, &format!("{}.boundary", entry), files)?
+            }
+            ObjectDescription::Translate { object, .. }
+            | ObjectDescription::Rotate { object, .. }
+            | ObjectDescription::Transform { object, .. }
+            | ObjectDescription::Animated { object, .. } => {
+                self.object_files(object, &format!("{}.object", entry), files)?
+            }
+            ObjectDescription::Bvh { objects, .. } | ObjectDescription::List { objects } => {
+                for (i, object) in objects.iter().enumerate() {
+                    self.object_files(object, &format!("{}.objects[{}]", entry, i), files)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn timeline(&self) -> Result<Option<Timeline>, SceneError> {
+        let animation = match &self.animation {
+            Some(animation) => animation,
+            None => return Ok(None),
+        };
+
+        if animation.last_frame < animation.first_frame {
+            return Err(SceneError::invalid(
+                "animation.last_frame",
+                "must not be before first_frame",
+            ));
+        }
+        if !(0.0..=1.0).contains(&animation.shutter) {
+            return Err(SceneError::invalid(
+                "animation.shutter",
+                "must be a fraction of a frame between 0 and 1",
+            ));
+        }
+
+        Ok(Some(Timeline::new(
+            animation.first_frame,
+            animation.last_frame,
+            animation.shutter,
+        )))
+    }
+
+    // A still with the shutter interval of the camera
+    pub fn build(&self, aspect_ratio: F) -> Result<Scene, SceneError> {
+        self.build_at(aspect_ratio, self.camera.time0, self.camera.time1)
+    }
+
+    // One frame of the animation, with parameters taken when its shutter opens
+    pub fn build_frame(&self, aspect_ratio: F, frame: u32) -> Result<Scene, SceneError> {
+        let timeline = self
+            .timeline()?
+            .ok_or_else(|| SceneError::invalid("animation", "scene has no animation"))?;
+        let (time0, time1) = timeline.shutter_interval(frame);
+
+        self.build_at(aspect_ratio, time0, time1)
+    }
+
+    fn build_at(&self, aspect_ratio: F, time0: F, time1: F) -> Result<Scene, SceneError> {
+        let camera = &self.camera;
+        let frame = time0;
+
+        let camera = Camera::new(
+            camera.look_from.at(frame, "camera.look_from", vec3)?,
+            camera.look_at.at(frame, "camera.look_at", vec3)?,
+            camera.v_up.at(frame, "camera.v_up", vec3)?,
+            camera.vfov.at(frame, "camera.vfov", scalar)?,
+            aspect_ratio,
+            camera.aperture.at(frame, "camera.aperture", scalar)?,
+            camera
+                .focus_distance
+                .at(frame, "camera.focus_distance", scalar)?,
+            time0,
+            time1,
+        );
+
+        let mut builder = Builder {
+            scene: self,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            resolving: HashSet::new(),
+            frame,
+            time0,
+            time1,
+        };
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+
+        for (i, description) in self.objects.iter().enumerate() {
+            let object = builder.object(description, &format!("objects[{}]", i))?;
+            if self.is_light(description) {
+                lights.add(Arc::clone(&object));
+            }
+            world.add(object);
+        }
+
+        Ok(Scene::with_lights(camera, world, lights))
+    }
+}
+
+// Resolves named references, building each texture and material at most once
+struct Builder<'a> {
+    scene: &'a SceneFile,
+    textures: HashMap<String, Arc<T>>,
+    materials: HashMap<String, Arc<M>>,
+    resolving: HashSet<String>,
+    // Animated parameters are evaluated at this frame
+    frame: F,
+    time0: F,
+    time1: F,
+}
+
+impl<'a> Builder<'a> {
+    fn kind_of(&self, name: &str) -> Option<&'static str> {
+        if self.scene.textures.contains_key(name) {
+            Some("texture")
+        } else if self.scene.materials.contains_key(name) {
+            Some("material")
+        } else {
+            None
+        }
+    }
+
+    fn unknown(&self, name: &str, expected: &str, entry: &str) -> SceneError {
+        match self.kind_of(name) {
+            Some(kind) => SceneError::invalid(
+                entry,
+                format!("'{}' is a {}, expected a {}", name, kind, expected),
+            ),
+            None => SceneError::invalid(entry, format!("unknown {} '{}'", expected, name)),
+        }
+    }
+
+    fn texture_ref(&mut self, texture: &TextureRef, entry: &str) -> Result<Arc<T>, SceneError> {
+        match texture {
+            TextureRef::Colour(colour) => {
+                let colour = colour.at(self.frame, entry, vec3)?;
+                Ok(Arc::new(SolidColour::new(colour)))
+            }
+            TextureRef::Named(name) => self.texture(name, entry),
+        }
+    }
+
+    fn texture(&mut self, name: &str, entry: &str) -> Result<Arc<T>, SceneError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(Arc::clone(texture));
+        }
+
+        let scene = self.scene;
+        let description = match scene.textures.get(name) {
+            Some(description) => description,
+            None => return Err(self.unknown(name, "texture", entry)),
+        };
+
+        if !self.resolving.insert(name.to_string()) {
+            return Err(SceneError::invalid(
+                entry,
+                format!("texture '{}' references itself", name),
+            ));
+        }
+
+        let entry = format!("textures.{}", name);
+        let texture: Arc<T> = match description {
+            TextureDescription::Solid { colour } => {
+                let colour = colour.at(self.frame, &format!("{}.colour", entry), vec3)?;
+                Arc::new(SolidColour::new(colour))
+            }
+            TextureDescription::Checkered { odd, even } => {
+                let odd = self.texture_ref(odd, &format!("{}.odd", entry))?;
+                let even = self.texture_ref(even, &format!("{}.even", entry))?;
+                Arc::new(Checkered::new(odd, even))
+            }
+            TextureDescription::Noise { scale } => {
+                let scale = scale.at(self.frame, &format!("{}.scale", entry), scalar)?;
+                Arc::new(Noise::new(scale))
+            }
+            TextureDescription::Image { filename } => {
+                let path = scene.directory.join(filename);
+                let image = Image::open(&path).map_err(|err| {
+                    SceneError::invalid(
+                        &format!("{}.filename", entry),
+                        format!("couldn't open image {}: {}", path.display(), err),
+                    )
+                })?;
+                Arc::new(image)
+            }
+        };
+
+        self.resolving.remove(name);
+        self.textures.insert(name.to_string(), Arc::clone(&texture));
+
+        Ok(texture)
+    }
+
+    fn material(&mut self, name: &str, entry: &str) -> Result<Arc<M>, SceneError> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(Arc::clone(material));
+        }
+
+        let description = match self.scene.materials.get(name) {
+            Some(description) => description,
+            None => return Err(self.unknown(name, "material", entry)),
+        };
+
+        let entry = format!("materials.{}", name);
+        let material: Arc<M> = match description {
+            MaterialDescription::Lambertian { albedo } => Arc::new(Lambertian::new(
+                self.texture_ref(albedo, &format!("{}.albedo", entry))?,
+            )),
+            MaterialDescription::Metal { albedo, fuzz } => {
+                let albedo = albedo.at(self.frame, &format!("{}.albedo", entry), vec3)?;
+                let fuzz = fuzz.at(self.frame, &format!("{}.fuzz", entry), scalar)?;
+                Arc::new(Metal::new(albedo, fuzz))
+            }
+            MaterialDescription::Dielectric { refractive_index } => {
+                let refractive_index = refractive_index.at(
+                    self.frame,
+                    &format!("{}.refractive_index", entry),
+                    scalar,
+                )?;
+                Arc::new(Dielectric::new(refractive_index))
+            }
+            MaterialDescription::DiffuseLight { emit } => Arc::new(DiffuseLight::new(
+                self.texture_ref(emit, &format!("{}.emit", entry))?,
+            )),
+            MaterialDescription::Isotropic { albedo } => Arc::new(Isotropic::new(
+                self.texture_ref(albedo, &format!("{}.albedo", entry))?,
+            )),
+        };
+
+        self.materials
+            .insert(name.to_string(), Arc::clone(&material));
+
+        Ok(material)
+    }
+
+    fn list(
+        &mut self,
+        objects: &[ObjectDescription],
+        entry: &str,
+    ) -> Result<HittableList, SceneError> {
+        let mut list = HittableList::new();
+        for (i, object) in objects.iter().enumerate() {
+            list.add(self.object(object, &format!("{}.objects[{}]", entry, i))?);
+        }
+
+        Ok(list)
+    }
+
+    fn object(&mut self, object: &ObjectDescription, entry: &str) -> Result<Arc<H>, SceneError> {
+        let material_entry = format!("{}.material", entry);
+
+        let object: Arc<H> = match object {
+            ObjectDescription::Sphere {
+                centre,
+                radius,
+                material,
+            } => Arc::new(Sphere::new(
+                vec3(*centre),
+                *radius,
+                self.material(material, &material_entry)?,
+            )),
+            ObjectDescription::MovingSphere {
+                centre0,
+                centre1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => Arc::new(MovingSphere::new(
+                vec3(*centre0),
+                vec3(*centre1),
+                *time0,
+                *time1,
+                *radius,
+                self.material(material, &material_entry)?,
+            )),
+            ObjectDescription::Rect {
+                plane,
+                a0,
+                a1,
+                b0,
+                b1,
+                k,
+                material,
+            } => Arc::new(AARect::new(
+                (*plane).into(),
+                *a0,
+                *a1,
+                *b0,
+                *b1,
+                *k,
+                self.material(material, &material_entry)?,
+            )),
+            ObjectDescription::Box { min, max, material } => Arc::new(AABox::new(
+                vec3(*min),
+                vec3(*max),
+                self.material(material, &material_entry)?,
+            )),
+            ObjectDescription::Triangle { vertices, material } => Arc::new(Triangle::new(
+                vec3(vertices[0]),
+                vec3(vertices[1]),
+                vec3(vertices[2]),
+                self.material(material, &material_entry)?,
+            )),
+            ObjectDescription::Obj { filename } => {
+                let path = self.scene.directory.join(filename);
+                let meshes =
+                    load_obj(&path, self.time0, self.time1).map_err(|source| SceneError::Obj {
+                        entry: format!("{}.filename", entry),
+                        source,
+                    })?;
+                Arc::new(meshes)
+            }
+            ObjectDescription::ConstantMedium {
+                boundary,
+                albedo,
+                density,
+            } => {
+                let boundary = self.object(boundary, &format!("{}.boundary", entry))?;
+                let albedo = self.texture_ref(albedo, &format!("{}.albedo", entry))?;
+                Arc::new(ConstantMedium::new(boundary, albedo, *density))
+            }
+            ObjectDescription::Translate { offset, object } => {
+                let object = self.object(object, &format!("{}.object", entry))?;
+                Arc::new(Translate::new(object, vec3(*offset)))
+            }
+            ObjectDescription::Rotate {
+                plane,
+                angle,
+                object,
+            } => {
+                let object_entry = format!("{}.object", entry);
+                let object = self.object(object, &object_entry)?;
+                if object.bounding_box(self.time0, self.time1).is_none() {
+                    return Err(SceneError::invalid(
+                        &object_entry,
+                        "cannot rotate an object without a bounding box",
+                    ));
+                }
+                Arc::new(Rotate::new(
+                    object,
+                    (*plane).into(),
+                    *angle,
+                    self.time0,
+                    self.time1,
+                ))
+            }
+            ObjectDescription::Transform {
+                scale,
+                rotate,
+                translate,
+                matrix,
+                object,
+            } => {
+                let object = self.object(object, &format!("{}.object", entry))?;
+
+                let matrix = match matrix {
+                    Some(_) if scale.is_some() || rotate.is_some() || translate.is_some() => {
+                        return Err(SceneError::invalid(
+                            entry,
+                            "matrix cannot be combined with scale, rotate or translate",
+                        ));
+                    }
+                    Some(rows) => Mat4::new(*rows),
+                    None => {
+                        let mut matrix = Mat4::identity();
+                        if let Some(scale) = scale {
+                            matrix = Mat4::scaling(vec3(*scale));
+                        }
+                        if let Some(rotate) = rotate {
+                            if vec3(rotate.axis).near_zero() {
+                                return Err(SceneError::invalid(
+                                    &format!("{}.rotate.axis", entry),
+                                    "rotation axis must not be zero",
+                                ));
+                            }
+                            matrix = Mat4::rotation(vec3(rotate.axis), rotate.angle) * matrix;
+                        }
+                        if let Some(translate) = translate {
+                            matrix = Mat4::translation(vec3(*translate)) * matrix;
+                        }
+                        matrix
+                    }
+                };
+
+                if matrix.inverse().is_none() {
+                    return Err(SceneError::invalid(entry, "transform is not invertible"));
+                }
+                Arc::new(Transform::new(object, matrix))
+            }
+            ObjectDescription::Animated { keyframes, object } => {
+                let object = self.object(object, &format!("{}.object", entry))?;
+                if keyframes.is_empty() {
+                    return Err(SceneError::invalid(entry, "needs at least one keyframe"));
+                }
+
+                let mut frames = Vec::new();
+                for (i, keyframe) in keyframes.iter().enumerate() {
+                    let keyframe_entry = format!("{}.keyframes[{}]", entry, i);
+
+                    let rotation = match &keyframe.rotate {
+                        Some(rotate) if vec3(rotate.axis).near_zero() => {
+                            return Err(SceneError::invalid(
+                                &format!("{}.rotate.axis", keyframe_entry),
+                                "rotation axis must not be zero",
+                            ));
+                        }
+                        Some(rotate) => {
+                            Quaternion::from_axis_angle(vec3(rotate.axis), rotate.angle)
+                        }
+                        None => Quaternion::identity(),
+                    };
+                    if keyframe.scale.contains(&0.0) {
+                        return Err(SceneError::invalid(
+                            &format!("{}.scale", keyframe_entry),
+                            "scale must not be zero",
+                        ));
+                    }
+
+                    frames.push(Keyframe::new(
+                        keyframe.time,
+                        vec3(keyframe.translate),
+                        rotation,
+                        vec3(keyframe.scale),
+                    ));
+                }
+
+                Arc::new(AnimatedTransform::new(object, frames))
+            }
+            ObjectDescription::Bvh {
+                builder,
+                bins,
+                leaf_size,
+                objects,
+            } => {
+                let list = self.list(objects, entry)?;
+                if objects.is_empty() {
+                    return Err(SceneError::invalid(entry, "bvh needs at least one object"));
+                }
+                if list.bounding_box(self.time0, self.time1).is_none() {
+                    return Err(SceneError::invalid(
+                        entry,
+                        "every object in a bvh needs a bounding box",
+                    ));
+                }
+
+                let mut options = SahOptions::default();
+                if let Some(bins) = *bins {
+                    if bins < 2 {
+                        return Err(SceneError::invalid(entry, "bvh needs at least 2 bins"));
+                    }
+                    options.bins = bins;
+                }
+                if let Some(leaf_size) = *leaf_size {
+                    if leaf_size == 0 {
+                        return Err(SceneError::invalid(entry, "bvh leaf_size must be positive"));
+                    }
+                    options.max_leaf_size = leaf_size;
+                }
+
+                match builder {
+                    BvhBuilder::Sah => {
+                        Arc::new(LinearBVH::new(&list, options, self.time0, self.time1))
+                    }
+                    BvhBuilder::Midpoint => {
+                        Arc::new(BVH::new(&list, 0, objects.len(), self.time0, self.time1))
+                    }
+                }
+            }
+            ObjectDescription::List { objects } => Arc::new(self.list(objects, entry)?),
+        };
+
+        Ok(object)
+    }
+}
@@ -5,14 +5,104 @@ use crate::{
     texture::*, transform::*, vec3::*,
 };
 
-// Image Constants
-pub const ASPECT_RATIO: F = 1.0;
-pub const IMAGE_HEIGHT: u32 = 800;
-pub const IMAGE_WIDTH: u32 = (IMAGE_HEIGHT as F * ASPECT_RATIO) as u32;
-pub const SAMPLES_PER_PIXEL: u32 = 1000;
-pub const MAX_DEPTH: u32 = 50;
-
-pub fn default_camera() -> Camera {
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    // Emissive objects, also in `world`, that the integrator samples directly
+    pub lights: HittableList,
+}
+
+impl Scene {
+    pub fn new(camera: Camera, world: HittableList) -> Self {
+        Self {
+            camera,
+            world,
+            lights: HittableList::new(),
+        }
+    }
+
+    pub fn with_lights(camera: Camera, world: HittableList, lights: HittableList) -> Self {
+        Self {
+            camera,
+            world,
+            lights,
+        }
+    }
+}
+
+pub struct BuiltinScene {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub background: [F; 3],
+    pub build: fn(F) -> Scene,
+}
+
+impl BuiltinScene {
+    pub fn background(&self) -> Colour {
+        let [r, g, b] = self.background;
+        Colour::new(r, g, b)
+    }
+}
+
+const SKY: [F; 3] = [0.7, 0.8, 1.0];
+const BLACK: [F; 3] = [0.0, 0.0, 0.0];
+
+pub const SCENES: &[BuiltinScene] = &[
+    BuiltinScene {
+        name: "random_scene",
+        description: "Final image of Ray Tracing in One Weekend",
+        background: SKY,
+        build: _random_scene,
+    },
+    BuiltinScene {
+        name: "two_spheres",
+        description: "Two checkered spheres",
+        background: SKY,
+        build: _two_spheres,
+    },
+    BuiltinScene {
+        name: "two_perlin_spheres",
+        description: "Two spheres with Perlin noise textures",
+        background: SKY,
+        build: _two_perlin_spheres,
+    },
+    BuiltinScene {
+        name: "earth",
+        description: "Image-textured globe",
+        background: SKY,
+        build: _earth,
+    },
+    BuiltinScene {
+        name: "simple_light",
+        description: "Perlin spheres lit by a rectangle light",
+        background: BLACK,
+        build: _simple_light,
+    },
+    BuiltinScene {
+        name: "cornell_box",
+        description: "Cornell box with two rotated boxes",
+        background: BLACK,
+        build: _cornell_box,
+    },
+    BuiltinScene {
+        name: "cornell_smoke",
+        description: "Cornell box with boxes of smoke",
+        background: BLACK,
+        build: _cornell_smoke,
+    },
+    BuiltinScene {
+        name: "final_scene",
+        description: "Final image of Ray Tracing: The Next Week",
+        background: BLACK,
+        build: _final_scene,
+    },
+];
+
+pub fn find_scene(name: &str) -> Option<&'static BuiltinScene> {
+    SCENES.iter().find(|scene| scene.name == name)
+}
+
+pub fn default_camera(aspect_ratio: F) -> Camera {
     let look_from = Point3::new(13.0, 2.0, 3.0);
     let look_at = Point3::zero();
     let vfov = 20.0;
@@ -20,22 +110,20 @@ pub fn default_camera() -> Camera {
     let distance_to_focus = 10.0;
     let aperture = 0.1;
 
-    let camera = Camera::new(
+    Camera::new(
         look_from,
         look_at,
         v_up,
         vfov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         distance_to_focus,
         0.0,
         1.0,
-    );
-
-    camera
+    )
 }
 
-pub fn _random_scene() -> (Camera, HittableList) {
+pub fn _random_scene(aspect_ratio: F) -> Scene {
     let mut world = HittableList::new();
 
     let ground_texture = Arc::new(Checkered::new(
@@ -106,10 +194,10 @@ pub fn _random_scene() -> (Camera, HittableList) {
         metal,
     )));
 
-    (default_camera(), world)
+    Scene::new(default_camera(aspect_ratio), world)
 }
 
-pub fn _two_spheres() -> (Camera, HittableList) {
+pub fn _two_spheres(aspect_ratio: F) -> Scene {
     let mut world = HittableList::new();
 
     let checkered = Arc::new(Checkered::colour(
@@ -130,10 +218,10 @@ pub fn _two_spheres() -> (Camera, HittableList) {
         Arc::clone(&material),
     )));
 
-    (default_camera(), world)
+    Scene::new(default_camera(aspect_ratio), world)
 }
 
-pub fn _two_perlin_spheres() -> (Camera, HittableList) {
+pub fn _two_perlin_spheres(aspect_ratio: F) -> Scene {
     let mut world = HittableList::new();
 
     let perlin = Arc::new(Noise::new(4.0));
@@ -150,10 +238,10 @@ pub fn _two_perlin_spheres() -> (Camera, HittableList) {
         Arc::clone(&material),
     )));
 
-    (default_camera(), world)
+    Scene::new(default_camera(aspect_ratio), world)
 }
 
-pub fn _earth() -> (Camera, HittableList) {
+pub fn _earth(aspect_ratio: F) -> Scene {
     let mut world = HittableList::new();
 
     let earth = Arc::new(Image::new("textures/earthmap.jpg"));
@@ -161,10 +249,10 @@ pub fn _earth() -> (Camera, HittableList) {
 
     world.add(Arc::new(Sphere::new(Point3::zero(), 2.0, material)));
 
-    (default_camera(), world)
+    Scene::new(default_camera(aspect_ratio), world)
 }
 
-pub fn _simple_light() -> (Camera, HittableList) {
+pub fn _simple_light(aspect_ratio: F) -> Scene {
     let look_from = Point3::new(26.0, 3.0, 6.0);
     let look_at = Point3::new(0.0, 2.0, 0.0);
     let vfov = 20.0;
@@ -177,7 +265,7 @@ pub fn _simple_light() -> (Camera, HittableList) {
         look_at,
         v_up,
         vfov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         distance_to_focus,
         0.0,
@@ -202,20 +290,16 @@ pub fn _simple_light() -> (Camera, HittableList) {
 
     let diff_light: Arc<M> = Arc::new(DiffuseLight::rgb(4.0, 4.0, 4.0));
 
-    world.add(Arc::new(AARect::new(
-        Plane::XY,
-        3.0,
-        5.0,
-        1.0,
-        3.0,
-        -2.0,
-        diff_light,
-    )));
+    let light: Arc<H> = Arc::new(AARect::new(Plane::XY, 3.0, 5.0, 1.0, 3.0, -2.0, diff_light));
+    world.add(Arc::clone(&light));
 
-    (camera, world)
+    let mut lights = HittableList::new();
+    lights.add(light);
+
+    Scene::with_lights(camera, world, lights)
 }
 
-pub fn _cornell_box() -> (Camera, HittableList) {
+pub fn _cornell_box(aspect_ratio: F) -> Scene {
     let look_from = Point3::new(278.0, 278.0, -800.0);
     let look_at = Point3::new(278.0, 278.0, 0.0);
     let v_up = Point3::new(0.0, 1.0, 0.0);
@@ -228,7 +312,7 @@ pub fn _cornell_box() -> (Camera, HittableList) {
         look_at,
         v_up,
         vfov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         distance_to_focus,
         0.0,
@@ -261,7 +345,7 @@ pub fn _cornell_box() -> (Camera, HittableList) {
         0.0,
         Arc::clone(&red),
     )));
-    world.add(Arc::new(AARect::new(
+    let light: Arc<H> = Arc::new(AARect::new(
         Plane::ZX,
         213.0,
         343.0,
@@ -269,7 +353,11 @@ pub fn _cornell_box() -> (Camera, HittableList) {
         332.0,
         554.0,
         Arc::clone(&light),
-    )));
+    ));
+    world.add(Arc::clone(&light));
+
+    let mut lights = HittableList::new();
+    lights.add(light);
     world.add(Arc::new(AARect::new(
         Plane::ZX,
         0.0,
@@ -310,21 +398,21 @@ pub fn _cornell_box() -> (Camera, HittableList) {
     ));
 
     let box1 = Translate::new(
-        Arc::new(Rotate::new(box1, Plane::ZX, -15.0)),
+        Arc::new(Rotate::new(box1, Plane::ZX, -15.0, 0.0, 1.0)),
         Vec3::new(265.0, 0.0, 295.0),
     );
     let box2 = Translate::new(
-        Arc::new(Rotate::new(box2, Plane::ZX, 18.0)),
+        Arc::new(Rotate::new(box2, Plane::ZX, 18.0, 0.0, 1.0)),
         Vec3::new(130.0, 0.0, 65.0),
     );
 
     world.add(Arc::new(box1));
     world.add(Arc::new(box2));
 
-    (camera, world)
+    Scene::with_lights(camera, world, lights)
 }
 
-pub fn _cornell_smoke() -> (Camera, HittableList) {
+pub fn _cornell_smoke(aspect_ratio: F) -> Scene {
     let look_from = Point3::new(278.0, 278.0, -800.0);
     let look_at = Point3::new(278.0, 278.0, 0.0);
     let v_up = Point3::new(0.0, 1.0, 0.0);
@@ -337,7 +425,7 @@ pub fn _cornell_smoke() -> (Camera, HittableList) {
         look_at,
         v_up,
         vfov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         distance_to_focus,
         0.0,
@@ -370,7 +458,7 @@ pub fn _cornell_smoke() -> (Camera, HittableList) {
         0.0,
         Arc::clone(&red),
     )));
-    world.add(Arc::new(AARect::new(
+    let light: Arc<H> = Arc::new(AARect::new(
         Plane::ZX,
         113.0,
         443.0,
@@ -378,7 +466,11 @@ pub fn _cornell_smoke() -> (Camera, HittableList) {
         432.0,
         554.0,
         Arc::clone(&light),
-    )));
+    ));
+    world.add(Arc::clone(&light));
+
+    let mut lights = HittableList::new();
+    lights.add(light);
     world.add(Arc::new(AARect::new(
         Plane::ZX,
         0.0,
@@ -419,11 +511,11 @@ pub fn _cornell_smoke() -> (Camera, HittableList) {
     ));
 
     let box1 = Translate::new(
-        Arc::new(Rotate::new(box1, Plane::ZX, -15.0)),
+        Arc::new(Rotate::new(box1, Plane::ZX, -15.0, 0.0, 1.0)),
         Vec3::new(265.0, 0.0, 295.0),
     );
     let box2 = Translate::new(
-        Arc::new(Rotate::new(box2, Plane::ZX, 18.0)),
+        Arc::new(Rotate::new(box2, Plane::ZX, 18.0, 0.0, 1.0)),
         Vec3::new(130.0, 0.0, 65.0),
     );
 
@@ -440,10 +532,10 @@ pub fn _cornell_smoke() -> (Camera, HittableList) {
         0.01,
     )));
 
-    (camera, world)
+    Scene::with_lights(camera, world, lights)
 }
 
-pub fn _final_scene() -> (Camera, HittableList) {
+pub fn _final_scene(aspect_ratio: F) -> Scene {
     let look_from = Point3::new(478.0, 278.0, -600.0);
     let look_at = Point3::new(278.0, 278.0, 0.0);
     let v_up = Point3::new(0.0, 1.0, 0.0);
@@ -456,7 +548,7 @@ pub fn _final_scene() -> (Camera, HittableList) {
         look_at,
         v_up,
         vfov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         distance_to_focus,
         0.0,
@@ -489,16 +581,15 @@ pub fn _final_scene() -> (Camera, HittableList) {
         }
     }
 
-    world.add(Arc::new(BVH::new(
+    world.add(Arc::new(LinearBVH::new(
         &ground_boxes,
-        0,
-        BOXES_PER_SIDE * BOXES_PER_SIDE,
+        SahOptions::default(),
         0.0,
         1.0,
     )));
 
     let light: Arc<M> = Arc::new(DiffuseLight::rgb(7.0, 7.0, 7.0));
-    world.add(Arc::new(AARect::new(
+    let light: Arc<H> = Arc::new(AARect::new(
         Plane::ZX,
         123.0,
         423.0,
@@ -506,7 +597,11 @@ pub fn _final_scene() -> (Camera, HittableList) {
         412.0,
         554.0,
         Arc::clone(&light),
-    )));
+    ));
+    world.add(Arc::clone(&light));
+
+    let mut lights = HittableList::new();
+    lights.add(light);
 
     let centre1 = Point3::new(400.0, 400.0, 400.0);
     let centre2 = centre1 + Point3::new(30.0, 0.0, 0.0);
@@ -582,12 +677,12 @@ pub fn _final_scene() -> (Camera, HittableList) {
         )));
     }
 
-    let boxes = Arc::new(BVH::new(&boxes, 0, N, 0.0, 1.0));
+    let boxes = Arc::new(LinearBVH::new(&boxes, SahOptions::default(), 0.0, 1.0));
 
     world.add(Arc::new(Translate::new(
-        Arc::new(Rotate::new(boxes, Plane::ZX, -15.0)),
+        Arc::new(Rotate::new(boxes, Plane::ZX, -15.0, 0.0, 1.0)),
         Vec3::new(-100.0, 270.0, 395.0),
     )));
 
-    (camera, world)
+    Scene::with_lights(camera, world, lights)
 }
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{aabb::AABB, hittable::*, ray::*, texture::*, vec3::*};
+use crate::{aabb::AABB, hittable::*, ray::*, sampler::*, texture::*, vec3::*};
 
 pub struct Sphere {
     centre: Point3,
@@ -89,4 +89,51 @@ impl Hittable for Sphere {
             self.centre + radius_vector,
         ))
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        const PI: F = std::f64::consts::PI;
+
+        if self
+            .hit(&Ray::new(origin, direction, 0.0), 0.001, F::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.centre - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            // Inside the sphere every direction hits it, sampled uniformly
+            1.0 / (4.0 * PI)
+        } else {
+            let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+            1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+        }
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        const PI: F = std::f64::consts::PI;
+
+        let direction = self.centre - origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        let u = sampler.next_2d();
+        if distance_squared <= radius_squared {
+            return sample_sphere(u);
+        }
+
+        // Uniform direction within the cone subtended by the sphere
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + u.0 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * u.1;
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        Onb::from_w(direction).to_world(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        visit(&self.material)
+    }
 }
@@ -1,6 +1,6 @@
-use image::{open, RgbImage};
+use image::{open, ImageResult, RgbImage};
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use crate::{hittable::T, perlin::*, vec3::*};
 
@@ -108,12 +108,14 @@ pub struct Image {
 
 impl Image {
     pub fn new(filename: &str) -> Self {
-        let rgb = open(filename)
-            .expect(&format!("Couldn't open image file {}", filename))
-            .into_rgb8();
+        Image::open(filename).unwrap_or_else(|_| panic!("Couldn't open image file {}", filename))
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let rgb = open(path)?.into_rgb8();
         let (width, height) = rgb.dimensions();
 
-        Self { rgb, width, height }
+        Ok(Self { rgb, width, height })
     }
 }
 
@@ -122,8 +124,9 @@ impl Texture for Image {
         let u = clamp(tp.u(), 0.0, 1.0);
         let v = 1.0 - clamp(tp.v(), 0.0, 1.0);
 
-        let i = (u * self.width as F).floor() as u32;
-        let j = (v * self.height as F).floor() as u32;
+        // u or v of exactly 1.0 would otherwise index one past the edge
+        let i = ((u * self.width as F).floor() as u32).min(self.width - 1);
+        let j = ((v * self.height as F).floor() as u32).min(self.height - 1);
 
         let colour_scale = 1.0 / 255.0;
 
@@ -0,0 +1,88 @@
+use std::ops::RangeInclusive;
+
+use crate::vec3::*;
+
+pub trait Lerp: Copy {
+    fn lerp(a: Self, b: Self, t: F) -> Self;
+}
+
+impl Lerp for F {
+    fn lerp(a: Self, b: Self, t: F) -> Self {
+        a * (1.0 - t) + b * t
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(a: Self, b: Self, t: F) -> Self {
+        a * (1.0 - t) + b * t
+    }
+}
+
+// Piecewise linear value over frame numbers, held constant outside its keys
+#[derive(Clone, Debug)]
+pub struct Track<V> {
+    keys: Vec<(F, V)>,
+}
+
+impl<V: Lerp> Track<V> {
+    // Panics without keys
+    pub fn new(mut keys: Vec<(F, V)>) -> Self {
+        assert!(!keys.is_empty(), "Track needs at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self { keys }
+    }
+
+    pub fn constant(value: V) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    pub fn at(&self, frame: F) -> V {
+        let next = self.keys.partition_point(|&(key, _)| key <= frame);
+
+        if next == 0 {
+            self.keys[0].1
+        } else if next == self.keys.len() {
+            self.keys[next - 1].1
+        } else {
+            let (f0, v0) = self.keys[next - 1];
+            let (f1, v1) = self.keys[next];
+            V::lerp(v0, v1, (frame - f0) / (f1 - f0))
+        }
+    }
+}
+
+// Frames of an animation. Scene time is measured in frames, so frame f opens
+// its shutter at time f and closes it at f + shutter.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeline {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    // Fraction of a frame the shutter stays open, 0.5 is a 180 degree shutter
+    pub shutter: F,
+}
+
+impl Timeline {
+    pub fn new(first_frame: u32, last_frame: u32, shutter: F) -> Self {
+        Self {
+            first_frame,
+            last_frame,
+            shutter,
+        }
+    }
+
+    pub fn frames(&self) -> RangeInclusive<u32> {
+        self.first_frame..=self.last_frame
+    }
+
+    pub fn shutter_interval(&self, frame: u32) -> (F, F) {
+        let open = frame as F;
+        (open, open + self.shutter)
+    }
+}
+
+pub fn frame_file_name(frame: u32, extension: &str) -> String {
+    format!("frame_{:04}.{}", frame, extension)
+}
@@ -0,0 +1,165 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::vec3::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Hable,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 5] = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::ExtendedReinhard,
+        ToneMapOperator::Aces,
+        ToneMapOperator::Hable,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::ExtendedReinhard => "extended_reinhard",
+            ToneMapOperator::Aces => "aces",
+            ToneMapOperator::Hable => "hable",
+        }
+    }
+}
+
+impl Display for ToneMapOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ToneMapOperator::ALL
+            .iter()
+            .find(|operator| operator.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = ToneMapOperator::ALL.iter().map(|op| op.name()).collect();
+                format!(
+                    "unknown tone map operator '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// Post-processing from linear film radiance to display-referred sRGB in [0, 1]
+#[derive(Clone, Copy)]
+pub struct ToneMap {
+    pub operator: ToneMapOperator,
+    // Exposure compensation in stops, each stop doubles the radiance
+    pub exposure: F,
+    // Radiance mapped to white by the extended Reinhard operator
+    pub white_point: F,
+    // Colour of the illuminant that should come out neutral
+    pub white_balance: Option<Colour>,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+            white_balance: None,
+        }
+    }
+}
+
+pub fn luminance(colour: Colour) -> F {
+    0.2126 * colour.x() + 0.7152 * colour.y() + 0.0722 * colour.z()
+}
+
+fn map_channels(colour: Colour, f: impl Fn(F) -> F) -> Colour {
+    Colour::new(f(colour.x()), f(colour.y()), f(colour.z()))
+}
+
+// Uncharted 2 filmic curve, John Hable
+fn hable_partial(x: F) -> F {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+// Fitted ACES filmic curve, Krzysztof Narkowicz
+fn aces(x: F) -> F {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    (x * (a * x + b)) / (x * (c * x + d) + e)
+}
+
+pub fn srgb_encode(x: F) -> F {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Inverse of `srgb_encode`
+pub fn srgb_decode(x: F) -> F {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl ToneMap {
+    pub fn white_balance(&self, colour: Colour) -> Colour {
+        match self.white_balance {
+            // Von Kries style scaling in RGB, keeping the illuminant's luminance
+            Some(white) if white.x() > 0.0 && white.y() > 0.0 && white.z() > 0.0 => {
+                let l = luminance(white);
+                Colour::new(
+                    colour.x() * l / white.x(),
+                    colour.y() * l / white.y(),
+                    colour.z() * l / white.z(),
+                )
+            }
+            _ => colour,
+        }
+    }
+
+    // Linear radiance to linear display values in [0, 1]
+    pub fn tone_map(&self, colour: Colour) -> Colour {
+        let colour = self.white_balance(colour) * 2.0_f64.powf(self.exposure);
+        let colour = map_channels(colour, |x| x.max(0.0));
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => colour,
+            ToneMapOperator::Reinhard => map_channels(colour, |x| x / (1.0 + x)),
+            ToneMapOperator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                map_channels(colour, |x| x * (1.0 + x / white_squared) / (1.0 + x))
+            }
+            ToneMapOperator::Aces => map_channels(colour, aces),
+            ToneMapOperator::Hable => {
+                let exposure_bias = 2.0;
+                let white_scale = 1.0 / hable_partial(11.2);
+                map_channels(colour, |x| hable_partial(x * exposure_bias) * white_scale)
+            }
+        };
+
+        map_channels(mapped, |x| clamp(x, 0.0, 1.0))
+    }
+
+    // Linear radiance to sRGB encoded values in [0, 1]
+    pub fn encode(&self, colour: Colour) -> Colour {
+        map_channels(self.tone_map(colour), srgb_encode)
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{aabb::*, aarect::*, hittable::*, ray::*, vec3::*};
+use crate::{aabb::*, aarect::*, hittable::*, ray::*, sampler::*, vec3::*};
 
 pub struct Translate {
     object: Arc<H>,
@@ -15,7 +15,7 @@ impl Translate {
 
 impl Hittable for Translate {
     fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
-        let translated_ray = Ray::new(ray.origin() - self.offset, ray.direction(), ray.time());
+        let translated_ray = ray.moved(ray.origin() - self.offset, ray.direction());
 
         if let Some(mut hit_record) = self.object.hit(&translated_ray, t_min, t_max) {
             hit_record.set_p(hit_record.p() + self.offset);
@@ -27,64 +27,88 @@ impl Hittable for Translate {
     }
 
     fn bounding_box(&self, time0: F, time1: F) -> Option<AABB> {
-        if let Some(output_box) = self.object.bounding_box(time0, time1) {
-            Some(AABB::new(
+        self.object.bounding_box(time0, time1).map(|output_box| {
+            AABB::new(
                 output_box.min() + self.offset,
                 output_box.max() + self.offset,
-            ))
-        } else {
-            None
+            )
+        })
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(origin - self.offset, sampler)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.object.materials(visit)
+    }
+}
+
+// Bounds of the eight corners of `bbox` after mapping them with `f`
+fn corner_bounds(bbox: &AABB, f: impl Fn(Point3) -> Point3) -> AABB {
+    let infinity = F::INFINITY;
+    let mut min = Point3::new(infinity, infinity, infinity);
+    let mut max = Point3::new(-infinity, -infinity, -infinity);
+
+    for i in 0..8 {
+        let corner = Point3::new(
+            if i & 1 == 0 {
+                bbox.min().x()
+            } else {
+                bbox.max().x()
+            },
+            if i & 2 == 0 {
+                bbox.min().y()
+            } else {
+                bbox.max().y()
+            },
+            if i & 4 == 0 {
+                bbox.min().z()
+            } else {
+                bbox.max().z()
+            },
+        );
+        let p = f(corner);
+
+        for a in 0..3 {
+            min.set(a, min.ix(a).min(p.ix(a)));
+            max.set(a, max.ix(a).max(p.ix(a)));
         }
     }
+
+    AABB::new(min, max)
 }
 
 pub struct Rotate {
     object: Arc<H>,
-    plane: Plane,
-    cos_theta: F,
-    sin_theta: F,
+    // Local to world, the inverse is its transpose
+    rotation: Mat3,
+    inverse: Mat3,
     bbox: Option<AABB>,
 }
 
 impl Rotate {
-    pub fn new(object: Arc<H>, plane: Plane, theta: F) -> Self {
-        let radians = deg_to_rad(theta);
-        let cos_theta = radians.cos();
-        let sin_theta = radians.sin();
-
-        let bbox = object.bounding_box(0.0, 1.0).unwrap();
-
-        let infinity = f64::INFINITY;
-        let mut min = Point3::new(infinity, infinity, infinity);
-        let mut max = Point3::new(-infinity, -infinity, -infinity);
-
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let x = i as F * bbox.max().x() + (1 - i) as F * bbox.min().x();
-                    let y = j as F * bbox.max().y() + (1 - j) as F * bbox.min().y();
-                    let z = k as F * bbox.max().z() + (1 - k) as F * bbox.min().z();
-
-                    let new_x = cos_theta * x + sin_theta * z;
-                    let new_z = -sin_theta * x + cos_theta * z;
-
-                    let temp = Vec3::new(new_x, y, new_z);
-
-                    for a in 0..2 {
-                        min.set(a, min.ix(a).min(temp.ix(a)));
-                        max.set(a, max.ix(a).max(temp.ix(a)));
-                    }
-                }
-            }
-        }
+    // Rotates from the first axis of `plane` away from the second by `theta` degrees,
+    // bounding the object over the shutter interval from `time0` to `time1`
+    pub fn new(object: Arc<H>, plane: Plane, theta: F, time0: F, time1: F) -> Self {
+        let (_i, _j, k) = plane.axes();
+        let mut axis = Vec3::zero();
+        axis.set(k, 1.0);
+
+        let rotation = Mat3::rotation(axis, -theta);
+        let inverse = rotation.transpose();
 
-        let bbox = Some(AABB::new(min, max));
+        let bbox = object.bounding_box(time0, time1).unwrap();
+        let bbox = Some(corner_bounds(&bbox, |corner| rotation * corner));
 
         Self {
             object,
-            plane,
-            sin_theta,
-            cos_theta,
+            rotation,
+            inverse,
             bbox,
         }
     }
@@ -92,61 +116,296 @@ impl Rotate {
 
 impl Hittable for Rotate {
     fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
-        let (i, j, _k) = self.plane.axes();
-        let mut origin = ray.origin();
-        let mut direction = ray.direction();
+        let rotated_ray = ray.moved(self.inverse * ray.origin(), self.inverse * ray.direction());
 
-        origin.set(
-            i,
-            self.cos_theta * ray.origin().ix(i) - self.sin_theta * ray.origin().ix(j),
-        );
-        origin.set(
-            j,
-            self.sin_theta * ray.origin().ix(i) + self.cos_theta * ray.origin().ix(j),
+        let mut hit_record = self.object.hit(&rotated_ray, t_min, t_max)?;
+        hit_record.set_p(self.rotation * hit_record.p());
+        hit_record.set_n(self.rotation * hit_record.n());
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self, _time0: F, _time1: F) -> Option<AABB> {
+        self.bbox
+    }
+
+    // Rotations keep solid angles, so densities carry over unchanged
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        self.object
+            .pdf_value(self.inverse * origin, self.inverse * direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.rotation * self.object.random(self.inverse * origin, sampler)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.object.materials(visit)
+    }
+}
+
+// Any affine transform of an object, the object can be shared between many instances
+pub struct Transform {
+    object: Arc<H>,
+    matrix: Mat4,
+    inverse: Mat4,
+    // Inverse transpose, maps normals
+    normal_matrix: Mat4,
+}
+
+impl Transform {
+    // Panics if `matrix` is not invertible
+    pub fn new(object: Arc<H>, matrix: Mat4) -> Self {
+        let inverse = matrix.inverse().expect("Singular matrix in transform");
+
+        Self {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
+        // Direction is not normalised, so t is the same in both spaces
+        let local_ray = ray.moved(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
         );
 
-        direction.set(
-            i,
-            self.cos_theta * ray.direction().ix(i) - self.sin_theta * ray.direction().ix(j),
+        let mut hit_record = self.object.hit(&local_ray, t_min, t_max)?;
+        hit_record.set_p(self.matrix.transform_point(hit_record.p()));
+        // The sign of dot(direction, normal) is preserved, and with it front_face
+        hit_record.set_n(self.normal_matrix.transform_vector(hit_record.n()).unit());
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self, time0: F, time1: F) -> Option<AABB> {
+        let bbox = self.object.bounding_box(time0, time1)?;
+        Some(corner_bounds(&bbox, |corner| {
+            self.matrix.transform_point(corner)
+        }))
+    }
+
+    // Solid angle densities change by |A d|^3 / |det A| for the linear part A
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        let local_direction = self.inverse.transform_vector(direction).unit();
+        let pdf = self
+            .object
+            .pdf_value(self.inverse.transform_point(origin), local_direction);
+
+        let stretch = self.matrix.transform_vector(local_direction).length();
+        pdf * stretch.powi(3) / self.matrix.determinant3().abs()
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self
+            .object
+            .random(self.inverse.transform_point(origin), sampler);
+        self.matrix.transform_vector(direction)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.object.materials(visit)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: F,
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: F, translation: Vec3, rotation: Quaternion, scale: Vec3) -> Self {
+        Self {
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    // Scale, then rotate, then translate
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translation(self.translation)
+            * Mat4::linear(self.rotation.to_mat3() * Mat3::scaling(self.scale))
+    }
+
+    fn inverse(&self) -> Mat4 {
+        let inverse_scale = Vec3::new(
+            1.0 / self.scale.x(),
+            1.0 / self.scale.y(),
+            1.0 / self.scale.z(),
         );
-        direction.set(
-            j,
-            self.sin_theta * ray.direction().ix(i) + self.cos_theta * ray.direction().ix(j),
+        Mat4::linear(Mat3::scaling(inverse_scale) * self.rotation.to_mat3().transpose())
+            * Mat4::translation(-self.translation)
+    }
+
+    fn interpolate(a: &Self, b: &Self, time: F) -> Self {
+        let t = (time - a.time) / (b.time - a.time);
+        Self {
+            time,
+            translation: a.translation * (1.0 - t) + b.translation * t,
+            rotation: Quaternion::slerp(a.rotation, b.rotation, t),
+            scale: a.scale * (1.0 - t) + b.scale * t,
+        }
+    }
+}
+
+// Object moved by keyframes interpolated at each ray's time, for motion blur
+pub struct AnimatedTransform {
+    object: Arc<H>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    // Panics without keyframes or with a zero scale component
+    pub fn new(object: Arc<H>, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "Animated transform needs a keyframe");
+        assert!(
+            keyframes
+                .iter()
+                .all(|k| k.scale.x() != 0.0 && k.scale.y() != 0.0 && k.scale.z() != 0.0),
+            "Zero scale in animated transform"
         );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Self { object, keyframes }
+    }
 
-        let rotated_ray = Ray::new(origin, direction, ray.time());
-
-        if let Some(mut hit_record) = self.object.hit(&rotated_ray, t_min, t_max) {
-            let mut new_p = hit_record.p();
-            new_p.set(
-                i,
-                self.cos_theta * hit_record.p().ix(i) + self.sin_theta * hit_record.p().ix(j),
-            );
-            new_p.set(
-                j,
-                -self.sin_theta * hit_record.p().ix(i) + self.cos_theta * hit_record.p().ix(j),
-            );
-
-            let mut new_n = hit_record.n();
-            new_n.set(
-                i,
-                self.cos_theta * hit_record.n().ix(i) + self.sin_theta * hit_record.n().ix(j),
-            );
-            new_n.set(
-                j,
-                -self.sin_theta * hit_record.n().ix(i) + self.cos_theta * hit_record.n().ix(j),
-            );
-
-            hit_record.set_p(new_p);
-            hit_record.set_face_normal(&rotated_ray, new_n);
+    // Held constant before the first and after the last keyframe
+    pub fn keyframe_at(&self, time: F) -> Keyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
 
-            Some(hit_record)
+        if next == 0 {
+            Keyframe {
+                time,
+                ..self.keyframes[0]
+            }
+        } else if next == self.keyframes.len() {
+            Keyframe {
+                time,
+                ..self.keyframes[next - 1]
+            }
         } else {
-            None
+            Keyframe::interpolate(&self.keyframes[next - 1], &self.keyframes[next], time)
         }
     }
+}
 
-    fn bounding_box(&self, _time0: F, _time1: F) -> Option<AABB> {
-        self.bbox
+impl Hittable for AnimatedTransform {
+    fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
+        let keyframe = self.keyframe_at(ray.time());
+        let inverse = keyframe.inverse();
+
+        let local_ray = ray.moved(
+            inverse.transform_point(ray.origin()),
+            inverse.transform_vector(ray.direction()),
+        );
+
+        let mut hit_record = self.object.hit(&local_ray, t_min, t_max)?;
+        hit_record.set_p(keyframe.matrix().transform_point(hit_record.p()));
+
+        // Inverse transpose of rotation times scale
+        let n = hit_record.n();
+        let n = Vec3::new(
+            n.x() / keyframe.scale.x(),
+            n.y() / keyframe.scale.y(),
+            n.z() / keyframe.scale.z(),
+        );
+        hit_record.set_n(keyframe.rotation.rotate(n).unit());
+
+        Some(hit_record)
+    }
+
+    // Union of the boxes at closely spaced times, each padded by how far any
+    // point of the object can move before the next time
+    fn bounding_box(&self, time0: F, time1: F) -> Option<AABB> {
+        const STEPS: usize = 16;
+
+        let bbox = self.object.bounding_box(time0, time1)?;
+        let radius = (0..8)
+            .map(|i| {
+                Vec3::new(
+                    if i & 1 == 0 {
+                        bbox.min().x()
+                    } else {
+                        bbox.max().x()
+                    },
+                    if i & 2 == 0 {
+                        bbox.min().y()
+                    } else {
+                        bbox.max().y()
+                    },
+                    if i & 4 == 0 {
+                        bbox.min().z()
+                    } else {
+                        bbox.max().z()
+                    },
+                )
+                .length()
+            })
+            .fold(0.0, F::max);
+
+        let mut times = vec![time0];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|&t| t > time0 && t < time1),
+        );
+        times.push(time1);
+
+        let mut output_box = corner_bounds(&bbox, |p| {
+            self.keyframe_at(time0).matrix().transform_point(p)
+        });
+
+        for span in times.windows(2) {
+            let (start, end) = (span[0], span[1]);
+            if end <= start {
+                continue;
+            }
+
+            let mut a = self.keyframe_at(start);
+            for step in 1..=STEPS {
+                let b = self.keyframe_at(start + (end - start) * step as F / STEPS as F);
+
+                let angle = 2.0 * a.rotation.dot(&b.rotation).abs().min(1.0).acos();
+                let max_scale = (0..3)
+                    .map(|i| a.scale.ix(i).abs().max(b.scale.ix(i).abs()))
+                    .fold(0.0, F::max);
+                let scale_change = (0..3)
+                    .map(|i| (b.scale.ix(i) - a.scale.ix(i)).abs())
+                    .fold(0.0, F::max);
+                let padding = (b.translation - a.translation).length()
+                    + angle * max_scale * radius
+                    + scale_change * radius;
+
+                let box_a = corner_bounds(&bbox, |p| a.matrix().transform_point(p));
+                let box_b = corner_bounds(&bbox, |p| b.matrix().transform_point(p));
+                output_box = AABB::surrounding_box(
+                    output_box,
+                    AABB::surrounding_box(box_a, box_b).expand(padding),
+                );
+
+                a = b;
+            }
+        }
+
+        Some(output_box)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        self.object.materials(visit)
     }
 }
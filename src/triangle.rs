@@ -0,0 +1,295 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
+
+use crate::{aabb::AABB, bvh::*, hittable::*, ray::Ray, sampler::*, texture::*, vec3::*};
+
+const EPSILON: F = 1e-8;
+const DEPTH: F = 1e-4;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MeshError {
+    // Per-vertex attributes need one value for each position
+    AttributeCount {
+        attribute: &'static str,
+        positions: usize,
+        found: usize,
+    },
+    IndexOutOfRange {
+        index: usize,
+        positions: usize,
+    },
+    // Nothing to hit, and no bounding box to put in a BVH
+    NoFaces,
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::AttributeCount {
+                attribute,
+                positions,
+                found,
+            } => write!(
+                f,
+                "{} {} for {} vertex positions",
+                found, attribute, positions
+            ),
+            MeshError::IndexOutOfRange { index, positions } => write!(
+                f,
+                "vertex index {} out of range for {} positions",
+                index, positions
+            ),
+            MeshError::NoFaces => write!(f, "mesh has no faces"),
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+pub struct Mesh {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<TexturePoint>>,
+    indices: Vec<[usize; 3]>,
+    material: Arc<M>,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Point3>, indices: Vec<[usize; 3]>, material: Arc<M>) -> Self {
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+            indices,
+            material,
+        }
+    }
+
+    fn check_count(&self, attribute: &'static str, found: usize) -> Result<(), MeshError> {
+        if found == self.positions.len() {
+            Ok(())
+        } else {
+            Err(MeshError::AttributeCount {
+                attribute,
+                positions: self.positions.len(),
+                found,
+            })
+        }
+    }
+
+    // Per-vertex attributes are indexed in the same way as positions
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Result<Self, MeshError> {
+        self.check_count("normals", normals.len())?;
+        self.normals = Some(normals);
+        Ok(self)
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<TexturePoint>) -> Result<Self, MeshError> {
+        self.check_count("texture coordinates", uvs.len())?;
+        self.uvs = Some(uvs);
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn material(&self) -> Arc<M> {
+        Arc::clone(&self.material)
+    }
+}
+
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    index: usize,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, material: Arc<M>) -> Self {
+        let mesh = Mesh::new(vec![p0, p1, p2], vec![[0, 1, 2]], material);
+
+        Self {
+            mesh: Arc::new(mesh),
+            index: 0,
+        }
+    }
+
+    pub fn from_mesh(mesh: Arc<Mesh>, index: usize) -> Self {
+        assert!(index < mesh.len());
+        Self { mesh, index }
+    }
+
+    pub fn vertices(&self) -> (Point3, Point3, Point3) {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+
+        (positions[i0], positions[i1], positions[i2])
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        let (p0, p1, p2) = self.vertices();
+        (p0 + p1 + p2) / 3.0
+    }
+
+    pub fn area(&self) -> F {
+        let (p0, p1, p2) = self.vertices();
+        cross(&(p1 - p0), &(p2 - p0)).length() * 0.5
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
+        // Möller–Trumbore
+        let (p0, p1, p2) = self.vertices();
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+
+        let pvec = cross(&ray.direction(), &edge2);
+        let det = dot(&edge1, &pvec);
+
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin() - p0;
+        let b1 = dot(&tvec, &pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = cross(&tvec, &edge1);
+        let b2 = dot(&ray.direction(), &qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = dot(&edge2, &qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        // Barycentric weights for p0, p1, p2
+        let b0 = 1.0 - b1 - b2;
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+
+        let tp = if let Some(uvs) = &self.mesh.uvs {
+            TexturePoint::new(
+                b0 * uvs[i0].u() + b1 * uvs[i1].u() + b2 * uvs[i2].u(),
+                b0 * uvs[i0].v() + b1 * uvs[i1].v() + b2 * uvs[i2].v(),
+            )
+        } else {
+            TexturePoint::new(b1, b2)
+        };
+
+        let outward_normal = cross(&edge1, &edge2).unit();
+        let mut hit_record =
+            HitRecord::new(ray.at(t), outward_normal, t, tp, true, self.mesh.material());
+        hit_record.set_face_normal(ray, outward_normal);
+
+        if let Some(normals) = &self.mesh.normals {
+            let shading_normal = (normals[i0] * b0 + normals[i1] * b1 + normals[i2] * b2).unit();
+
+            // Front face is decided by the geometry, the shading normal only follows it
+            hit_record.set_n(if hit_record.front_face() {
+                shading_normal
+            } else {
+                -shading_normal
+            });
+        }
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self, _time0: F, _time1: F) -> Option<AABB> {
+        let (p0, p1, p2) = self.vertices();
+        let mut min = Point3::zero();
+        let mut max = Point3::zero();
+
+        // Pad so that axis-aligned triangles still have a non-zero width
+        for i in 0..3 {
+            min.set(i, p0.ix(i).min(p1.ix(i)).min(p2.ix(i)) - DEPTH);
+            max.set(i, p0.ix(i).max(p1.ix(i)).max(p2.ix(i)) + DEPTH);
+        }
+
+        Some(AABB::new(min, max))
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> F {
+        if let Some(hit_record) = self.hit(&Ray::new(origin, direction, 0.0), 0.001, F::INFINITY) {
+            let distance_squared = hit_record.t() * hit_record.t() * direction.length_squared();
+            let (p0, p1, p2) = self.vertices();
+            let normal = cross(&(p1 - p0), &(p2 - p0)).unit();
+            let cosine = dot(&direction, &normal).abs() / direction.length();
+
+            distance_squared / (cosine * self.area())
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        // Uniform over the triangle's area
+        let (p0, p1, p2) = self.vertices();
+        let (u, r2) = sampler.next_2d();
+        let r1 = u.sqrt();
+
+        p0 * (1.0 - r1) + p1 * (r1 * (1.0 - r2)) + p2 * (r1 * r2) - origin
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        visit(&self.mesh.material)
+    }
+}
+
+pub struct TriangleMesh {
+    mesh: Arc<Mesh>,
+    bvh: LinearBVH,
+}
+
+impl TriangleMesh {
+    // Fails for meshes without faces or with indices past the last position
+    pub fn new(mesh: Mesh, time0: F, time1: F) -> Result<Self, MeshError> {
+        if mesh.is_empty() {
+            return Err(MeshError::NoFaces);
+        }
+        let positions = mesh.positions.len();
+        if let Some(&index) = mesh.indices.iter().flatten().find(|&&i| i >= positions) {
+            return Err(MeshError::IndexOutOfRange { index, positions });
+        }
+
+        let mesh = Arc::new(mesh);
+        let mut triangles = HittableList::new();
+        for index in 0..mesh.len() {
+            triangles.add(Arc::new(Triangle::from_mesh(Arc::clone(&mesh), index)));
+        }
+        let bvh = LinearBVH::new(&triangles, SahOptions::default(), time0, time1);
+
+        Ok(Self { mesh, bvh })
+    }
+
+    pub fn mesh(&self) -> Arc<Mesh> {
+        Arc::clone(&self.mesh)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: F, t_max: F) -> Option<HitRecord> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: F, time1: F) -> Option<AABB> {
+        self.bvh.bounding_box(time0, time1)
+    }
+
+    fn materials(&self, visit: &mut dyn FnMut(&Arc<M>)) {
+        visit(&self.mesh.material)
+    }
+}
@@ -1,14 +1,14 @@
 use std::ops::{Add, Div, Mul, Neg, Sub};
-use std::{fmt::Display, fmt::Formatter, fmt::Result, iter::Sum};
+use std::{cell::RefCell, fmt::Display, fmt::Formatter, fmt::Result, iter::Sum};
 
-use rand::{thread_rng, Rng};
+use rand::{Rng, RngCore};
 use rand_distr::StandardNormal;
 
 pub type F = f64;
 pub type Colour = Vec3;
 pub type Point3 = Vec3;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Vec3([F; 3]);
 
 impl Vec3 {
@@ -82,7 +82,7 @@ impl Vec3 {
     }
 
     pub fn length_squared(&self) -> F {
-        dot(&self, &self)
+        dot(self, self)
     }
 
     pub fn length(&self) -> F {
@@ -92,22 +92,6 @@ impl Vec3 {
     pub fn unit(self) -> Self {
         self / self.length()
     }
-
-    pub fn write_colour(&self, samples_per_pixel: u32) -> String {
-        let scale = 1.0 / samples_per_pixel as F;
-        // Divide colour by number of samples and gamma-correct for gamma = 2.0
-        let colour = Vec3::new(
-            (self.x() * scale).sqrt(),
-            (self.y() * scale).sqrt(),
-            (self.z() * scale).sqrt(),
-        );
-        format!(
-            "{} {} {}",
-            (clamp(colour.x(), 0.0, 0.999) * 256.0).floor(),
-            (clamp(colour.y(), 0.0, 0.999) * 256.0).floor(),
-            (clamp(colour.z(), 0.0, 0.999) * 256.0).floor()
-        )
-    }
 }
 
 impl Display for Vec3 {
@@ -231,11 +215,11 @@ pub fn refract(ray_in: Vec3, n: Vec3, eta_over_eta_prime: F) -> Vec3 {
 
 pub fn clamp(x: F, min: F, max: F) -> F {
     if x < min {
-        return min;
+        min
     } else if x > max {
-        return max;
+        max
     } else {
-        return x;
+        x
     }
 }
 
@@ -243,9 +227,591 @@ pub fn deg_to_rad(deg: F) -> F {
     deg * std::f64::consts::PI / 180.0
 }
 
+// Row major 3x3 matrix acting on column vectors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat3([[F; 3]; 3]);
+
+impl Mat3 {
+    pub fn new(rows: [[F; 3]; 3]) -> Self {
+        Self(rows)
+    }
+
+    // Matrix whose columns are `a`, `b` and `c`
+    pub fn from_columns(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Self([
+            [a.x(), b.x(), c.x()],
+            [a.y(), b.y(), c.y()],
+            [a.z(), b.z(), c.z()],
+        ])
+    }
+
+    pub fn identity() -> Self {
+        Self::scaling(Vec3::one())
+    }
+
+    pub fn scaling(scale: Vec3) -> Self {
+        Self([
+            [scale.x(), 0.0, 0.0],
+            [0.0, scale.y(), 0.0],
+            [0.0, 0.0, scale.z()],
+        ])
+    }
+
+    // Counter-clockwise rotation by `theta` degrees about `axis`, Rodrigues' formula
+    pub fn rotation(axis: Vec3, theta: F) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = deg_to_rad(theta).sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let t = 1.0 - cos;
+
+        Self([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos],
+        ])
+    }
+
+    pub fn ix(&self, row: usize, col: usize) -> F {
+        self.0[row][col]
+    }
+
+    pub fn row(&self, i: usize) -> Vec3 {
+        Vec3(self.0[i])
+    }
+
+    pub fn column(&self, j: usize) -> Vec3 {
+        Vec3::new(self.0[0][j], self.0[1][j], self.0[2][j])
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::from_columns(self.row(0), self.row(1), self.row(2))
+    }
+
+    pub fn determinant(&self) -> F {
+        dot(&self.row(0), &cross(&self.row(1), &self.row(2)))
+    }
+
+    // Adjugate over determinant, None if singular
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let (r0, r1, r2) = (self.row(0), self.row(1), self.row(2));
+        let m = Self::from_columns(cross(&r1, &r2), cross(&r2, &r0), cross(&r0, &r1));
+        Some(m * (1.0 / det))
+    }
+}
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            dot(&self.row(0), &v),
+            dot(&self.row(1), &v),
+            dot(&self.row(2), &v),
+        )
+    }
+}
+
+impl Mul<Mat3> for Mat3 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let columns: Vec<_> = (0..3).map(|j| self * other.column(j)).collect();
+        Self::from_columns(columns[0], columns[1], columns[2])
+    }
+}
+
+impl Mul<F> for Mat3 {
+    type Output = Self;
+
+    fn mul(self, lambda: F) -> Self {
+        Self::from_columns(
+            self.column(0) * lambda,
+            self.column(1) * lambda,
+            self.column(2) * lambda,
+        )
+    }
+}
+
+// Row major 4x4 matrix acting on column vectors, points have w = 1, vectors w = 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4([[F; 4]; 4]);
+
+impl Mat4 {
+    pub fn new(rows: [[F; 4]; 4]) -> Self {
+        Self(rows)
+    }
+
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self(m)
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut m = Self::identity();
+        for i in 0..3 {
+            m.0[i][3] = offset.ix(i);
+        }
+        m
+    }
+
+    pub fn scaling(scale: Vec3) -> Self {
+        let mut m = Self::identity();
+        for i in 0..3 {
+            m.0[i][i] = scale.ix(i);
+        }
+        m
+    }
+
+    pub fn rotation(axis: Vec3, theta: F) -> Self {
+        Self::linear(Mat3::rotation(axis, theta))
+    }
+
+    // Linear part `m` with no translation
+    pub fn linear(m: Mat3) -> Self {
+        let mut rows = Self::identity().0;
+        for (i, row) in rows.iter_mut().enumerate().take(3) {
+            for (j, value) in row.iter_mut().enumerate().take(3) {
+                *value = m.ix(i, j);
+            }
+        }
+        Self(rows)
+    }
+
+    // Upper left 3x3
+    pub fn to_mat3(&self) -> Mat3 {
+        let m = &self.0;
+        Mat3::new([
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ])
+    }
+
+    pub fn ix(&self, row: usize, col: usize) -> F {
+        self.0[row][col]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        Self(m)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.0;
+        let mut inv = Self::identity().0;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Self(inv))
+    }
+
+    // Determinant of the upper 3x3, the volume scale of the linear part
+    pub fn determinant3(&self) -> F {
+        self.to_mat3().determinant()
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::new(self.0[0][3], self.0[1][3], self.0[2][3])
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        self.to_mat3() * v
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Self(m)
+    }
+}
+
+// Unit quaternions represent rotations, w is the scalar part
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    w: F,
+    v: Vec3,
+}
+
+impl Quaternion {
+    pub fn new(w: F, x: F, y: F, z: F) -> Self {
+        Self {
+            w,
+            v: Vec3::new(x, y, z),
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    // Counter-clockwise rotation by `theta` degrees about `axis`
+    pub fn from_axis_angle(axis: Vec3, theta: F) -> Self {
+        let (sin, cos) = (deg_to_rad(theta) / 2.0).sin_cos();
+        Self {
+            w: cos,
+            v: axis.unit() * sin,
+        }
+    }
+
+    // Rotation part of an orthonormal matrix, Shepperd's method
+    pub fn from_mat3(m: &Mat3) -> Self {
+        let trace = m.ix(0, 0) + m.ix(1, 1) + m.ix(2, 2);
+
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Self::new(
+                s / 4.0,
+                (m.ix(2, 1) - m.ix(1, 2)) / s,
+                (m.ix(0, 2) - m.ix(2, 0)) / s,
+                (m.ix(1, 0) - m.ix(0, 1)) / s,
+            )
+        } else if m.ix(0, 0) > m.ix(1, 1) && m.ix(0, 0) > m.ix(2, 2) {
+            let s = 2.0 * (1.0 + m.ix(0, 0) - m.ix(1, 1) - m.ix(2, 2)).sqrt();
+            Self::new(
+                (m.ix(2, 1) - m.ix(1, 2)) / s,
+                s / 4.0,
+                (m.ix(0, 1) + m.ix(1, 0)) / s,
+                (m.ix(0, 2) + m.ix(2, 0)) / s,
+            )
+        } else if m.ix(1, 1) > m.ix(2, 2) {
+            let s = 2.0 * (1.0 + m.ix(1, 1) - m.ix(0, 0) - m.ix(2, 2)).sqrt();
+            Self::new(
+                (m.ix(0, 2) - m.ix(2, 0)) / s,
+                (m.ix(0, 1) + m.ix(1, 0)) / s,
+                s / 4.0,
+                (m.ix(1, 2) + m.ix(2, 1)) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + m.ix(2, 2) - m.ix(0, 0) - m.ix(1, 1)).sqrt();
+            Self::new(
+                (m.ix(1, 0) - m.ix(0, 1)) / s,
+                (m.ix(0, 2) + m.ix(2, 0)) / s,
+                (m.ix(1, 2) + m.ix(2, 1)) / s,
+                s / 4.0,
+            )
+        };
+
+        q.unit()
+    }
+
+    pub fn w(&self) -> F {
+        self.w
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn dot(&self, other: &Self) -> F {
+        self.w * other.w + dot(&self.v, &other.v)
+    }
+
+    pub fn length(&self) -> F {
+        self.dot(self).sqrt()
+    }
+
+    pub fn unit(self) -> Self {
+        self * (1.0 / self.length())
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    // Rotates `v` by this unit quaternion
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let t = cross(&self.v, &v) * 2.0;
+        v + t * self.w + cross(&self.v, &t)
+    }
+
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_columns(
+            self.rotate(Vec3::new(1.0, 0.0, 0.0)),
+            self.rotate(Vec3::new(0.0, 1.0, 0.0)),
+            self.rotate(Vec3::new(0.0, 0.0, 1.0)),
+        )
+    }
+
+    // Constant angular velocity interpolation along the shorter arc
+    pub fn slerp(a: Self, b: Self, t: F) -> Self {
+        let mut cos_theta = a.dot(&b);
+        let b = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            -b
+        } else {
+            b
+        };
+
+        // Nearly parallel, fall back to normalised linear interpolation
+        if cos_theta > 0.9995 {
+            return (a * (1.0 - t) + b * t).unit();
+        }
+
+        let theta = cos_theta.min(1.0).acos();
+        let sin_theta = theta.sin();
+        a * (((1.0 - t) * theta).sin() / sin_theta) + b * ((t * theta).sin() / sin_theta)
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Add<Quaternion> for Quaternion {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            w: self.w + other.w,
+            v: self.v + other.v,
+        }
+    }
+}
+
+impl Neg for Quaternion {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            w: -self.w,
+            v: -self.v,
+        }
+    }
+}
+
+// Hamilton product, `a * b` rotates by b and then by a
+impl Mul<Quaternion> for Quaternion {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            w: self.w * other.w - dot(&self.v, &other.v),
+            v: other.v * self.w + self.v * other.w + cross(&self.v, &other.v),
+        }
+    }
+}
+
+impl Mul<F> for Quaternion {
+    type Output = Self;
+
+    fn mul(self, lambda: F) -> Self {
+        Self {
+            w: self.w * lambda,
+            v: self.v * lambda,
+        }
+    }
+}
+
+// Orthonormal basis, right handed with w as the main axis
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    // Any basis around the normal `n`
+    pub fn from_w(n: Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = cross(&w, &a).unit();
+        let u = cross(&v, &w);
+
+        Self { u, v, w }
+    }
+
+    // Basis around `w` with v as close to `up` as possible, as for a camera
+    pub fn from_w_up(w: Vec3, up: Vec3) -> Self {
+        let w = w.unit();
+        let u = cross(&up, &w).unit();
+        let v = cross(&w, &u);
+
+        Self { u, v, w }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    pub fn to_world(&self, a: Vec3) -> Vec3 {
+        self.u * a.x() + self.v * a.y() + self.w * a.z()
+    }
+
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(dot(&a, &self.u), dot(&a, &self.v), dot(&a, &self.w))
+    }
+
+    // Columns u, v, w, maps local to world
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_columns(self.u, self.v, self.w)
+    }
+}
+
 // Random
+
+// PCG-XSH-RR with 64 bits of state, O'Neill. Small and fast to seed, so every
+// pixel sample can start its own stream.
+#[derive(Clone, Debug)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+    // Different `stream`s give unrelated sequences for the same `seed`
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        (self.next_u32() as u64) << 32 | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Threads start from entropy, anything that has to be repeatable seeds its
+// own generator with `with_seed` or `seed_thread_rng`
+thread_local! {
+    static RNG: RefCell<Pcg32> = RefCell::new(Pcg32::new(rand::random(), rand::random()));
+}
+
+// Restarts only the calling thread's generator, e.g. for one pixel sample
+pub fn seed_thread_rng(seed: u64, stream: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = Pcg32::new(seed, stream));
+}
+
+// Runs `f` with the calling thread's generator seeded from `seed`, then puts
+// the previous generator back. Scene construction runs under this so that the
+// same seed always builds the same scene.
+pub fn with_seed<R>(seed: u64, f: impl FnOnce() -> R) -> R {
+    let previous = RNG.with(|rng| rng.replace(Pcg32::new(seed, 0)));
+    let result = f();
+    RNG.with(|rng| *rng.borrow_mut() = previous);
+
+    result
+}
+
+pub fn with_rng<R>(f: impl FnOnce(&mut Pcg32) -> R) -> R {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
 pub fn random() -> F {
-    thread_rng().gen::<F>()
+    with_rng(|rng| rng.gen::<F>())
 }
 
 pub fn random_range(min: F, max: F) -> F {
@@ -253,5 +819,5 @@ pub fn random_range(min: F, max: F) -> F {
 }
 
 pub fn random_gaussian() -> F {
-    thread_rng().sample(StandardNormal)
+    with_rng(|rng| rng.sample(StandardNormal))
 }
@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use raytracer::{aov::*, film::*, render::*, scenes::*, vec3::*};
+
+fn settings(aovs: AovSet) -> RenderSettings {
+    common::settings(|settings| {
+        settings.seed = Some(5);
+        settings.aovs = aovs;
+    })
+}
+
+fn layer(film: &Film, aov: Aov) -> usize {
+    film.layers()
+        .iter()
+        .position(|layer| layer.aov == aov)
+        .unwrap()
+}
+
+fn assert_close(a: Colour, b: Colour) {
+    assert!(
+        (a - b).length() <= 1e-9 * (1.0 + b.length()),
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn aovs_leave_the_beauty_image_unchanged() {
+    // Media draw random numbers when intersected
+    let scene = with_seed(3, || _final_scene(1.0));
+    let plain = render(&settings(AovSet::default()), &scene);
+    let with_aovs = render(&settings(AovSet::all()), &scene);
+
+    assert!(plain.layers().is_empty());
+    assert!(plain.pixels().eq(with_aovs.pixels()));
+}
+
+#[test]
+fn light_aovs_add_up_to_the_beauty_image() {
+    let scene = _cornell_box(1.0);
+    let film = render(&settings(AovSet::all()), &scene);
+    let (emission, direct, indirect, light) = (
+        layer(&film, Aov::Emission),
+        layer(&film, Aov::Direct),
+        layer(&film, Aov::Indirect),
+        layer(&film, Aov::Lights),
+    );
+
+    for y in 0..16 {
+        for x in 0..16 {
+            let beauty = film.pixel(x, y);
+            let split =
+                film.aov(x, y, emission) + film.aov(x, y, direct) + film.aov(x, y, indirect);
+            assert_close(split, beauty);
+            // The background is black and the only emitter is the light
+            assert_close(film.aov(x, y, light), beauty);
+        }
+    }
+
+    assert!(film.aov_pixels(emission).any(|c| c != Colour::zero()));
+    assert!(film.aov_pixels(indirect).any(|c| c != Colour::zero()));
+}
+
+#[test]
+fn ids_are_whole_numbers_of_the_first_sample() {
+    let scene = _cornell_box(1.0);
+    let aovs = AovSet::default().with(Aov::ObjectId).with(Aov::MaterialId);
+    let film = render(&settings(aovs), &scene);
+
+    for index in 0..2 {
+        for id in film.aov_pixels(index) {
+            let id = id.x();
+            assert_eq!(id, id.round());
+            // 0 where the camera sees past the box
+            assert!(id <= scene.world.len() as F);
+        }
+    }
+    // Red, green, white and light
+    let materials: Vec<_> = film.aov_pixels(1).map(|id| id.x() as u32).collect();
+    assert_eq!(materials.iter().max(), Some(&4));
+}
+
+#[test]
+fn aov_sets_parse_names_and_all() {
+    let set: AovSet = "albedo, normal,lights".parse().unwrap();
+    assert!(set.contains(Aov::Albedo) && set.contains(Aov::Normal) && set.contains(Aov::Lights));
+    assert!(!set.contains(Aov::Depth));
+    assert_eq!(set.to_string().parse::<AovSet>(), Ok(set));
+
+    assert_eq!("all".parse::<AovSet>(), Ok(AovSet::all()));
+    assert!("albedo,shadow".parse::<AovSet>().is_err());
+
+    let names: Vec<_> = set.layers(2).iter().map(|layer| layer.name()).collect();
+    assert_eq!(names, ["albedo", "normal", "light_0", "light_1"]);
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use raytracer::{bvh::*, hittable::*, material::*, ray::*, sphere::*, vec3::*};
+
+// Every sphere has its own material, which tells the hits apart
+fn sphere(centre: Point3, radius: F) -> Arc<H> {
+    let material: Arc<M> = Arc::new(Lambertian::rgb(0.5, 0.5, 0.5));
+    Arc::new(Sphere::new(centre, radius, material))
+}
+
+fn soup(count: usize) -> HittableList {
+    let mut world = HittableList::new();
+    for _ in 0..count {
+        world.add(sphere(
+            Vec3::random_vector(-10.0, 10.0),
+            random_range(0.05, 1.0),
+        ));
+    }
+    world
+}
+
+// Each sphere twice as far along x as the last, so the SAH can only ever split
+// off the farthest and the tree grows as deep as the builder allows
+fn spiral(count: usize) -> HittableList {
+    let mut world = HittableList::new();
+    for i in 0..count {
+        let x = (2.0 as F).powi(i as i32);
+        world.add(sphere(Point3::new(x, 0.1 * x.sin(), 0.0), 0.2 * x));
+    }
+    world
+}
+
+fn assert_same_hits(bvh: &dyn Hittable, world: &HittableList, rays: &[Ray]) {
+    for ray in rays {
+        let expected = world.hit(ray, 0.001, F::INFINITY);
+        let found = bvh.hit(ray, 0.001, F::INFINITY);
+
+        match (expected, found) {
+            (None, None) => {}
+            (Some(expected), Some(found)) => {
+                assert_eq!(found.t(), expected.t());
+                assert!(Arc::ptr_eq(&found.material(), &expected.material()));
+            }
+            (expected, found) => panic!(
+                "hit {} through the list but {} through the bvh",
+                expected.is_some(),
+                found.is_some()
+            ),
+        }
+    }
+}
+
+fn random_rays(count: usize, spread: F) -> Vec<Ray> {
+    (0..count)
+        .map(|_| {
+            Ray::new(
+                Vec3::random_vector(-spread, spread),
+                Vec3::random_on_unit_sphere(),
+                0.0,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn bvhs_find_the_same_hits_as_brute_force() {
+    with_seed(1, || {
+        let world = soup(300);
+        let rays = random_rays(2000, 12.0);
+
+        for options in [
+            SahOptions::default(),
+            SahOptions {
+                bins: 2,
+                max_leaf_size: 1,
+            },
+        ] {
+            assert_same_hits(&BVH::sah(&world, options, 0.0, 1.0), &world, &rays);
+            assert_same_hits(&LinearBVH::new(&world, options, 0.0, 1.0), &world, &rays);
+        }
+        assert_same_hits(&BVH::new(&world, 0, world.len(), 0.0, 1.0), &world, &rays);
+    });
+}
+
+#[test]
+fn deep_trees_fall_back_to_median_splits() {
+    with_seed(2, || {
+        let world = spiral(120);
+        let stats = BVH::sah(&world, SahOptions::default(), 0.0, 1.0).stats();
+        // Median splits take over halfway to the 64 entry traversal stack
+        assert!(stats.depth > 32, "depth {}", stats.depth);
+        assert!(stats.depth <= 64, "depth {}", stats.depth);
+        assert_eq!(stats.primitives, 120);
+
+        // Along the spiral from its start, through most of the tree
+        let mut rays: Vec<_> = (0..500)
+            .map(|_| {
+                let target = Point3::new((2.0 as F).powf(random_range(0.0, 120.0)), 0.0, 0.0);
+                let origin = Point3::new(-1.0, random_range(-0.5, 0.5), random_range(-0.5, 0.5));
+                Ray::new(origin, target - origin, 0.0)
+            })
+            .collect();
+        rays.extend(random_rays(500, 50.0));
+
+        assert_same_hits(
+            &LinearBVH::new(&world, SahOptions::default(), 0.0, 1.0),
+            &world,
+            &rays,
+        );
+        assert_same_hits(
+            &BVH::sah(&world, SahOptions::default(), 0.0, 1.0),
+            &world,
+            &rays,
+        );
+    });
+}
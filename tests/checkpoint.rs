@@ -0,0 +1,254 @@
+mod common;
+
+use std::{env, fs, path::PathBuf, process};
+
+use common::*;
+use raytracer::{aov::*, checkpoint::*, filter::*, render::*, sampler::*, scenes::*};
+
+fn settings(samples_per_pixel: u32) -> RenderSettings {
+    common::settings(|settings| {
+        settings.image_width = 20;
+        settings.image_height = 20;
+        settings.samples_per_pixel = samples_per_pixel;
+        settings.max_depth = 5;
+        settings.tile_size = 8;
+    })
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("raytracer_{}_{}.checkpoint", name, process::id()))
+}
+
+#[test]
+fn resumed_render_matches_uninterrupted_render() {
+    let scene = _cornell_box(1.0);
+    let hash = settings_hash(&settings(8), b"cornell_box");
+    let path = temp_path("resume");
+
+    let mut uninterrupted = RenderState::new(&settings(8));
+    render_state(&settings(8), &scene, &mut uninterrupted);
+
+    let mut first_half = RenderState::new(&settings(4));
+    render_state(&settings(4), &scene, &mut first_half);
+    write_checkpoint(&path, hash, 7, &first_half).unwrap();
+
+    let checkpoint = read_checkpoint(&path, &settings(8), b"cornell_box").unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(checkpoint.scene_seed, 7);
+    assert_eq!(checkpoint.state.seed, 7);
+    assert_eq!(checkpoint.state.tiles, first_half.tiles);
+
+    let mut resumed = checkpoint.state;
+    render_state(&settings(8), &scene, &mut resumed);
+
+    assert_eq!(resumed.samples(), 8);
+    assert_eq!(resumed.tiles, uninterrupted.tiles);
+    for y in 0..20 {
+        for x in 0..20 {
+            assert_eq!(resumed.film.sum(x, y), uninterrupted.film.sum(x, y));
+            assert_eq!(resumed.film.samples(x, y), 8);
+        }
+    }
+}
+
+#[test]
+fn render_stopped_mid_pass_resumes_with_the_same_filter_weights() {
+    let scene = _cornell_box(1.0);
+    let settings = RenderSettings {
+        filter: Filter::new(FilterKind::Mitchell),
+        ..settings(4)
+    };
+    let hash = settings_hash(&settings, b"cornell_box");
+    let path = temp_path("filter");
+
+    let mut uninterrupted = RenderState::new(&settings);
+    render_state(&settings, &scene, &mut uninterrupted);
+
+    // Stops a few tiles into the second pass, whose splats reach tiles not yet rendered
+    let mut stopped = RenderState::new(&settings);
+    let mut tiles = 0;
+    let result = render_progressive(&settings, &scene, &mut stopped, |_, progress| {
+        if let Progress::Tile { .. } = progress {
+            tiles += 1;
+        }
+        if tiles == 12 {
+            Err(())
+        } else {
+            Ok(())
+        }
+    });
+    assert!(result.is_err());
+    write_checkpoint(&path, hash, 7, &stopped).unwrap();
+
+    let mut resumed = read_checkpoint(&path, &settings, b"cornell_box")
+        .unwrap()
+        .state;
+    fs::remove_file(&path).unwrap();
+    render_state(&settings, &scene, &mut resumed);
+
+    for y in 0..20 {
+        for x in 0..20 {
+            assert_eq!(resumed.film.sum(x, y), uninterrupted.film.sum(x, y));
+            assert_eq!(resumed.film.weight(x, y), uninterrupted.film.weight(x, y));
+        }
+    }
+}
+
+#[test]
+fn samples_per_pixel_does_not_change_settings_hash() {
+    let hash = settings_hash(&settings(8), b"cornell_box");
+
+    assert_eq!(settings_hash(&settings(1000), b"cornell_box"), hash);
+    assert_ne!(settings_hash(&settings(8), b"final_scene"), hash);
+
+    let wider = RenderSettings {
+        image_width: 21,
+        ..settings(8)
+    };
+    assert_ne!(settings_hash(&wider, b"cornell_box"), hash);
+
+    let filtered = RenderSettings {
+        filter: Filter::new(FilterKind::Gaussian),
+        ..settings(8)
+    };
+    assert_ne!(settings_hash(&filtered, b"cornell_box"), hash);
+}
+
+#[test]
+fn checkpoints_resume_with_their_own_sampler() {
+    let path = temp_path("sampler");
+    let state = RenderState::new(&settings(8));
+    write_checkpoint(
+        &path,
+        settings_hash(&settings(8), b"cornell_box"),
+        7,
+        &state,
+    )
+    .unwrap();
+
+    let sobol = RenderSettings {
+        sampler: SamplerKind::Sobol,
+        ..settings(8)
+    };
+    let mismatch = read_checkpoint(&path, &sobol, b"cornell_box");
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(mismatch, Err(CheckpointError::Mismatch { .. })));
+
+    // Stratified samples are spread over all of them, so the count can't change
+    let stratified = |samples_per_pixel| {
+        let settings = RenderSettings {
+            sampler: SamplerKind::Stratified,
+            ..settings(samples_per_pixel)
+        };
+        settings_hash(&settings, b"cornell_box")
+    };
+    assert_eq!(stratified(8), stratified(8));
+    assert_ne!(stratified(8), stratified(16));
+}
+
+#[test]
+fn mismatched_or_damaged_checkpoints_are_rejected() {
+    let path = temp_path("reject");
+    let state = RenderState::new(&settings(8));
+    let hash = settings_hash(&settings(8), b"cornell_box");
+    write_checkpoint(&path, hash, 7, &state).unwrap();
+
+    let mismatch = read_checkpoint(&path, &settings(8), b"final_scene");
+    assert!(matches!(mismatch, Err(CheckpointError::Mismatch { .. })));
+
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    let truncated = read_checkpoint(&path, &settings(8), b"cornell_box");
+    assert!(matches!(truncated, Err(CheckpointError::Invalid { .. })));
+
+    // A film whose size disagrees with the hash is rejected before it's allocated
+    let larger = RenderSettings {
+        image_width: 30,
+        ..settings(8)
+    };
+    write_checkpoint(&path, hash, 7, &RenderState::new(&larger)).unwrap();
+    let resized = read_checkpoint(&path, &settings(8), b"cornell_box");
+    fs::remove_file(&path).unwrap();
+    match resized {
+        Err(CheckpointError::Invalid { message, .. }) => {
+            assert_eq!(message, "30x20 film in a 20x20 render")
+        }
+        _ => panic!("read a checkpoint of the wrong size"),
+    }
+}
+
+#[test]
+fn aov_layers_are_saved_with_the_film() {
+    let scene = _cornell_box(1.0);
+    let with_aovs = RenderSettings {
+        aovs: AovSet::all(),
+        ..settings(2)
+    };
+    let path = temp_path("aovs");
+
+    let layers = with_aovs.aovs.layers(scene.lights.len());
+    let mut state = RenderState::with_layers(&with_aovs, layers);
+    render_state(&with_aovs, &scene, &mut state);
+    let hash = settings_hash(&with_aovs, b"cornell_box");
+    write_checkpoint(&path, hash, 7, &state).unwrap();
+
+    let checkpoint = read_checkpoint(&path, &with_aovs, b"cornell_box").unwrap();
+    fs::remove_file(&path).unwrap();
+    let film = &checkpoint.state.film;
+    assert_eq!(film.layers(), state.film.layers());
+    for y in 0..20 {
+        for x in 0..20 {
+            assert_eq!(film.aov_sums(x, y), state.film.aov_sums(x, y));
+        }
+    }
+
+    assert_ne!(hash, settings_hash(&settings(2), b"cornell_box"));
+}
+
+#[test]
+fn light_layers_are_checked_against_the_scene() {
+    let scene = _cornell_box(1.0);
+    let with_lights = RenderSettings {
+        aovs: AovSet::all(),
+        ..settings(1)
+    };
+    let hash = settings_hash(&with_lights, b"cornell_box");
+    let path = temp_path("lights");
+
+    // Numbered consistently, but for more lights than the scene has
+    let state = RenderState::with_layers(&with_lights, with_lights.aovs.layers(3));
+    write_checkpoint(&path, hash, 7, &state).unwrap();
+    let checkpoint = read_checkpoint(&path, &with_lights, b"cornell_box").unwrap();
+    match checkpoint.check_lights(&path, &with_lights, scene.lights.len()) {
+        Err(CheckpointError::Invalid { message, .. }) => {
+            assert_eq!(message, "AOV layers for 3 lights, the scene has 1")
+        }
+        _ => panic!("resumed with the light layers of another scene"),
+    }
+
+    let mut layers = with_lights.aovs.layers(1);
+    layers.push(Layer {
+        aov: Aov::Lights,
+        light: 5,
+    });
+    let state = RenderState::with_layers(&with_lights, layers);
+    write_checkpoint(&path, hash, 7, &state).unwrap();
+    let numbered = read_checkpoint(&path, &with_lights, b"cornell_box");
+    fs::remove_file(&path).unwrap();
+    match numbered {
+        Err(CheckpointError::Invalid { message, .. }) => {
+            assert_eq!(message, "AOV layers differ from the render's")
+        }
+        _ => panic!("read a light layer out of order"),
+    }
+
+    let state = RenderState::with_layers(&with_lights, with_lights.aovs.layers(1));
+    let checkpoint = Checkpoint {
+        settings_hash: hash,
+        scene_seed: 7,
+        state,
+    };
+    assert!(checkpoint
+        .check_lights(&path, &with_lights, scene.lights.len())
+        .is_ok());
+}
@@ -0,0 +1,41 @@
+// Small seeded renders, shared by the tests that render a scene. Each test
+// uses only some of these.
+#![allow(dead_code)]
+
+use raytracer::{film::*, render::*, scenes::*, vec3::*};
+
+// 16x16 pixels at 8 samples and a depth of 8 with a fixed seed, then `adjust`
+pub fn settings(adjust: impl FnOnce(&mut RenderSettings)) -> RenderSettings {
+    let mut settings = RenderSettings {
+        image_width: 16,
+        image_height: 16,
+        samples_per_pixel: 8,
+        max_depth: 8,
+        seed: Some(7),
+        ..RenderSettings::default()
+    };
+    adjust(&mut settings);
+    settings
+}
+
+// Takes `state` on to the samples per pixel of `settings`
+pub fn render_state(settings: &RenderSettings, scene: &Scene, state: &mut RenderState) {
+    render_progressive(settings, scene, state, |_, _| Ok::<_, ()>(())).unwrap();
+}
+
+// A new film with every AOV layer the settings need
+pub fn render(settings: &RenderSettings, scene: &Scene) -> Film {
+    let layers = settings.film_aovs().layers(scene.lights.len());
+    let mut state = RenderState::with_layers(settings, layers);
+    render_state(settings, scene, &mut state);
+    state.film
+}
+
+// The image of `render` on a pool of `threads` threads
+pub fn render_pixels(settings: &RenderSettings, scene: &Scene, threads: usize) -> Vec<Colour> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    pool.install(|| render(settings, scene)).pixels().collect()
+}
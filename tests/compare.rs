@@ -0,0 +1,55 @@
+use raytracer::{compare::*, vec3::*};
+
+// Smooth gradient with a bright square in the middle
+fn gradient(size: u32, offset: F) -> Image {
+    let pixels = (0..size * size)
+        .map(|i| {
+            let (x, y) = ((i % size) as F, (i / size) as F);
+            let square = (size / 4..3 * size / 4).contains(&(i % size))
+                && (size / 4..3 * size / 4).contains(&(i / size));
+            let value = 0.2 + 0.4 * x / size as F + if square { 0.3 } else { 0.0 };
+            Colour::new(value, 0.5 * value + 0.1 * y / size as F, 0.3) + offset
+        })
+        .collect();
+
+    Image::new(size, size, pixels)
+}
+
+#[test]
+fn identical_images_match_perfectly() {
+    let image = gradient(16, 0.0);
+    let metrics = compare(&image, &image);
+
+    assert_eq!(metrics.rmse, 0.0);
+    assert_eq!(metrics.rel_mse, 0.0);
+    assert!((metrics.ssim - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn metrics_grow_with_the_difference() {
+    let reference = gradient(16, 0.0);
+    let near = compare(&reference, &gradient(16, 0.01));
+    let far = compare(&reference, &gradient(16, 0.1));
+
+    assert!((near.rmse - 0.01).abs() < 1e-9);
+    assert!(near.rmse < far.rmse);
+    assert!(near.rel_mse < far.rel_mse);
+    assert!(near.ssim > far.ssim);
+
+    // Losing the structure hurts SSIM far more than a small shift in brightness
+    let flat = Image::new(16, 16, vec![Colour::new(0.45, 0.25, 0.3); 256]);
+    assert!(compare(&reference, &flat).ssim < far.ssim);
+}
+
+#[test]
+fn heatmap_is_white_at_the_largest_error() {
+    let reference = gradient(8, 0.0);
+    let mut pixels = reference.pixels().to_vec();
+    pixels[9] = pixels[9] + 0.5;
+    pixels[20] = pixels[20] + 0.1;
+    let heatmap = diff_heatmap(&reference, &Image::new(8, 8, pixels));
+
+    assert_eq!(heatmap.get_pixel(1, 1).0, [255, 255, 255]);
+    assert!(heatmap.get_pixel(4, 2).0[0] > 0);
+    assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 0]);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use raytracer::{compare::*, denoise::*, film::*, scenes::*, tonemap::*, vec3::*};
+
+fn render(samples_per_pixel: u32, scene: &Scene) -> Film {
+    let settings = common::settings(|settings| {
+        settings.image_width = 48;
+        settings.image_height = 48;
+        settings.samples_per_pixel = samples_per_pixel;
+        settings.seed = Some(9);
+        settings.denoise = Some(Denoiser::default());
+    });
+    common::render(&settings, scene)
+}
+
+fn image(film: &Film, pixels: Vec<Colour>) -> Image {
+    let film = Film::from_pixels(film.width(), film.height(), &pixels);
+    Image::from_film(&film, &ToneMap::default())
+}
+
+#[test]
+fn denoising_brings_a_noisy_render_closer_to_the_reference() {
+    let scene = _cornell_box(1.0);
+    let reference = render(128, &scene);
+    let reference = image(&reference, reference.pixels().collect());
+    let noisy = render(4, &scene);
+
+    let raw = rmse(&reference, &image(&noisy, noisy.pixels().collect()));
+    let denoised = rmse(
+        &reference,
+        &image(&noisy, Denoiser::default().denoise(&noisy)),
+    );
+    assert!(
+        denoised < 0.8 * raw,
+        "rmse {} denoised, {} raw",
+        denoised,
+        raw
+    );
+}
+
+#[test]
+fn zero_strength_leaves_the_image_as_rendered() {
+    let film = render(4, &_cornell_box(1.0));
+    let denoiser = Denoiser {
+        strength: 0.0,
+        ..Denoiser::default()
+    };
+
+    assert!(denoiser.denoise(&film).into_iter().eq(film.pixels()));
+}
+
+#[test]
+fn noise_free_edges_are_kept() {
+    // One sample per pixel has no measured noise to smooth away
+    let pixels: Vec<_> = (0..16 * 16)
+        .map(|i| match i % 16 < 8 {
+            true => Colour::new(0.2, 0.4, 0.6),
+            false => Colour::one(),
+        })
+        .collect();
+    let film = Film::from_pixels(16, 16, &pixels);
+
+    for (denoised, pixel) in Denoiser::default().denoise(&film).iter().zip(&pixels) {
+        assert!((*denoised - *pixel).length() < 1e-12);
+    }
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::*;
+use raytracer::{render::*, sampler::*, scenes::*, vec3::*};
+
+fn settings(tile_size: u32, sampler: SamplerKind) -> RenderSettings {
+    common::settings(|settings| {
+        settings.image_width = 24;
+        settings.image_height = 24;
+        settings.samples_per_pixel = 6;
+        settings.seed = Some(11);
+        settings.tile_size = tile_size;
+        settings.sampler = sampler;
+    })
+}
+
+#[test]
+fn same_seed_gives_same_image_on_any_tiling_and_thread_count() {
+    // Media, Perlin noise and a random layout draw numbers while building and rendering
+    let scene = with_seed(3, || _final_scene(1.0));
+
+    for sampler in &[SamplerKind::Independent, SamplerKind::Sobol] {
+        let reference = render_pixels(&settings(32, *sampler), &scene, 1);
+        assert_eq!(render_pixels(&settings(5, *sampler), &scene, 3), reference);
+        assert_eq!(render_pixels(&settings(8, *sampler), &scene, 2), reference);
+    }
+}
+
+#[test]
+fn scene_seed_fixes_the_built_scene() {
+    let settings = RenderSettings {
+        background: Colour::new(0.7, 0.8, 1.0),
+        ..settings(8, SamplerKind::Independent)
+    };
+    let first = render_pixels(&settings, &with_seed(3, || _random_scene(1.0)), 1);
+    let again = render_pixels(&settings, &with_seed(3, || _random_scene(1.0)), 1);
+    let other = render_pixels(&settings, &with_seed(4, || _random_scene(1.0)), 1);
+
+    assert_eq!(first, again);
+    assert_ne!(first, other);
+}
+
+#[test]
+fn with_seed_restores_the_thread_generator() {
+    seed_thread_rng(5, 0);
+    let expected: Vec<F> = (0..4).map(|_| random()).collect();
+
+    seed_thread_rng(5, 0);
+    let inner: Vec<F> = with_seed(9, || (0..4).map(|_| random()).collect());
+    let outer: Vec<F> = (0..4).map(|_| random()).collect();
+
+    assert_eq!(outer, expected);
+    assert_ne!(inner, expected);
+}
@@ -0,0 +1,67 @@
+use raytracer::{film::*, render::*, vec3::*};
+
+fn assert_close(a: F, b: F) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+fn welford(values: &[F]) -> Welford {
+    let mut stats = Welford::default();
+    for &value in values {
+        stats.add(value);
+    }
+    stats
+}
+
+#[test]
+fn welford_matches_two_pass_variance() {
+    let values = [0.5, 2.0, 0.0, 3.5, 1.25, 0.75];
+    let stats = welford(&values);
+
+    let n = values.len() as F;
+    let mean = values.iter().sum::<F>() / n;
+    let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<F>() / (n - 1.0);
+
+    assert_eq!(stats.count(), 6);
+    assert_close(stats.mean(), mean);
+    assert_close(stats.variance(), variance);
+}
+
+#[test]
+fn merged_welford_matches_sequential() {
+    let values = [0.1, 4.0, 2.5, 0.0, 0.0, 7.25, 1.0];
+    let mut merged = welford(&values[..3]);
+    merged.merge(&welford(&values[3..]));
+    merged.merge(&Welford::default());
+    let sequential = welford(&values);
+
+    assert_eq!(merged.count(), sequential.count());
+    assert_close(merged.mean(), sequential.mean());
+    assert_close(merged.m2(), sequential.m2());
+}
+
+#[test]
+fn adaptive_waits_for_min_samples_and_low_error() {
+    let adaptive = Adaptive {
+        threshold: 0.05,
+        min_samples: 4,
+    };
+
+    // No variance, but too few samples
+    assert!(!adaptive.converged(&welford(&[1.0, 1.0, 1.0])));
+    assert!(adaptive.converged(&welford(&[1.0; 4])));
+    // Black pixels converge too
+    assert!(adaptive.converged(&welford(&[0.0; 4])));
+    assert!(!adaptive.converged(&welford(&[0.0, 2.0, 0.0, 2.0])));
+}
+
+#[test]
+fn film_averages_samples_per_pixel() {
+    let mut film = Film::new(2, 1);
+    film.add_sample(0, 0, Colour::new(1.0, 0.0, 0.0));
+    film.add_sample(0, 0, Colour::new(0.0, 1.0, 0.0));
+
+    assert_eq!(film.samples(0, 0), 2);
+    assert_eq!(film.samples(1, 0), 0);
+    assert_eq!(film.pixel(0, 0), Colour::new(0.5, 0.5, 0.0));
+    assert_eq!(film.pixel(1, 0), Colour::zero());
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+use raytracer::{filter::*, hittable::*, render::*, scenes::*, vec3::*};
+
+fn settings(filter: Filter) -> RenderSettings {
+    common::settings(|settings| {
+        settings.image_width = 24;
+        settings.image_height = 24;
+        settings.samples_per_pixel = 6;
+        settings.tile_size = 8;
+        settings.filter = filter;
+    })
+}
+
+#[test]
+fn filters_parse_by_name() {
+    for kind in &FilterKind::ALL {
+        assert_eq!(kind.to_string().parse::<FilterKind>(), Ok(*kind));
+    }
+    assert!("sinc".parse::<FilterKind>().is_err());
+    assert_eq!(Filter::default(), Filter::new(FilterKind::Box));
+}
+
+#[test]
+fn weights_vanish_outside_the_radius() {
+    let margins: Vec<_> = FilterKind::ALL
+        .iter()
+        .map(|kind| Filter::new(*kind).margin())
+        .collect();
+    assert_eq!(margins, [0, 1, 1, 2, 3]);
+
+    for kind in &FilterKind::ALL {
+        let filter = Filter::new(*kind);
+        assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        assert_eq!(filter.evaluate(filter.radius + 0.01, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.0, -filter.radius - 0.01), 0.0);
+    }
+
+    assert_eq!(Filter::new(FilterKind::Box).evaluate(0.5, -0.5), 1.0);
+    assert_eq!(Filter::new(FilterKind::Tent).evaluate(0.5, 0.0), 0.5);
+    // The sharpening lobes
+    assert!(Filter::new(FilterKind::Mitchell).evaluate(1.5, 0.0) < 0.0);
+    assert!(Filter::new(FilterKind::Lanczos).evaluate(1.5, 0.0) < 0.0);
+}
+
+#[test]
+fn weights_are_normalised_up_to_the_edges() {
+    // Every sample sees the same background, so any filter must give it back
+    let background = Colour::new(0.3, 0.5, 0.9);
+    let scene = Scene::new(_cornell_box(1.0).camera, HittableList::new());
+
+    for kind in &FilterKind::ALL {
+        let settings = RenderSettings {
+            background,
+            ..settings(Filter::new(*kind))
+        };
+        for pixel in render_pixels(&settings, &scene, 1) {
+            assert!(
+                (pixel - background).length() < 1e-12,
+                "{} {:?}",
+                kind,
+                pixel
+            );
+        }
+    }
+}
+
+#[test]
+fn splats_across_tiles_add_up_the_same_on_any_thread_count() {
+    let scene = _cornell_box(1.0);
+
+    for kind in &[FilterKind::Mitchell, FilterKind::Lanczos] {
+        let settings = settings(Filter::new(*kind));
+        let reference = render_pixels(&settings, &scene, 1);
+        assert_eq!(render_pixels(&settings, &scene, 3), reference);
+        assert_ne!(
+            reference,
+            render_pixels(&self::settings(Filter::default()), &scene, 1)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use raytracer::{compare::*, render::*, scenes::*, vec3::*};
+
+// Renders are deterministic, so the tolerances only absorb floating point
+// differences between platforms. Changes to materials, sampling or how scenes and
+// their BVHs are built will fail, run with UPDATE_GOLDEN=1 to accept them.
+const MAX_RMSE: F = 0.01;
+const MAX_REL_MSE: F = 0.001;
+const MIN_SSIM: F = 0.99;
+
+const SEED: u64 = 1;
+
+fn settings(scene: &BuiltinScene) -> RenderSettings {
+    RenderSettings {
+        image_width: 32,
+        image_height: 32,
+        samples_per_pixel: 32,
+        max_depth: 10,
+        background: scene.background(),
+        seed: Some(SEED),
+        ..RenderSettings::default()
+    }
+}
+
+fn render(scene: &BuiltinScene) -> Image {
+    let settings = settings(scene);
+    let built = with_seed(SEED, || (scene.build)(settings.aspect_ratio()));
+
+    let mut state = RenderState::new(&settings);
+    render_progressive(&settings, &built, &mut state, |_, _| Ok::<_, ()>(())).unwrap();
+
+    Image::from_film(&state.film, &settings.tone_map)
+}
+
+// Leaves the render and a heatmap of its difference next to the test binaries
+fn write_failure(name: &str, reference: &Image, image: &Image) -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&directory).unwrap();
+
+    image
+        .write(directory.join(format!("{}.png", name)))
+        .unwrap();
+    diff_heatmap(reference, image)
+        .save(directory.join(format!("{}_diff.png", name)))
+        .unwrap();
+
+    directory
+}
+
+#[test]
+fn builtin_scenes_match_golden_images() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    for scene in SCENES {
+        let image = render(scene);
+        let path = golden.join(format!("{}.png", scene.name));
+
+        if update {
+            image.write(&path).unwrap();
+            continue;
+        }
+
+        let reference = match Image::read(&path) {
+            Ok(reference) => reference,
+            Err(err) => {
+                failures.push(format!("{}: {}", path.display(), err));
+                continue;
+            }
+        };
+        if (reference.width(), reference.height()) != (image.width(), image.height()) {
+            failures.push(format!("{}: reference differs in size", scene.name));
+            continue;
+        }
+
+        let metrics = compare(&reference, &image);
+        if metrics.rmse > MAX_RMSE || metrics.rel_mse > MAX_REL_MSE || metrics.ssim < MIN_SSIM {
+            let directory = write_failure(scene.name, &reference, &image);
+            failures.push(format!(
+                "{}: rmse {:.4}, relMSE {:.4}, SSIM {:.4}, render and diff in {}",
+                scene.name,
+                metrics.rmse,
+                metrics.rel_mse,
+                metrics.ssim,
+                directory.display()
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "golden images differ, run with UPDATE_GOLDEN=1 to accept the new renders\n{}",
+        failures.join("\n")
+    );
+}
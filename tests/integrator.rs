@@ -0,0 +1,167 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::*;
+use raytracer::{
+    bvh::*, debug_integrator::*, hittable::*, integrator::*, material::*, ray::*, render::*,
+    sampler::*, scenes::*, sphere::*, vec3::*,
+};
+
+fn settings(russian_roulette_depth: u32, depth_limits: DepthLimits) -> RenderSettings {
+    common::settings(|settings| {
+        settings.samples_per_pixel = 64;
+        // Deep enough for russian roulette to be what ends most paths
+        settings.max_depth = RenderSettings::default().max_depth;
+        settings.seed = Some(3);
+        settings.russian_roulette_depth = russian_roulette_depth;
+        settings.depth_limits = depth_limits;
+    })
+}
+
+fn mean_radiance(settings: &RenderSettings, scene: &Scene) -> Colour {
+    let pixels = (settings.image_width * settings.image_height) as F;
+    render(settings, scene)
+        .pixels()
+        .fold(Colour::zero(), |sum, c| sum + c)
+        / pixels
+}
+
+#[test]
+fn russian_roulette_keeps_the_mean() {
+    let scene = _cornell_box(1.0);
+    let full = mean_radiance(&settings(u32::MAX, DepthLimits::default()), &scene);
+    let roulette = mean_radiance(&settings(1, DepthLimits::default()), &scene);
+
+    for (a, b) in [(full.x(), roulette.x()), (full.y(), roulette.y())] {
+        assert!(
+            (a - b).abs() < 0.05 * a,
+            "{} without roulette, {} with",
+            a,
+            b
+        );
+    }
+}
+
+#[test]
+fn depth_limits_cut_off_indirect_light() {
+    let scene = _cornell_box(1.0);
+    let full = mean_radiance(&settings(3, DepthLimits::default()), &scene);
+    let direct = DepthLimits {
+        diffuse: 0,
+        ..DepthLimits::default()
+    };
+    let direct = mean_radiance(&settings(3, direct), &scene);
+
+    assert!(direct.y() > 0.0);
+    assert!(
+        direct.y() < 0.8 * full.y(),
+        "{} of {}",
+        direct.y(),
+        full.y()
+    );
+}
+
+#[test]
+fn any_hittable_can_be_the_world() {
+    let scene = _cornell_box(1.0);
+    let bvh = LinearBVH::new(&scene.world, SahOptions::default(), 0.0, 1.0);
+    let integrator = PathIntegrator::new(&settings(3, DepthLimits::default()));
+
+    for i in 0..64 {
+        let (u, v) = ((i % 8) as F / 8.0 + 0.05, (i / 8) as F / 8.0 + 0.05);
+        let ray = scene.camera.get_ray(u, v, &mut Independent);
+        let radiance = |world: &dyn Hittable| {
+            seed_thread_rng(i, 0);
+            integrator.radiance(&ray, Colour::zero(), world, &scene.lights, &mut Independent)
+        };
+
+        assert_eq!(radiance(&bvh), radiance(&scene.world));
+    }
+}
+
+// Unit sphere at the origin inside a hollow sphere of radius 10
+fn nested_spheres() -> HittableList {
+    let material: Arc<M> = Arc::new(Lambertian::rgb(0.25, 0.5, 0.75));
+    let mut world = HittableList::new();
+    world.add(Arc::new(Sphere::new(
+        Point3::zero(),
+        1.0,
+        Arc::clone(&material),
+    )));
+    world.add(Arc::new(Sphere::new(Point3::zero(), 10.0, material)));
+    world
+}
+
+fn first_hit_view(integrator: &dyn Integrator, world: &dyn Hittable) -> Colour {
+    let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+    let lights = HittableList::new();
+    integrator.radiance(&ray, Colour::one(), world, &lights, &mut Independent)
+}
+
+#[test]
+fn debug_views_show_the_first_hit() {
+    let world = nested_spheres();
+
+    assert_eq!(
+        first_hit_view(&NormalsIntegrator, &world),
+        Colour::new(0.5, 0.5, 1.0)
+    );
+    assert_eq!(
+        first_hit_view(&DepthIntegrator, &world),
+        Colour::one() * 4.0
+    );
+    assert_eq!(
+        first_hit_view(&AlbedoIntegrator, &world),
+        Colour::new(0.25, 0.5, 0.75)
+    );
+
+    // The outer sphere is always 9 away, so only a radius beyond it occludes
+    let near = AmbientOcclusionIntegrator { radius: 8.0 };
+    let far = AmbientOcclusionIntegrator { radius: 12.0 };
+    assert_eq!(first_hit_view(&near, &world), Colour::one());
+    assert_eq!(first_hit_view(&far, &world), Colour::zero());
+}
+
+#[test]
+fn bvh_cost_counts_traversal_work() {
+    let world = nested_spheres();
+    let bvh = LinearBVH::new(&world, SahOptions::default(), 0.0, 1.0);
+
+    let (hit, cost) = measure_traversal(|| {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        bvh.hit(&ray, 0.001, F::INFINITY)
+    });
+    assert!(hit.is_some());
+    assert_eq!(cost.intersections, 2);
+    assert!(cost.nodes >= 1);
+
+    // Without a BVH there is no traversal to count
+    assert_eq!(first_hit_view(&BvhCostIntegrator, &world), Colour::zero());
+    assert_ne!(first_hit_view(&BvhCostIntegrator, &bvh), Colour::zero());
+}
+
+#[test]
+fn integrator_names_round_trip() {
+    for kind in IntegratorKind::ALL.iter() {
+        assert_eq!(kind.name().parse::<IntegratorKind>(), Ok(*kind));
+    }
+    assert!("whitted".parse::<IntegratorKind>().is_err());
+}
+
+#[test]
+fn scenes_without_lights_are_lit_by_what_paths_find() {
+    let lights = HittableList::new();
+    let direction = lights.random(Point3::zero(), &mut Independent);
+    assert_eq!(lights.pdf_value(Point3::zero(), direction), 0.0);
+
+    // The lamp is still in the world, only nothing samples it directly
+    let cornell_box = _cornell_box(1.0);
+    let scene = Scene::new(cornell_box.camera, cornell_box.world);
+    let settings = RenderSettings {
+        samples_per_pixel: 8,
+        ..settings(3, DepthLimits::default())
+    };
+    let radiance = mean_radiance(&settings, &scene);
+    assert!(radiance.y() > 0.0 && radiance.y().is_finite());
+}
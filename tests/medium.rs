@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use raytracer::{aarect::*, hittable::*, material::*, medium::*, ray::*, texture::*, vec3::*};
+
+fn fog() -> ConstantMedium {
+    let boundary = Arc::new(AABox::new(
+        Point3::new(-1.0, -1.0, -1.0),
+        Point3::new(1.0, 1.0, 1.0),
+        Arc::new(Lambertian::rgb(0.5, 0.5, 0.5)),
+    ));
+    ConstantMedium::new(boundary, Arc::new(SolidColour::rgb(0.5, 0.5, 0.5)), 0.5)
+}
+
+#[test]
+fn scatter_distances_come_from_the_ray_sample() {
+    let fog = fog();
+    // Twice as long as a unit direction, so t is half the distance travelled
+    let ray = |u: F| {
+        Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), 0.0).with_medium_sample(u)
+    };
+
+    for u in [0.0, 0.3, 0.6] {
+        let hit_record = fog.hit(&ray(u), 0.001, F::INFINITY).unwrap();
+        let distance = -(1.0 - u).ln() / 0.5;
+        assert!((hit_record.t() - (2.0 + distance / 2.0)).abs() < 1e-9);
+        assert!(hit_record.material().is_volume());
+    }
+
+    // Past the two units of fog the ray goes straight through
+    assert!(fog.hit(&ray(0.9), 0.001, F::INFINITY).is_none());
+}
@@ -0,0 +1,91 @@
+use std::{env, fs, path::PathBuf, process};
+
+use raytracer::{hittable::*, obj::*, ray::*, vec3::*};
+
+const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("raytracer_{}_{}.obj", name, process::id()))
+}
+
+fn load(name: &str, source: &str) -> Result<HittableList, ObjError> {
+    let path = temp_path(name);
+    fs::write(&path, source).unwrap();
+    let result = load_obj(&path, 0.0, 1.0);
+    fs::remove_file(&path).unwrap();
+    result
+}
+
+// The error of loading `source`, which must be on `line`
+fn assert_error(name: &str, source: &str, line: usize, message: &str) {
+    let error = load(name, source)
+        .err()
+        .expect("loaded an invalid file")
+        .to_string();
+    let prefix = format!("{}:{}: ", temp_path(name).display(), line);
+
+    assert!(error.starts_with(&prefix), "{}", error);
+    assert!(error.ends_with(message), "{}", error);
+}
+
+#[test]
+fn out_of_range_indices_name_their_line() {
+    let source = format!("{}f 1 2 4\n", TRIANGLE);
+    assert_error("range", &source, 4, "vertex index 4 out of range (1..=3)");
+
+    let source = format!("{}vt 0 0\nf 1/1 2/1 3/-2\n", TRIANGLE);
+    assert_error(
+        "texture_range",
+        &source,
+        5,
+        "texture index -2 out of range (1..=1)",
+    );
+}
+
+#[test]
+fn bad_numbers_name_their_line() {
+    assert_error("number", "v 0 0 0\nv 1 x 0\n", 2, "invalid number 'x'");
+    assert_error("short", "v 0 0\n", 1, "expected 3 numbers, found 2");
+}
+
+#[test]
+fn unknown_materials_name_their_line() {
+    let source = format!("{}usemtl missing\nf 1 2 3\n", TRIANGLE);
+    assert_error("usemtl", &source, 4, "unknown material 'missing'");
+}
+
+#[test]
+fn faces_need_three_vertices() {
+    let source = format!("# a line\n{}f 1 2\n", TRIANGLE);
+    assert_error(
+        "face",
+        &source,
+        5,
+        "face needs at least 3 vertices, found 2",
+    );
+}
+
+#[test]
+fn texture_coordinates_take_one_to_three_components() {
+    let source = format!("{}vt 0.25\nvt 0.5 0.5 0\nvt 1 1\nf 1/1 2/2 3/3\n", TRIANGLE);
+    let world = load("vt", &source).unwrap();
+
+    let ray = Ray::new(Point3::new(0.2, 0.3, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let hit_record = world.hit(&ray, 0.001, F::INFINITY).unwrap();
+    // Weights 0.5, 0.2 and 0.3, with v of the first vertex 0
+    assert!((hit_record.tp().u() - 0.525).abs() < 1e-9);
+    assert!((hit_record.tp().v() - 0.4).abs() < 1e-9);
+}
+
+#[test]
+fn files_without_faces_are_an_error() {
+    // Would otherwise leave an empty mesh with no bounding box for the BVH
+    let error = load("empty", TRIANGLE)
+        .err()
+        .expect("loaded a file without faces");
+    assert!(matches!(error, ObjError::NoFaces { .. }));
+    assert_eq!(
+        error.to_string(),
+        format!("{}: no faces", temp_path("empty").display())
+    );
+}
@@ -0,0 +1,112 @@
+use std::{env, fs, io::BufReader, path::PathBuf, process};
+
+use image::{codecs::hdr::HdrDecoder, ImageFormat};
+use raytracer::{aov::*, film::*, output::*, tonemap::*, vec3::*};
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("raytracer_{}_{}", process::id(), name))
+}
+
+fn film() -> Film {
+    let pixels = [
+        Colour::new(0.0, 0.25, 0.5),
+        Colour::new(1.0, 2.0, 4.0),
+        Colour::new(100.0, 0.001, 3.5),
+        Colour::new(12.5, 12.5, 12.5),
+    ];
+    Film::from_pixels(2, 2, &pixels)
+}
+
+#[test]
+fn hdr_keeps_values_above_one() {
+    let film = film();
+    let path = temp_path("round_trip.hdr");
+    write_hdr(&film, &path).unwrap();
+    let file = fs::File::open(&path).unwrap();
+    let decoder = HdrDecoder::new(BufReader::new(file)).unwrap();
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!((metadata.width, metadata.height), (2, 2));
+    for (read, written) in pixels.iter().zip(film.pixels()) {
+        let written = [written.x(), written.y(), written.z()];
+        // RGBE shares one exponent between the channels, keeping 8 bits of the largest
+        let largest = written.iter().cloned().fold(0.0, F::max);
+        for (read, written) in read.0.iter().zip(&written) {
+            assert!(
+                (*read as F - written).abs() <= largest / 128.0,
+                "{} read back as {}",
+                written,
+                read
+            );
+        }
+    }
+}
+
+#[test]
+fn ppm_holds_the_tone_mapped_image() {
+    let film = film();
+    let tone_map = ToneMap {
+        operator: ToneMapOperator::Aces,
+        ..ToneMap::default()
+    };
+    let path = temp_path("round_trip.ppm");
+    write_ppm(&film, &path, &tone_map).unwrap();
+    let read = image::open(&path).unwrap().to_rgb8();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(read, to_rgb8(&film, &tone_map));
+    // Black stays black and brighter than white saturates
+    assert_eq!(read.get_pixel(0, 0).0[0], 0);
+    assert_eq!(read.get_pixel(0, 1).0[0], 255);
+}
+
+#[test]
+fn positions_at_the_origin_are_hits() {
+    // Left to right a hit at the origin, a miss and two hits either side
+    let layers = vec![
+        Layer {
+            aov: Aov::Position,
+            light: 0,
+        },
+        Layer {
+            aov: Aov::ObjectId,
+            light: 0,
+        },
+    ];
+    let mut film = Film::with_layers(4, 2, layers);
+    let hits = [
+        (Point3::zero(), 1.0),
+        (Point3::zero(), 0.0),
+        (Point3::new(-1.0, -1.0, -1.0), 2.0),
+        (Point3::new(1.0, 1.0, 1.0), 2.0),
+    ];
+    for y in 0..2 {
+        for (x, (position, id)) in hits.iter().enumerate() {
+            let mut samples = PixelSamples::default();
+            samples.add(Colour::zero());
+            samples.add_aovs([*position, Colour::one() * *id].iter().copied());
+            film.add_samples(x as u32, y, &samples);
+        }
+    }
+
+    let path = temp_path("position.png");
+    write_layer(
+        &film,
+        0,
+        &path,
+        ImageFormat::Png,
+        BitDepth::Eight,
+        &ToneMap::default(),
+    )
+    .unwrap();
+    let read = image::open(&path).unwrap().to_rgb8();
+    fs::remove_file(&path).unwrap();
+
+    let red = |x| read.get_pixel(x, 0).0[0];
+    assert_eq!(red(0), 128);
+    assert_eq!(red(1), 0);
+    assert_eq!(red(2), 0);
+    assert_eq!(red(3), 255);
+}
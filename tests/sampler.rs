@@ -0,0 +1,120 @@
+use std::f64::consts::PI;
+
+use raytracer::{sampler::*, vec3::*};
+
+const PIXELS: u32 = 64;
+
+// Root mean square error of the estimates of `integral` made by each of PIXELS
+// pixels with `samples` samples, reading the 2D dimension `pair` of each sample
+fn rmse(kind: SamplerKind, samples: u32, pair: usize, f: impl Fn(F, F) -> F, integral: F) -> F {
+    seed_thread_rng(1, 0);
+    let mut sampler = kind.create(samples, 7);
+    let mut squared_error = 0.0;
+
+    for pixel in 0..PIXELS {
+        let mut sum = 0.0;
+        for index in 0..samples {
+            sampler.start_pixel_sample(pixel, 3, index);
+            for _ in 0..pair {
+                sampler.next_2d();
+            }
+            let (u, v) = sampler.next_2d();
+            sum += f(u, v);
+        }
+        let error = sum / samples as F - integral;
+        squared_error += error * error;
+    }
+
+    (squared_error / PIXELS as F).sqrt()
+}
+
+fn smooth(u: F, v: F) -> F {
+    u * v * v
+}
+
+fn disc(u: F, v: F) -> F {
+    if u * u + v * v < 1.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[test]
+fn samples_lie_in_the_unit_interval() {
+    for kind in SamplerKind::ALL.iter() {
+        let mut sampler = kind.create(16, 3);
+        for index in 0..64 {
+            sampler.start_pixel_sample(5, 9, index);
+            for _ in 0..20 {
+                let u = sampler.next_1d();
+                let (v, w) = sampler.next_2d();
+                for value in &[u, v, w] {
+                    assert!((0.0..1.0).contains(value), "{} gave {}", kind, value);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn samplers_converge_faster_than_independent() {
+    // Halton's larger bases need a few hundred samples before they pull ahead
+    for &(pair, name) in &[(0, "first"), (4, "fifth")] {
+        let independent = rmse(SamplerKind::Independent, 256, pair, disc, PI / 4.0);
+
+        for kind in SamplerKind::ALL.iter().skip(1) {
+            let error = rmse(*kind, 256, pair, disc, PI / 4.0);
+            assert!(
+                error < 0.6 * independent,
+                "{} on the {} dimension pair: rmse {} against {} independent",
+                kind,
+                name,
+                error,
+                independent
+            );
+        }
+    }
+}
+
+#[test]
+fn sobol_converges_faster_than_monte_carlo_rate() {
+    // Independent samples halve the error for 4x the samples, Sobol does far better
+    for &(f, integral) in &[(smooth as fn(F, F) -> F, 1.0 / 6.0), (disc, PI / 4.0)] {
+        let coarse = rmse(SamplerKind::Sobol, 16, 0, f, integral);
+        let fine = rmse(SamplerKind::Sobol, 256, 0, f, integral);
+        assert!(
+            fine < coarse / 8.0,
+            "rmse {} at 16 spp, {} at 256",
+            coarse,
+            fine
+        );
+    }
+
+    let coarse = rmse(SamplerKind::Independent, 16, 0, smooth, 1.0 / 6.0);
+    let fine = rmse(SamplerKind::Independent, 256, 0, smooth, 1.0 / 6.0);
+    assert!(fine > coarse / 8.0);
+}
+
+#[test]
+fn blue_noise_mask_holds_every_rank_once() {
+    let size = BLUE_NOISE_SIZE as usize;
+    let mut ranks: Vec<_> = blue_noise()
+        .iter()
+        .map(|value| (value * (size * size) as F) as usize)
+        .collect();
+    ranks.sort_unstable();
+
+    assert!(ranks.iter().enumerate().all(|(i, &rank)| i == rank));
+
+    // Neighbours differ far more than they would in white noise, where the
+    // mean absolute difference is 1/3
+    let difference: F = (0..size * size)
+        .map(|i| {
+            let right = (i / size) * size + (i + 1) % size;
+            (blue_noise()[i] - blue_noise()[right]).abs()
+        })
+        .sum::<F>()
+        / (size * size) as F;
+    assert!(difference > 0.4, "mean neighbour difference {}", difference);
+}
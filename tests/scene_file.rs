@@ -0,0 +1,226 @@
+use std::{env, fs, process};
+
+use raytracer::{hittable::*, ray::*, render::*, scene_file::*, scenes::*, vec3::*};
+
+const CAMERA: &str = r#"
+[camera]
+look_from = [0.0, 0.0, 5.0]
+look_at = [0.0, 0.0, 0.0]
+vfov = 40.0
+"#;
+
+fn parse(source: &str) -> Result<SceneFile, SceneError> {
+    SceneFile::parse(&format!("{}{}", CAMERA, source), "scenes/test.toml")
+}
+
+fn build(source: &str) -> Result<Scene, SceneError> {
+    parse(source)?.build(1.0)
+}
+
+fn assert_error(result: Result<impl Sized, SceneError>, message: &str) {
+    match result {
+        Ok(_) => panic!("expected '{}'", message),
+        Err(error) => assert!(
+            error.to_string().contains(message),
+            "'{}' doesn't contain '{}'",
+            error,
+            message
+        ),
+    }
+}
+
+#[test]
+fn references_name_the_entry_they_come_from() {
+    let sphere = |material: &str| {
+        format!(
+            r#"
+[textures.checker]
+type = "checkered"
+odd = [0.0, 0.0, 0.0]
+even = [1.0, 1.0, 1.0]
+
+[materials.grey]
+type = "lambertian"
+albedo = "missing"
+
+[[objects]]
+type = "sphere"
+centre = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "{}"
+"#,
+            material
+        )
+    };
+
+    assert_error(
+        build(&sphere("chrome")),
+        "objects[0].material: unknown material 'chrome'",
+    );
+    assert_error(
+        build(&sphere("checker")),
+        "objects[0].material: 'checker' is a texture, expected a material",
+    );
+    assert_error(
+        build(&sphere("grey")),
+        "materials.grey.albedo: unknown texture 'missing'",
+    );
+}
+
+#[test]
+fn unknown_kinds_and_bad_vectors_are_syntax_errors() {
+    let cone = r#"
+[[objects]]
+type = "cone"
+"#;
+    assert_error(parse(cone), "scenes/test.toml: ");
+    assert_error(parse(cone), "unknown variant `cone`");
+
+    let short = r#"
+[[objects]]
+type = "sphere"
+centre = [0.0, 0.0]
+radius = 1.0
+material = "grey"
+"#;
+    assert_error(parse(short), "invalid length 2");
+
+    assert_error(parse("[image]\nwidht = 100\n"), "unknown field `widht`");
+}
+
+#[test]
+fn image_settings_are_checked() {
+    let settings = |image: &str| parse(&format!("[image]\n{}\n", image))?.settings();
+
+    assert_error(
+        settings("sampler = \"random\""),
+        "image.sampler: unknown sampler",
+    );
+    assert_error(
+        settings("aovs = [\"shadow\"]"),
+        "image.aovs: unknown AOV 'shadow'",
+    );
+    assert_error(
+        settings("adaptive_threshold = -1.0"),
+        "image.adaptive_threshold: must be a positive number",
+    );
+    assert_error(
+        settings("filter_radius = 0.0"),
+        "image.filter_radius: must be a positive number",
+    );
+
+    // Sizes and sample counts that would divide by zero
+    assert_error(
+        settings("width = 1"),
+        "image.width: image must be at least 2x2 pixels, not 1x1",
+    );
+    assert_error(
+        settings("width = 100\naspect_ratio = 100.0"),
+        "image.aspect_ratio: image must be at least 2x2 pixels, not 100x1",
+    );
+    assert_error(
+        settings("samples_per_pixel = 0"),
+        "image.samples_per_pixel: samples per pixel must be at least 1",
+    );
+    let settings = RenderSettings {
+        image_height: 0,
+        ..RenderSettings::default()
+    };
+    assert_eq!(
+        settings.validate(),
+        Err(SettingsError::ImageSize {
+            width: 800,
+            height: 0
+        })
+    );
+    assert_eq!(RenderSettings::default().validate(), Ok(()));
+}
+
+#[test]
+fn a_valid_scene_builds_its_objects_and_lights() {
+    let scene = SceneFile::load("scenes/cornell_box.toml").unwrap();
+    let settings = scene.settings().unwrap();
+    assert_eq!((settings.image_width, settings.image_height), (600, 600));
+    assert_eq!(settings.samples_per_pixel, 200);
+
+    let scene = scene.build(settings.aspect_ratio()).unwrap();
+    let builtin = _cornell_box(1.0);
+    assert_eq!(scene.world.len(), builtin.world.len());
+    assert_eq!(scene.lights.len(), 1);
+
+    // The same walls in the same places
+    let ray = Ray::new(
+        Point3::new(278.0, 278.0, -800.0),
+        Vec3::new(0.1, -0.05, 1.0),
+        0.0,
+    );
+    let (from_file, from_code) = (
+        scene.world.hit(&ray, 0.001, F::INFINITY).unwrap(),
+        builtin.world.hit(&ray, 0.001, F::INFINITY).unwrap(),
+    );
+    assert_eq!(from_file.t(), from_code.t());
+    assert_eq!(from_file.object_id(), from_code.object_id());
+}
+
+#[test]
+fn rotated_emitters_are_lights() {
+    let scene = build(
+        r#"
+[materials.lamp]
+type = "diffuse_light"
+emit = [4.0, 4.0, 4.0]
+
+[[objects]]
+type = "rotate"
+plane = "xy"
+angle = 30.0
+object = { type = "rect", plane = "zx", a0 = -1.0, a1 = 1.0, b0 = -1.0, b1 = 1.0, k = 2.0, material = "lamp" }
+"#,
+    )
+    .unwrap();
+    assert_eq!(scene.lights.len(), 1);
+}
+
+#[test]
+fn scenes_list_the_files_they_load() {
+    let directory = env::temp_dir().join(format!("raytracer_files_{}", process::id()));
+    fs::create_dir_all(directory.join("meshes")).unwrap();
+    fs::write(
+        directory.join("meshes/cube.obj"),
+        "mtllib cube.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("meshes/cube.mtl"),
+        "newmtl paint\nmap_Kd -s 2 2 2 textures/paint.png\n",
+    )
+    .unwrap();
+
+    let source = r#"
+[textures.earth]
+type = "image"
+filename = "earthmap.png"
+
+[[objects]]
+type = "translate"
+offset = [1.0, 0.0, 0.0]
+object = { type = "obj", filename = "meshes/cube.obj" }
+"#;
+    let scene = SceneFile::parse(
+        &format!("{}{}", CAMERA, source),
+        directory.join("scene.toml"),
+    )
+    .unwrap();
+    let files = scene.files();
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(
+        files.unwrap(),
+        [
+            directory.join("earthmap.png"),
+            directory.join("meshes/cube.obj"),
+            directory.join("meshes/textures/paint.png"),
+            directory.join("meshes/cube.mtl"),
+        ]
+    );
+}
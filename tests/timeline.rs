@@ -0,0 +1,204 @@
+use std::{
+    env, fs,
+    process::{self, Command},
+};
+
+use raytracer::{hittable::*, ray::*, sampler::*, scene_file::*, timeline::*, vec3::*};
+
+fn assert_close(a: F, b: F) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+#[test]
+fn tracks_interpolate_and_hold_their_ends() {
+    let track = Track::new(vec![(5.0, 10.0), (1.0, 2.0), (3.0, 6.0)]);
+    assert_eq!(track.at(0.0), 2.0);
+    assert_eq!(track.at(2.0), 4.0);
+    assert_eq!(track.at(4.5), 9.0);
+    assert_eq!(track.at(9.0), 10.0);
+
+    let track = Track::new(vec![(1.0, Vec3::zero()), (3.0, Vec3::new(2.0, 4.0, 6.0))]);
+    assert_eq!(track.at(1.5), Vec3::new(0.5, 1.0, 1.5));
+    assert_eq!(Track::constant(7.0).at(100.0), 7.0);
+}
+
+#[test]
+fn frames_open_their_shutter_at_their_number() {
+    let timeline = Timeline::new(3, 6, 0.25);
+    assert_eq!(timeline.frames().collect::<Vec<_>>(), [3, 4, 5, 6]);
+    assert_eq!(timeline.shutter_interval(3), (3.0, 3.25));
+    assert_eq!(timeline.shutter_interval(6), (6.0, 6.25));
+
+    assert_eq!(frame_file_name(7, "png"), "frame_0007.png");
+    assert_eq!(frame_file_name(12345, "hdr"), "frame_12345.hdr");
+}
+
+const ANIMATION: &str = r#"
+[animation]
+first_frame = 1
+last_frame = 3
+shutter = 0.25
+
+[camera]
+look_from = [{ frame = 1, value = [0.0, 0.0, 10.0] }, { frame = 3, value = [0.0, 0.0, 20.0] }]
+look_at = [0.0, 0.0, 0.0]
+vfov = 40.0
+
+[materials.grey]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+"#;
+
+fn parse(objects: &str) -> SceneFile {
+    SceneFile::parse(&format!("{}{}", ANIMATION, objects), "scenes/test.toml").unwrap()
+}
+
+// Straight down the z axis from `x` at `time`
+fn ray_at(x: F, time: F) -> Ray {
+    Ray::new(Point3::new(x, 0.0, 50.0), Vec3::new(0.0, 0.0, -1.0), time)
+}
+
+#[test]
+fn frames_build_with_their_own_keyframed_values() {
+    let scene = parse(
+        r#"
+[[objects]]
+type = "animated"
+
+[[objects.keyframes]]
+frame = 1
+translate = [0.0, 0.0, 0.0]
+
+[[objects.keyframes]]
+frame = 3
+translate = [4.0, 0.0, 0.0]
+
+[objects.object]
+type = "sphere"
+centre = [0.0, 0.0, 0.0]
+radius = 0.5
+material = "grey"
+"#,
+    );
+
+    for (frame, look_from, x) in [(1, 10.0, 0.0), (2, 15.0, 2.0), (3, 20.0, 4.0)] {
+        let built = scene.build_frame(1.0, frame).unwrap();
+
+        // Every camera ray starts at the frame's position within its shutter
+        for _ in 0..10 {
+            let ray = built.camera.get_ray(0.5, 0.5, &mut Independent);
+            assert_eq!(ray.origin(), Point3::new(0.0, 0.0, look_from));
+            let (open, close) = (frame as F, frame as F + 0.25);
+            assert!(ray.time() >= open && ray.time() <= close, "{}", ray.time());
+        }
+
+        // The sphere is where its keyframes put it when the shutter opens
+        let time = frame as F;
+        let hit_record = built.world.hit(&ray_at(x, time), 0.001, F::INFINITY);
+        assert_close(hit_record.unwrap().p().z(), 0.5);
+        assert!(built
+            .world
+            .hit(&ray_at(x + 1.0, time), 0.001, F::INFINITY)
+            .is_none());
+    }
+
+    let still = ANIMATION.replace(
+        "[animation]\nfirst_frame = 1\nlast_frame = 3\nshutter = 0.25\n",
+        "",
+    );
+    let still = SceneFile::parse(&still, "scenes/test.toml").unwrap();
+    assert!(still.build_frame(1.0, 1).is_err());
+}
+
+#[test]
+fn rotated_moving_objects_are_hit_after_the_first_frame() {
+    // Rotating about x keeps the sphere on its path along the x axis
+    let scene = parse(
+        r#"
+[[objects]]
+type = "bvh"
+
+[[objects.objects]]
+type = "sphere"
+centre = [0.0, -100.0, 0.0]
+radius = 1.0
+material = "grey"
+
+[[objects.objects]]
+type = "rotate"
+plane = "yz"
+angle = 30.0
+
+[objects.objects.object]
+type = "moving_sphere"
+centre0 = [0.0, 0.0, 0.0]
+centre1 = [10.0, 0.0, 0.0]
+time0 = 0.0
+time1 = 10.0
+radius = 0.5
+material = "grey"
+"#,
+    );
+
+    for frame in 1..=3 {
+        let built = scene.build_frame(1.0, frame).unwrap();
+        let time = frame as F + 0.1;
+        let hit_record = built.world.hit(&ray_at(time, time), 0.001, F::INFINITY);
+        assert!(hit_record.is_some(), "missed in frame {}", frame);
+        assert_close(hit_record.unwrap().p().z(), 0.5);
+    }
+}
+
+#[test]
+fn existing_frames_are_skipped_unless_overwritten() {
+    let directory = env::temp_dir().join(format!("raytracer_frames_{}", process::id()));
+    let scene = directory.with_extension("toml");
+    fs::write(&scene, ANIMATION).unwrap();
+
+    let animate = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_main"))
+            .arg("--scene")
+            .arg(&scene)
+            .arg("--output")
+            .arg(&directory)
+            .args([
+                "--animate",
+                "--width",
+                "4",
+                "--height",
+                "4",
+                "--samples",
+                "1",
+            ])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    animate(&[]);
+    let names = |frames: &[u32]| -> Vec<_> {
+        frames
+            .iter()
+            .map(|frame| frame_file_name(*frame, "png"))
+            .collect()
+    };
+    let mut written: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    written.sort();
+    assert_eq!(written, names(&[1, 2, 3]));
+
+    fs::remove_file(directory.join(frame_file_name(2, "png"))).unwrap();
+    let log = animate(&[]);
+    assert!(log.contains("Skipping frame 1"), "{}", log);
+    assert!(!log.contains("Skipping frame 2"), "{}", log);
+    assert!(directory.join(frame_file_name(2, "png")).exists());
+
+    let log = animate(&["--overwrite"]);
+    fs::remove_dir_all(&directory).unwrap();
+    fs::remove_file(&scene).unwrap();
+    assert!(!log.contains("Skipping"), "{}", log);
+}
@@ -0,0 +1,78 @@
+use raytracer::{tonemap::*, vec3::*};
+
+fn grey(x: F) -> Colour {
+    Colour::one() * x
+}
+
+#[test]
+fn srgb_decode_inverts_encode() {
+    for i in 0..=1000 {
+        let x = i as F / 1000.0;
+        assert!((srgb_decode(srgb_encode(x)) - x).abs() < 1e-12, "{}", x);
+    }
+
+    assert_eq!(srgb_encode(0.0), 0.0);
+    assert!((srgb_encode(1.0) - 1.0).abs() < 1e-12);
+    // The linear and power segments meet at the knee
+    let knee: F = 0.003_130_8;
+    assert!((12.92 * knee - (1.055 * knee.powf(1.0 / 2.4) - 0.055)).abs() < 1e-6);
+}
+
+#[test]
+fn operators_are_monotonic_within_zero_and_one() {
+    for operator in &ToneMapOperator::ALL {
+        let tone_map = ToneMap {
+            operator: *operator,
+            ..ToneMap::default()
+        };
+        assert_eq!(tone_map.tone_map(Colour::zero()), Colour::zero());
+
+        let mut last = 0.0;
+        for i in 1..=400 {
+            // From 2^-10 to 2^10
+            let x = (2.0 as F).powf(i as F / 20.0 - 10.0);
+            let mapped = tone_map.tone_map(grey(x)).x();
+            assert!(mapped >= last, "{} at {}", operator, x);
+            assert!((0.0..=1.0).contains(&mapped), "{} at {}", operator, x);
+            last = mapped;
+        }
+    }
+}
+
+#[test]
+fn operators_reach_white_where_they_should() {
+    let tone_map = |operator| ToneMap {
+        operator,
+        ..ToneMap::default()
+    };
+    let white = |operator, x| tone_map(operator).tone_map(grey(x)).x();
+
+    assert_eq!(white(ToneMapOperator::Clamp, 1.0), 1.0);
+    assert_eq!(white(ToneMapOperator::Clamp, 0.5), 0.5);
+    assert_eq!(white(ToneMapOperator::Reinhard, 1.0), 0.5);
+    assert!(white(ToneMapOperator::Reinhard, 1e6) < 1.0);
+
+    // The white point, 4 by default, and nothing below it
+    let extended = ToneMapOperator::ExtendedReinhard;
+    assert!((white(extended, 4.0) - 1.0).abs() < 1e-12);
+    assert!(white(extended, 3.9) < 1.0);
+    let brighter = ToneMap {
+        white_point: 8.0,
+        ..tone_map(extended)
+    };
+    assert!((brighter.tone_map(grey(8.0)).x() - 1.0).abs() < 1e-12);
+    assert!(brighter.tone_map(grey(4.0)).x() < 1.0);
+
+    // Hable's linear white of 11.2 is reached at half that with its exposure bias
+    assert!((white(ToneMapOperator::Hable, 5.6) - 1.0).abs() < 1e-12);
+    assert!(white(ToneMapOperator::Hable, 5.0) < 1.0);
+    assert_eq!(white(ToneMapOperator::Aces, 100.0), 1.0);
+    assert!(white(ToneMapOperator::Aces, 0.5) < 1.0);
+
+    // Each stop of exposure doubles the radiance
+    let exposed = ToneMap {
+        exposure: 1.0,
+        ..tone_map(ToneMapOperator::Clamp)
+    };
+    assert_eq!(exposed.tone_map(grey(0.25)).x(), 0.5);
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use raytracer::{
+    aarect::*, hittable::*, material::*, ray::*, sampler::*, sphere::*, transform::*, vec3::*,
+};
+
+const EPSILON: F = 1e-9;
+
+fn material() -> Arc<M> {
+    Arc::new(Lambertian::rgb(0.5, 0.5, 0.5))
+}
+
+fn planes() -> [Plane; 3] {
+    [Plane::XY, Plane::YZ, Plane::ZX]
+}
+
+fn assert_vec_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < EPSILON, "{} != {}", a, b);
+}
+
+// From the first axis of the plane towards minus the second, as Rotate does
+fn rotate(p: Point3, plane: &Plane, theta: F) -> Point3 {
+    let (a, b, _k) = plane.axes();
+    let (sin, cos) = deg_to_rad(theta).sin_cos();
+    let mut rotated = p;
+    rotated.set(a, cos * p.ix(a) + sin * p.ix(b));
+    rotated.set(b, -sin * p.ix(a) + cos * p.ix(b));
+    rotated
+}
+
+#[test]
+fn rotated_boxes_are_bounded_by_their_rotated_corners() {
+    let (min, max) = (Point3::new(0.5, 1.0, 1.5), Point3::new(1.0, 2.0, 3.0));
+
+    for (plane, rotated_plane) in planes().iter().zip(planes()) {
+        let rotated = Rotate::new(
+            Arc::new(AABox::new(min, max, material())),
+            rotated_plane,
+            45.0,
+            0.0,
+            1.0,
+        );
+
+        let mut expected_min = Point3::new(F::INFINITY, F::INFINITY, F::INFINITY);
+        let mut expected_max = -expected_min;
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { min.x() } else { max.x() },
+                if i & 2 == 0 { min.y() } else { max.y() },
+                if i & 4 == 0 { min.z() } else { max.z() },
+            );
+            let p = rotate(corner, plane, 45.0);
+            for a in 0..3 {
+                expected_min.set(a, expected_min.ix(a).min(p.ix(a)));
+                expected_max.set(a, expected_max.ix(a).max(p.ix(a)));
+            }
+        }
+
+        let bbox = rotated.bounding_box(0.0, 1.0).unwrap();
+        assert_vec_close(bbox.min(), expected_min);
+        assert_vec_close(bbox.max(), expected_max);
+
+        // Rays at the rotated centre hit the box where the bounds say it is
+        let centre = rotate((min + max) * 0.5, plane, 45.0);
+        with_seed(3, || {
+            for _ in 0..100 {
+                let direction = Vec3::random_on_unit_sphere();
+                let ray = Ray::new(centre - direction * 10.0, direction, 0.0);
+                let p = rotated.hit(&ray, 0.001, F::INFINITY).unwrap().p();
+                for a in 0..3 {
+                    assert!(p.ix(a) >= bbox.min().ix(a) - EPSILON, "{} {}", p, a);
+                    assert!(p.ix(a) <= bbox.max().ix(a) + EPSILON, "{} {}", p, a);
+                }
+            }
+        });
+    }
+}
+
+fn unit_sphere() -> Arc<H> {
+    Arc::new(Sphere::new(Point3::zero(), 1.0, material()))
+}
+
+// `object` is the unit sphere moved by `matrix` at `time`
+fn assert_normals_perpendicular(object: &dyn Hittable, matrix: Mat4, time: F) {
+    let inverse = matrix.inverse().unwrap();
+
+    for _ in 0..200 {
+        let target = matrix.transform_point(Vec3::random_on_unit_sphere() * 0.5);
+        let origin = target + Vec3::random_on_unit_sphere() * 20.0;
+        let ray = Ray::new(origin, target - origin, time);
+        let hit_record = object.hit(&ray, 0.001, F::INFINITY).unwrap();
+        let n = hit_record.n();
+        assert!((n.length() - 1.0).abs() < EPSILON);
+
+        // Tangents of the unit sphere, carried to the ellipsoid by the matrix
+        let local = inverse.transform_point(hit_record.p());
+        assert!((local.length() - 1.0).abs() < 1e-6);
+        for _ in 0..4 {
+            let tangent = cross(&local, &Vec3::random_on_unit_sphere());
+            let tangent = matrix.transform_vector(tangent).unit();
+            assert!(dot(&n, &tangent).abs() < 1e-6, "{} {}", n, tangent);
+        }
+    }
+}
+
+#[test]
+fn normals_stay_perpendicular_under_non_uniform_scale() {
+    let matrix = Mat4::translation(Vec3::new(1.0, -2.0, 0.5))
+        * Mat4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+        * Mat4::scaling(Vec3::new(4.0, 0.5, 1.5));
+    let ellipsoid = Transform::new(unit_sphere(), matrix);
+
+    with_seed(4, || assert_normals_perpendicular(&ellipsoid, matrix, 0.0));
+}
+
+fn z_axis() -> Vec3 {
+    Vec3::new(0.0, 0.0, 1.0)
+}
+
+// Moves, turns a quarter about z and stretches along x between times 0 and 1
+fn keyframes() -> Vec<Keyframe> {
+    vec![
+        Keyframe::new(
+            1.0,
+            Vec3::new(2.0, 4.0, 0.0),
+            Quaternion::from_axis_angle(z_axis(), 90.0),
+            Vec3::new(3.0, 1.0, 1.0),
+        ),
+        Keyframe::new(0.0, Vec3::zero(), Quaternion::identity(), Vec3::one()),
+    ]
+}
+
+#[test]
+fn keyframes_are_interpolated_and_held_at_the_ends() {
+    let animated = AnimatedTransform::new(unit_sphere(), keyframes());
+
+    let middle = animated.keyframe_at(0.5);
+    assert_eq!(middle.time, 0.5);
+    assert_vec_close(middle.translation, Vec3::new(1.0, 2.0, 0.0));
+    assert_vec_close(middle.scale, Vec3::new(2.0, 1.0, 1.0));
+    // Slerped half way round, not the normalised lerp of the two
+    let x = middle.rotation.rotate(Vec3::new(1.0, 0.0, 0.0));
+    let half = deg_to_rad(45.0);
+    assert_vec_close(x, Vec3::new(half.cos(), half.sin(), 0.0));
+    let quarter = animated.keyframe_at(0.25).rotation;
+    let eighth = deg_to_rad(22.5);
+    assert_vec_close(
+        quarter.rotate(Vec3::new(1.0, 0.0, 0.0)),
+        Vec3::new(eighth.cos(), eighth.sin(), 0.0),
+    );
+
+    let before = animated.keyframe_at(-1.0);
+    assert_eq!(before.time, -1.0);
+    assert_eq!(before.translation, Vec3::zero());
+    assert_eq!(before.rotation, Quaternion::identity());
+    assert_eq!(before.scale, Vec3::one());
+
+    let after = animated.keyframe_at(3.0);
+    assert_eq!(after.translation, Vec3::new(2.0, 4.0, 0.0));
+    assert_eq!(after.rotation, Quaternion::from_axis_angle(z_axis(), 90.0));
+    assert_eq!(after.scale, Vec3::new(3.0, 1.0, 1.0));
+}
+
+#[test]
+fn animated_boxes_hold_the_object_across_the_shutter() {
+    // Off the origin, so the turn sweeps it round in an arc
+    let sphere: Arc<H> = Arc::new(Sphere::new(Point3::new(1.5, 0.0, 0.5), 1.0, material()));
+    let animated = AnimatedTransform::new(sphere, keyframes());
+    let (time0, time1) = (0.2, 0.9);
+    let bbox = animated.bounding_box(time0, time1).unwrap();
+
+    with_seed(6, || {
+        for step in 0..=50 {
+            let time = time0 + (time1 - time0) * step as F / 50.0;
+            let matrix = animated.keyframe_at(time).matrix();
+            for _ in 0..50 {
+                let p = Point3::new(1.5, 0.0, 0.5) + Vec3::random_on_unit_sphere();
+                let p = matrix.transform_point(p);
+                for a in 0..3 {
+                    assert!(p.ix(a) >= bbox.min().ix(a) - EPSILON, "{} at {}", p, time);
+                    assert!(p.ix(a) <= bbox.max().ix(a) + EPSILON, "{} at {}", p, time);
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn animated_normals_stay_perpendicular_under_non_uniform_scale() {
+    let keyframes = vec![
+        Keyframe::new(
+            0.0,
+            Vec3::new(1.0, 0.0, 0.0),
+            Quaternion::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 30.0),
+            Vec3::new(4.0, 0.5, 1.5),
+        ),
+        Keyframe::new(
+            1.0,
+            Vec3::new(-1.0, 2.0, 0.0),
+            Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 1.0), 80.0),
+            Vec3::new(0.5, 3.0, 1.0),
+        ),
+    ];
+    let animated = AnimatedTransform::new(unit_sphere(), keyframes);
+
+    with_seed(7, || {
+        for time in [0.0, 0.3, 1.0] {
+            let matrix = animated.keyframe_at(time).matrix();
+            assert_normals_perpendicular(&animated, matrix, time);
+        }
+    });
+}
+
+#[test]
+fn rotated_lights_are_sampled_through_the_rotation() {
+    let light = || -> Arc<H> {
+        Arc::new(AARect::new(
+            Plane::ZX,
+            -1.0,
+            1.0,
+            -0.5,
+            0.5,
+            2.0,
+            material(),
+        ))
+    };
+    let rotated = Rotate::new(light(), Plane::XY, 30.0, 0.0, 1.0);
+    let unrotated = light();
+    let origin = Point3::zero();
+
+    with_seed(5, || {
+        for _ in 0..100 {
+            let direction = rotated.random(origin, &mut Independent);
+            let ray = Ray::new(origin, direction, 0.0);
+            assert!(rotated.hit(&ray, 0.001, F::INFINITY).is_some());
+
+            // The same density as the light before it was turned
+            let pdf = rotated.pdf_value(origin, direction);
+            let expected = unrotated.pdf_value(origin, rotate(direction, &Plane::XY, -30.0));
+            assert!(pdf > 0.0);
+            assert!(
+                (pdf - expected).abs() < 1e-9 * expected,
+                "{} {}",
+                pdf,
+                expected
+            );
+        }
+    });
+}
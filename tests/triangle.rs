@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use raytracer::{hittable::*, material::*, ray::*, texture::*, triangle::*, vec3::*};
+
+fn material() -> Arc<M> {
+    Arc::new(Lambertian::rgb(0.5, 0.5, 0.5))
+}
+
+// The unit right triangle in the z = 0 plane, facing +z
+fn triangle() -> Triangle {
+    Triangle::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+        material(),
+    )
+}
+
+fn towards_plane(x: F, y: F, from: F) -> Ray {
+    Ray::new(
+        Point3::new(x, y, from),
+        Vec3::new(0.0, 0.0, -from.signum()),
+        0.0,
+    )
+}
+
+fn assert_close(a: F, b: F) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+#[test]
+fn rays_hit_inside_the_triangle_only() {
+    let triangle = triangle();
+
+    let hit_record = triangle
+        .hit(&towards_plane(0.25, 0.25, 2.0), 0.001, F::INFINITY)
+        .unwrap();
+    assert_close(hit_record.t(), 2.0);
+    assert_close(hit_record.p().x(), 0.25);
+    assert_close(hit_record.p().y(), 0.25);
+
+    // Beyond the hypotenuse, and outside each leg
+    for (x, y) in [(0.6, 0.6), (-0.1, 0.5), (0.5, -0.1)] {
+        assert!(triangle
+            .hit(&towards_plane(x, y, 2.0), 0.001, F::INFINITY)
+            .is_none());
+    }
+    // Parallel to the plane, and outside the t range
+    let parallel = Ray::new(Point3::new(-1.0, 0.2, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    assert!(triangle.hit(&parallel, 0.001, F::INFINITY).is_none());
+    assert!(triangle
+        .hit(&towards_plane(0.25, 0.25, 2.0), 0.001, 1.5)
+        .is_none());
+}
+
+#[test]
+fn uvs_are_barycentric_or_interpolated() {
+    let hit_record = triangle()
+        .hit(&towards_plane(0.2, 0.3, 1.0), 0.001, F::INFINITY)
+        .unwrap();
+    // The weights of the second and third vertices
+    assert_close(hit_record.tp().u(), 0.2);
+    assert_close(hit_record.tp().v(), 0.3);
+
+    let mesh = Mesh::new(
+        vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ],
+        vec![[0, 1, 2]],
+        material(),
+    )
+    .with_uvs(vec![
+        TexturePoint::new(0.5, 0.5),
+        TexturePoint::new(1.0, 0.5),
+        TexturePoint::new(0.5, 0.0),
+    ])
+    .unwrap();
+    let triangle = Triangle::from_mesh(Arc::new(mesh), 0);
+    let hit_record = triangle
+        .hit(&towards_plane(0.2, 0.3, 1.0), 0.001, F::INFINITY)
+        .unwrap();
+    assert_close(hit_record.tp().u(), 0.5 + 0.5 * 0.2);
+    assert_close(hit_record.tp().v(), 0.5 - 0.5 * 0.3);
+}
+
+#[test]
+fn back_faces_are_hit_with_the_normal_against_the_ray() {
+    let triangle = triangle();
+
+    let front = triangle
+        .hit(&towards_plane(0.25, 0.25, 1.0), 0.001, F::INFINITY)
+        .unwrap();
+    assert!(front.front_face());
+    assert_close(front.n().z(), 1.0);
+
+    let back = triangle
+        .hit(&towards_plane(0.25, 0.25, -1.0), 0.001, F::INFINITY)
+        .unwrap();
+    assert!(!back.front_face());
+    assert_close(back.n().z(), -1.0);
+    assert_close(back.outward_n().z(), 1.0);
+
+    // Shading normals follow the side the ray came from
+    let mesh = Mesh::new(
+        vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ],
+        vec![[0, 1, 2]],
+        material(),
+    )
+    .with_normals(vec![Vec3::new(0.0, 0.0, 1.0); 3])
+    .unwrap();
+    let smooth = Triangle::from_mesh(Arc::new(mesh), 0);
+    let back = smooth
+        .hit(&towards_plane(0.25, 0.25, -1.0), 0.001, F::INFINITY)
+        .unwrap();
+    assert!(!back.front_face());
+    assert_close(back.n().z(), -1.0);
+}
+
+#[test]
+fn meshes_hit_their_nearest_triangle() {
+    // Two parallel triangles, the nearer one at z = 1
+    let mesh = Mesh::new(
+        vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(1.0, 0.0, 1.0),
+            Point3::new(0.0, 1.0, 1.0),
+        ],
+        vec![[0, 1, 2], [3, 4, 5]],
+        material(),
+    );
+    let mesh = TriangleMesh::new(mesh, 0.0, 1.0).unwrap();
+
+    let hit_record = mesh
+        .hit(&towards_plane(0.25, 0.25, 3.0), 0.001, F::INFINITY)
+        .unwrap();
+    assert_close(hit_record.t(), 2.0);
+    assert!(mesh.bounding_box(0.0, 1.0).is_some());
+}
+
+#[test]
+fn meshes_check_their_attributes_and_faces() {
+    let positions = vec![
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+    ];
+    let mesh = || Mesh::new(positions.clone(), vec![[0, 1, 2]], material());
+
+    assert_eq!(
+        mesh().with_normals(vec![Vec3::new(0.0, 0.0, 1.0); 2]).err(),
+        Some(MeshError::AttributeCount {
+            attribute: "normals",
+            positions: 3,
+            found: 2,
+        })
+    );
+    assert!(mesh()
+        .with_uvs(vec![TexturePoint::new(0.0, 0.0); 4])
+        .is_err());
+
+    let empty = Mesh::new(positions.clone(), Vec::new(), material());
+    assert_eq!(
+        TriangleMesh::new(empty, 0.0, 1.0).err(),
+        Some(MeshError::NoFaces)
+    );
+    let outside = Mesh::new(positions.clone(), vec![[0, 1, 3]], material());
+    assert_eq!(
+        TriangleMesh::new(outside, 0.0, 1.0).err(),
+        Some(MeshError::IndexOutOfRange {
+            index: 3,
+            positions: 3,
+        })
+    );
+}
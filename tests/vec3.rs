@@ -0,0 +1,193 @@
+use raytracer::vec3::*;
+
+const EPSILON: F = 1e-9;
+
+fn assert_close(a: F, b: F) {
+    assert!((a - b).abs() < EPSILON, "{} != {}", a, b);
+}
+
+fn assert_vec_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < EPSILON, "{} != {}", a, b);
+}
+
+fn assert_mat3_close(a: Mat3, b: Mat3) {
+    for i in 0..3 {
+        for j in 0..3 {
+            assert_close(a.ix(i, j), b.ix(i, j));
+        }
+    }
+}
+
+fn assert_mat4_close(a: Mat4, b: Mat4) {
+    for i in 0..4 {
+        for j in 0..4 {
+            assert_close(a.ix(i, j), b.ix(i, j));
+        }
+    }
+}
+
+fn x_axis() -> Vec3 {
+    Vec3::new(1.0, 0.0, 0.0)
+}
+
+fn y_axis() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+fn z_axis() -> Vec3 {
+    Vec3::new(0.0, 0.0, 1.0)
+}
+
+#[test]
+fn mat3_rotation_is_counter_clockwise() {
+    let m = Mat3::rotation(z_axis(), 90.0);
+    assert_vec_close(m * x_axis(), y_axis());
+    assert_vec_close(m * y_axis(), -x_axis());
+    assert_close(m.determinant(), 1.0);
+}
+
+#[test]
+fn mat3_inverse() {
+    let m = Mat3::new([[2.0, 1.0, 0.0], [0.0, 3.0, 1.0], [1.0, 0.0, 4.0]]);
+    let inverse = m.inverse().unwrap();
+
+    assert_mat3_close(m * inverse, Mat3::identity());
+    assert_mat3_close(inverse * m, Mat3::identity());
+    assert!(Mat3::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+}
+
+#[test]
+fn mat3_product_composes() {
+    let a = Mat3::rotation(Vec3::new(1.0, 2.0, 3.0), 40.0);
+    let b = Mat3::scaling(Vec3::new(2.0, 3.0, 4.0));
+    let v = Vec3::new(0.3, -1.2, 2.5);
+
+    assert_vec_close((a * b) * v, a * (b * v));
+    assert_mat3_close((a * b).transpose(), b.transpose() * a.transpose());
+    assert_close((a * b).determinant(), 24.0);
+}
+
+#[test]
+fn mat4_inverse_and_composition() {
+    let m = Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+        * Mat4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+        * Mat4::scaling(Vec3::new(2.0, 0.5, 1.5));
+    let inverse = m.inverse().unwrap();
+
+    assert_mat4_close(m * inverse, Mat4::identity());
+
+    let p = Point3::new(0.5, 4.0, -1.0);
+    assert_vec_close(inverse.transform_point(m.transform_point(p)), p);
+    assert!(Mat4::scaling(Vec3::zero()).inverse().is_none());
+}
+
+#[test]
+fn mat4_points_translate_vectors_do_not() {
+    let m = Mat4::translation(Vec3::new(1.0, 2.0, 3.0));
+    let v = Vec3::new(1.0, 1.0, 1.0);
+
+    assert_vec_close(m.transform_point(v), Vec3::new(2.0, 3.0, 4.0));
+    assert_vec_close(m.transform_vector(v), v);
+}
+
+#[test]
+fn quaternion_matches_matrix_rotation() {
+    let axis = Vec3::new(-1.0, 2.0, 0.5);
+    let q = Quaternion::from_axis_angle(axis, 75.0);
+    let m = Mat3::rotation(axis, 75.0);
+    let v = Vec3::new(0.2, 1.0, -3.0);
+
+    assert_vec_close(q.rotate(v), m * v);
+    assert_mat3_close(q.to_mat3(), m);
+    assert_close(q.length(), 1.0);
+}
+
+#[test]
+fn quaternion_from_mat3_round_trips() {
+    for &(axis, angle) in &[
+        (x_axis(), 10.0),
+        (y_axis(), 179.0),
+        (z_axis(), -120.0),
+        (Vec3::new(1.0, 1.0, 1.0), 200.0),
+    ] {
+        let m = Mat3::rotation(axis, angle);
+        assert_mat3_close(Quaternion::from_mat3(&m).to_mat3(), m);
+    }
+}
+
+#[test]
+fn quaternion_product_composes_rotations() {
+    let a = Quaternion::from_axis_angle(x_axis(), 90.0);
+    let b = Quaternion::from_axis_angle(z_axis(), 90.0);
+    let v = Vec3::new(1.0, 2.0, 3.0);
+
+    assert_vec_close((a * b).rotate(v), a.rotate(b.rotate(v)));
+    assert_vec_close((a * a.conjugate()).v(), Vec3::zero());
+}
+
+#[test]
+fn slerp_interpolates_angle_linearly() {
+    let a = Quaternion::identity();
+    let b = Quaternion::from_axis_angle(y_axis(), 90.0);
+
+    assert_vec_close(Quaternion::slerp(a, b, 0.0).rotate(x_axis()), x_axis());
+    assert_vec_close(
+        Quaternion::slerp(a, b, 1.0).rotate(x_axis()),
+        b.rotate(x_axis()),
+    );
+
+    let quarter = Quaternion::slerp(a, b, 0.25);
+    let expected = Quaternion::from_axis_angle(y_axis(), 22.5);
+    assert_vec_close(quarter.rotate(z_axis()), expected.rotate(z_axis()));
+    assert_close(quarter.length(), 1.0);
+}
+
+#[test]
+fn slerp_takes_the_shorter_arc() {
+    let a = Quaternion::from_axis_angle(z_axis(), 170.0);
+    let b = Quaternion::from_axis_angle(z_axis(), -170.0);
+
+    // 20 degrees apart through 180, not 340 through 0
+    let middle = Quaternion::slerp(a, b, 0.5);
+    assert_vec_close(middle.rotate(x_axis()), -x_axis());
+}
+
+#[test]
+fn onb_is_orthonormal() {
+    for &n in &[
+        x_axis(),
+        -y_axis(),
+        Vec3::new(0.3, -0.4, 2.0),
+        Vec3::new(0.95, 0.1, 0.0),
+    ] {
+        let onb = Onb::from_w(n);
+
+        assert_vec_close(onb.w(), n.unit());
+        assert_close(onb.u().length(), 1.0);
+        assert_close(onb.v().length(), 1.0);
+        assert_close(dot(&onb.u(), &onb.v()), 0.0);
+        assert_close(dot(&onb.u(), &onb.w()), 0.0);
+        assert_vec_close(cross(&onb.u(), &onb.v()), onb.w());
+        assert_close(onb.to_mat3().determinant(), 1.0);
+    }
+}
+
+#[test]
+fn onb_local_world_round_trip() {
+    let onb = Onb::from_w(Vec3::new(1.0, 2.0, -2.0));
+    let a = Vec3::new(0.5, -1.5, 3.0);
+
+    assert_vec_close(onb.to_local(onb.to_world(a)), a);
+    assert_vec_close(onb.to_world(z_axis()), onb.w());
+    assert_vec_close(onb.to_mat3() * a, onb.to_world(a));
+}
+
+#[test]
+fn onb_from_w_up_keeps_v_towards_up() {
+    let onb = Onb::from_w_up(Vec3::new(13.0, 2.0, 3.0), y_axis());
+
+    assert_close(dot(&onb.u(), &y_axis()), 0.0);
+    assert!(dot(&onb.v(), &y_axis()) > 0.0);
+    assert_close(onb.u().length(), 1.0);
+    assert_vec_close(cross(&onb.u(), &onb.v()), onb.w());
+}